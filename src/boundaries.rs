@@ -1,5 +1,8 @@
 use std::collections::HashMap;
 
+use crate::output_grid::OutputGrid;
+use crate::terminal_pane::{AnsiCode, CharacterStyles, NamedColor, TerminalCharacter, EMPTY_TERMINAL_CHARACTER};
+
 fn _debug_log_to_file (message: String) {
     use std::fs::OpenOptions;
     use std::io::prelude::*;
@@ -148,6 +151,7 @@ pub struct Boundaries {
     columns: usize,
     rows: usize,
     boundary_characters: HashMap<Coordinates, BoundaryType>,
+    boundary_colors: HashMap<Coordinates, NamedColor>, // foreground colors
 }
 
 impl Boundaries {
@@ -158,6 +162,7 @@ impl Boundaries {
             columns,
             rows,
             boundary_characters: HashMap::new(),
+            boundary_colors: HashMap::new(),
         }
     }
     pub fn add_rect<R: Rect>(&mut self, rect: &R) {
@@ -201,12 +206,31 @@ impl Boundaries {
             }
         }
     }
-    pub fn vte_output (&self) -> String {
-        let mut vte_output = String::new();
+    // colors the boundaries that were added around this rect, so this should come after add_rect
+    // was called for all of them
+    pub fn color_frame<R: Rect>(&mut self, rect: &R, color: NamedColor) {
+        let first_col = if rect.x() == 0 { 0 } else { rect.x() - 1 };
+        let first_row = if rect.y() == 0 { 0 } else { rect.y() - 1 };
+        let last_col = rect.right_boundary_x_coords();
+        let last_row = rect.bottom_boundary_y_coords();
+        let horizontal_edges = (first_col..=last_col).flat_map(|col| vec![(col, first_row), (col, last_row)]);
+        let vertical_edges = (first_row..=last_row).flat_map(|row| vec![(first_col, row), (last_col, row)]);
+        for (col, row) in horizontal_edges.chain(vertical_edges) {
+            let coordinates = Coordinates::new(col, row);
+            if self.boundary_characters.contains_key(&coordinates) {
+                self.boundary_colors.insert(coordinates, color);
+            }
+        }
+    }
+    pub fn draw_on (&self, output_grid: &mut OutputGrid) {
         for (coordinates, boundary_character) in &self.boundary_characters {
-            vte_output.push_str(&format!("\u{1b}[{};{}H\u{1b}[m{}", coordinates.y + 1, coordinates.x + 1, boundary_character)); // goto row/col + boundary character
+            let styles = match self.boundary_colors.get(coordinates) {
+                Some(color) => CharacterStyles::new().foreground(Some(AnsiCode::NamedColor(*color))),
+                None => EMPTY_TERMINAL_CHARACTER.styles,
+            };
+            let character = boundary_character.chars().next().unwrap();
+            output_grid.set_cell(coordinates.x, coordinates.y, TerminalCharacter { character, styles });
         }
-        vte_output
     }
     fn rect_right_boundary_is_before_screen_edge <R: Rect>(&self, rect: &R) -> bool {
         rect.x() + rect.columns() < self.columns
@@ -0,0 +1,104 @@
+use ::std::io::{Read, Write};
+use ::std::sync::{Arc, Mutex};
+use ::std::thread;
+
+use crate::exit_with_error;
+use crate::ipc::{ApiCommand, AttachOptions, ClientToServerMsg, ExitReason, ServerToClientMsg, TerminalSize};
+use crate::os_input_output::ClientOsApi;
+
+/*
+ * Client
+ *
+ * a client is what runs in the user's terminal. it doesn't know anything about panes: it sends
+ * everything the user types to the session's server, and draws whatever the server renders
+ * until the user detaches or the session ends
+ *
+ */
+
+pub fn start_client(mut os_input: Box<dyn ClientOsApi>, session_name: &str, attach_options: AttachOptions) {
+    let mut server = os_input.connect_to_server(session_name).unwrap_or_else(|e| exit_with_error(e));
+    let full_screen_ws = os_input.get_terminal_size_using_fd(0);
+    let terminal_size = TerminalSize {
+        rows: full_screen_ws.ws_row,
+        cols: full_screen_ws.ws_col,
+    };
+    bincode::serialize_into(&mut server, &ApiCommand::AttachClient(terminal_size, attach_options))
+        .unwrap_or_else(|e| exit_with_error(e));
+    os_input.into_raw_mode(0);
+
+    // both threads below send messages to the server, one at a time so that they don't interleave
+    let send_to_server = {
+        let server = Arc::new(Mutex::new(server.try_clone().expect("failed to clone server stream")));
+        move |message: &ClientToServerMsg| {
+            let mut server = server.lock().unwrap();
+            bincode::serialize_into(&mut *server, message).is_ok()
+        }
+    };
+
+    // these threads are not joined: they're blocked on reading stdin or waiting for a resize,
+    // and so can't notice the session ending or this client being detached
+    let _stdin_thread = thread::Builder::new()
+        .name("stdin_handler".to_string())
+        .spawn({
+            let os_input = os_input.clone();
+            let send_to_server = send_to_server.clone();
+            move || {
+                let mut stdin = os_input.get_stdin_reader();
+                loop {
+                    let mut buffer = [0; 256];
+                    let read_bytes = match stdin.read(&mut buffer) {
+                        Ok(0) | Err(_) => break,
+                        Ok(read_bytes) => read_bytes,
+                    };
+                    if !send_to_server(&ClientToServerMsg::Input(buffer[..read_bytes].to_vec())) {
+                        break;
+                    }
+                }
+            }
+        }).unwrap();
+    let _sigwinch_thread = thread::Builder::new()
+        .name("sigwinch_handler".to_string())
+        .spawn({
+            let os_input = os_input.clone();
+            move || {
+                let on_resize = {
+                    let os_input = os_input.clone();
+                    move || {
+                        let new_size = os_input.get_terminal_size_using_fd(0);
+                        let terminal_size = TerminalSize {
+                            rows: new_size.ws_row,
+                            cols: new_size.ws_col,
+                        };
+                        send_to_server(&ClientToServerMsg::Resize(terminal_size));
+                    }
+                };
+                os_input.receive_sigwinch(Box::new(on_resize));
+            }
+        }).unwrap();
+
+    let mut stdout = os_input.get_stdout_writer();
+    let exit_reason = loop {
+        match bincode::deserialize_from(&mut server) {
+            Ok(ServerToClientMsg::Render(output)) => {
+                stdout.write_all(output.as_bytes()).expect("cannot write to stdout");
+                stdout.flush().expect("could not flush");
+            }
+            Ok(ServerToClientMsg::Exit(exit_reason)) => break exit_reason,
+            Err(_) => break ExitReason::LostConnection,
+        }
+    };
+
+    let reset_style = "\u{1b}[m";
+    let full_screen_ws = os_input.get_terminal_size_using_fd(0); // it might have been resized since we started
+    let goto_start_of_last_line = format!("\u{1b}[{};{}H", full_screen_ws.ws_row, 1);
+    let goodbye_message = match exit_reason {
+        ExitReason::Quit => String::from("Bye from Mosaic!"),
+        ExitReason::Detached => format!("[detached from session \"{}\"]", session_name),
+        ExitReason::LostConnection => format!("[lost connection to session \"{}\"]", session_name),
+    };
+    let goodbye_message = format!("{}\n{}{}", goto_start_of_last_line, reset_style, goodbye_message);
+
+    // the terminal is taken out of raw mode even if it can't be written to anymore
+    stdout.write_all(goodbye_message.as_bytes()).and_then(|_| stdout.flush()).ok();
+    os_input.unset_raw_mode(0);
+}
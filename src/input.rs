@@ -0,0 +1,324 @@
+use ::std::fmt;
+use ::std::os::unix::net::UnixStream;
+use ::std::path::PathBuf;
+use ::std::str::FromStr;
+use ::std::sync::mpsc::{channel, Sender};
+use ::serde::{Serialize, Deserialize};
+
+use crate::ipc::{ClientToServerMsg, Event, InputMode};
+use crate::pty_bus::{PtyInstruction, RunCommand, TerminalAction};
+use crate::screen::ScreenInstruction;
+use crate::server::{ClientId, ServerInstruction};
+
+/*
+ * Input
+ *
+ * clients forward whatever their user types as is, and it is interpreted here, on the server
+ * most keys are sent straight to the focused pane. the ones mosaic uses are single control
+ * characters, with ctrl-g acting as a prefix for the less common actions (eg. ctrl-g d to
+ * detach), so that we don't take over more of the keys the applications in the panes need
+ *
+ * clients that attached as read-only can only detach, the rest of their input is ignored
+ *
+ */
+
+const COMMAND_PREFIX: u8 = 7; // ctrl-g
+const COMMAND_KEYS: &[u8] = b"drhjkl;HJKLszfcwnpm123456789"; // that follow it, see handle_command
+
+// a key of the user's own that follows ctrl-g to open a pane running a command (eg. a watcher, a
+// log or a REPL), given as KEY=COMMAND. the command is run by sh, so it can be a pipeline
+#[derive(Debug, Clone, PartialEq)]
+pub struct CommandBinding {
+    pub key: char,
+    pub command: String,
+}
+
+impl FromStr for CommandBinding {
+    type Err = String;
+    fn from_str(binding: &str) -> Result<Self, Self::Err> {
+        let mut chars = binding.chars();
+        let (key, command) = match (chars.next(), chars.next()) {
+            (Some(key), Some('=')) if !chars.as_str().is_empty() => (key, chars.as_str()),
+            _ => return Err(format!("expected KEY=COMMAND, got \"{}\"", binding)),
+        };
+        if !key.is_ascii_graphic() {
+            return Err(format!("{:?} can't follow ctrl-g, bind a letter, digit or symbol instead", key));
+        }
+        if COMMAND_KEYS.contains(&(key as u8)) {
+            return Err(format!("ctrl-g {} is already taken", key));
+        }
+        Ok(CommandBinding { key, command: command.to_string() })
+    }
+}
+
+impl fmt::Display for CommandBinding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}={}", self.key, self.command)
+    }
+}
+
+impl CommandBinding {
+    fn run_command(&self) -> RunCommand {
+        RunCommand {
+            command: PathBuf::from("sh"),
+            args: vec![String::from("-c"), self.command.clone()],
+            ..RunCommand::default()
+        }
+    }
+}
+
+// a key sent to a pane from the outside (eg. with mosaic action send-keys), by name (Enter, Up,
+// C-c, M-x...). anything that isn't the name of a key is sent as the text it is
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum Key {
+    Enter,
+    Tab,
+    BackTab,
+    Backspace,
+    Escape,
+    Space,
+    Up,
+    Down,
+    Left,
+    Right,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+    Insert,
+    Delete,
+    Ctrl(char),
+    Alt(char),
+    Text(String),
+}
+
+impl FromStr for Key {
+    type Err = String;
+    fn from_str(key_name: &str) -> Result<Self, Self::Err> {
+        let key = match key_name {
+            "Enter" => Key::Enter,
+            "Tab" => Key::Tab,
+            "BTab" => Key::BackTab,
+            "BSpace" => Key::Backspace,
+            "Escape" => Key::Escape,
+            "Space" => Key::Space,
+            "Up" => Key::Up,
+            "Down" => Key::Down,
+            "Left" => Key::Left,
+            "Right" => Key::Right,
+            "Home" => Key::Home,
+            "End" => Key::End,
+            "PPage" | "PageUp" => Key::PageUp,
+            "NPage" | "PageDown" => Key::PageDown,
+            "IC" | "Insert" => Key::Insert,
+            "DC" | "Delete" => Key::Delete,
+            _ => {
+                let mut chars = key_name.chars();
+                match (chars.next(), chars.next(), chars.next(), chars.next()) {
+                    (Some('C'), Some('-'), Some(c), None) if c.is_ascii_alphabetic() || "@[\\]^_".contains(c) => Key::Ctrl(c),
+                    (Some('M'), Some('-'), Some(c), None) => Key::Alt(c),
+                    _ => Key::Text(key_name.to_string()),
+                }
+            }
+        };
+        Ok(key)
+    }
+}
+
+impl Key {
+    pub fn as_bytes(&self) -> Vec<u8> {
+        match self {
+            Key::Enter => b"\r".to_vec(),
+            Key::Tab => b"\t".to_vec(),
+            Key::BackTab => b"\x1b[Z".to_vec(),
+            Key::Backspace => vec![127],
+            Key::Escape => vec![27],
+            Key::Space => b" ".to_vec(),
+            Key::Up => b"\x1b[A".to_vec(),
+            Key::Down => b"\x1b[B".to_vec(),
+            Key::Right => b"\x1b[C".to_vec(),
+            Key::Left => b"\x1b[D".to_vec(),
+            Key::Home => b"\x1b[H".to_vec(),
+            Key::End => b"\x1b[F".to_vec(),
+            Key::PageUp => b"\x1b[5~".to_vec(),
+            Key::PageDown => b"\x1b[6~".to_vec(),
+            Key::Insert => b"\x1b[2~".to_vec(),
+            Key::Delete => b"\x1b[3~".to_vec(),
+            Key::Ctrl(c) => vec![c.to_ascii_uppercase() as u8 & 0x1f],
+            Key::Alt(c) => format!("\x1b{}", c).into_bytes(),
+            Key::Text(text) => text.as_bytes().to_vec(),
+        }
+    }
+}
+
+struct InputHandler {
+    client_id: ClientId,
+    read_only: bool,
+    command_mode: bool, // the previous key was the command prefix
+    moving_pane: bool, // the previous keys were ctrl-g m, so this one is where to move the focused pane
+    command_bindings: Vec<CommandBinding>,
+    send_screen_instructions: Sender<ScreenInstruction>,
+    send_pty_instructions: Sender<PtyInstruction>,
+    send_server_instructions: Sender<ServerInstruction>,
+}
+
+impl InputHandler {
+    fn set_command_mode(&mut self, command_mode: bool) {
+        if command_mode != self.command_mode {
+            self.command_mode = command_mode;
+            let mode = if command_mode { InputMode::Command } else { InputMode::Normal };
+            self.send_server_instructions.send(ServerInstruction::Event(Event::ModeChanged { mode })).unwrap();
+        }
+    }
+    // returns false once this client should not send any more input (eg. it detached)
+    fn handle_byte(&mut self, byte: u8) -> bool {
+        if self.moving_pane {
+            self.moving_pane = false;
+            if (b'1'..=b'9').contains(&byte) {
+                let position = (byte - b'1') as usize;
+                self.send_screen_instructions.send(ScreenInstruction::MovePaneToPosition(position)).unwrap();
+            }
+            true
+        } else if self.command_mode {
+            self.set_command_mode(false);
+            self.handle_command(byte)
+        } else if self.read_only {
+            self.set_command_mode(byte == COMMAND_PREFIX);
+            true
+        } else {
+            self.handle_key(byte)
+        }
+    }
+    fn handle_command(&mut self, byte: u8) -> bool {
+        if byte == b'd' {
+            self.send_server_instructions.send(ServerInstruction::DetachClient(self.client_id)).unwrap();
+            return false;
+        } else if byte == b'r' && !self.read_only {
+            self.send_screen_instructions.send(ScreenInstruction::RerunFocusedPane).unwrap();
+        } else if byte == b'h' && !self.read_only {
+            self.send_screen_instructions.send(ScreenInstruction::MoveFocusLeft).unwrap();
+        } else if byte == b'j' && !self.read_only {
+            self.send_screen_instructions.send(ScreenInstruction::MoveFocusDown).unwrap();
+        } else if byte == b'k' && !self.read_only {
+            self.send_screen_instructions.send(ScreenInstruction::MoveFocusUp).unwrap();
+        } else if byte == b'l' && !self.read_only {
+            self.send_screen_instructions.send(ScreenInstruction::MoveFocusRight).unwrap();
+        } else if byte == b';' && !self.read_only {
+            self.send_screen_instructions.send(ScreenInstruction::FocusPreviousPane).unwrap();
+        } else if byte == b'H' && !self.read_only {
+            self.send_screen_instructions.send(ScreenInstruction::SwapPaneLeft).unwrap();
+        } else if byte == b'J' && !self.read_only {
+            self.send_screen_instructions.send(ScreenInstruction::SwapPaneDown).unwrap();
+        } else if byte == b'K' && !self.read_only {
+            self.send_screen_instructions.send(ScreenInstruction::SwapPaneUp).unwrap();
+        } else if byte == b'L' && !self.read_only {
+            self.send_screen_instructions.send(ScreenInstruction::SwapPaneRight).unwrap();
+        } else if byte == b's' && !self.read_only {
+            self.send_screen_instructions.send(ScreenInstruction::SwapWithNextPane).unwrap();
+        } else if byte == b'z' && !self.read_only {
+            self.send_screen_instructions.send(ScreenInstruction::ToggleZoom).unwrap();
+        } else if byte == b'f' && !self.read_only {
+            self.send_screen_instructions.send(ScreenInstruction::ToggleFloatingPanes).unwrap();
+        } else if byte == b'c' && !self.read_only {
+            self.send_pty_instructions.send(PtyInstruction::SpawnTerminalInNewTab(None)).unwrap();
+        } else if byte == b'w' && !self.read_only {
+            self.send_screen_instructions.send(ScreenInstruction::CloseTab).unwrap();
+        } else if byte == b'n' && !self.read_only {
+            self.send_screen_instructions.send(ScreenInstruction::NextTab).unwrap();
+        } else if byte == b'p' && !self.read_only {
+            self.send_screen_instructions.send(ScreenInstruction::PreviousTab).unwrap();
+        } else if (b'1'..=b'9').contains(&byte) && !self.read_only {
+            // nobody waits for the answer, there is nothing to go to if there is no such tab
+            let (send_result, _receive_result) = channel();
+            let index = (byte - b'1') as usize;
+            self.send_screen_instructions.send(ScreenInstruction::GoToTab(index, send_result)).unwrap();
+        } else if byte == b'm' && !self.read_only {
+            self.moving_pane = true;
+        } else if let Some(binding) = self.command_bindings.iter().find(|binding| binding.key == byte as char) {
+            if !self.read_only {
+                let terminal_action = Some(TerminalAction::RunCommand(binding.run_command()));
+                self.send_pty_instructions.send(PtyInstruction::SpawnTerminal(terminal_action)).unwrap();
+            }
+        } else if byte == COMMAND_PREFIX && !self.read_only {
+            // pressing the prefix twice sends it to the focused pane
+            self.send_screen_instructions.send(ScreenInstruction::WriteCharacter(COMMAND_PREFIX)).unwrap();
+        }
+        // any other key cancels the command
+        true
+    }
+    fn handle_key(&mut self, byte: u8) -> bool {
+        if byte == COMMAND_PREFIX {
+            self.set_command_mode(true);
+        } else if byte == 10 { // ctrl-j
+            self.send_screen_instructions.send(ScreenInstruction::ResizeDown).unwrap();
+        } else if byte == 11 { // ctrl-k
+            self.send_screen_instructions.send(ScreenInstruction::ResizeUp).unwrap();
+        } else if byte == 16 { // ctrl-p
+            self.send_screen_instructions.send(ScreenInstruction::MoveFocus).unwrap();
+        } else if byte == 8 { // ctrl-h
+            self.send_screen_instructions.send(ScreenInstruction::ResizeLeft).unwrap();
+        } else if byte == 12 { // ctrl-l
+            self.send_screen_instructions.send(ScreenInstruction::ResizeRight).unwrap();
+        } else if byte == 26 { // ctrl-z
+            self.send_pty_instructions.send(PtyInstruction::SpawnTerminal(None)).unwrap();
+        } else if byte == 14 { // ctrl-n
+            self.send_pty_instructions.send(PtyInstruction::SpawnTerminalVertically(None)).unwrap();
+        } else if byte == 2 { // ctrl-b
+            self.send_pty_instructions.send(PtyInstruction::SpawnTerminalHorizontally(None)).unwrap();
+        } else if byte == 17 { // ctrl-q
+            self.send_screen_instructions.send(ScreenInstruction::Quit).unwrap();
+            self.send_pty_instructions.send(PtyInstruction::Quit).unwrap();
+            return false;
+        } else if byte == 27 { // ctrl-[
+            self.send_screen_instructions.send(ScreenInstruction::ScrollUp).unwrap();
+        } else if byte == 29 { // ctrl-]
+            self.send_screen_instructions.send(ScreenInstruction::ScrollDown).unwrap();
+        } else if byte == 24 { // ctrl-x
+            self.send_screen_instructions.send(ScreenInstruction::CloseFocusedPane).unwrap();
+        } else {
+            self.send_screen_instructions.send(ScreenInstruction::ClearScroll).unwrap();
+            self.send_screen_instructions.send(ScreenInstruction::WriteCharacter(byte)).unwrap();
+        }
+        true
+    }
+}
+
+pub fn handle_client_input(
+    mut client: UnixStream,
+    client_id: ClientId,
+    read_only: bool,
+    command_bindings: Vec<CommandBinding>,
+    send_screen_instructions: Sender<ScreenInstruction>,
+    send_pty_instructions: Sender<PtyInstruction>,
+    send_server_instructions: Sender<ServerInstruction>,
+) {
+    let mut input_handler = InputHandler {
+        client_id,
+        read_only,
+        command_mode: false,
+        moving_pane: false,
+        command_bindings,
+        send_screen_instructions,
+        send_pty_instructions,
+        send_server_instructions,
+    };
+    loop {
+        match bincode::deserialize_from(&mut client) {
+            Ok(ClientToServerMsg::Input(bytes)) => {
+                for byte in bytes {
+                    if !input_handler.handle_byte(byte) {
+                        return;
+                    }
+                }
+            }
+            Ok(ClientToServerMsg::Resize(terminal_size)) => {
+                input_handler.send_server_instructions.send(ServerInstruction::ResizeClient(client_id, terminal_size)).unwrap();
+            }
+            Err(_) => {
+                // the client went away without detaching, eg. its terminal was closed
+                input_handler.send_server_instructions.send(ServerInstruction::DetachClient(client_id)).unwrap();
+                return;
+            }
+        }
+    }
+}
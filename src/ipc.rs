@@ -0,0 +1,360 @@
+use ::std::env;
+use ::std::fs;
+use ::std::io;
+use ::std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt, FileTypeExt};
+use ::std::os::unix::io::RawFd;
+use ::std::os::unix::net::{UnixListener, UnixStream};
+use ::std::path::PathBuf;
+use ::std::str::FromStr;
+use ::std::time::{SystemTime, UNIX_EPOCH};
+use ::nix::unistd::getuid;
+use ::serde::{Serialize, Deserialize};
+use ::serde::de::DeserializeOwned;
+
+use crate::input::Key;
+use crate::pty_bus::RunCommand;
+
+/*
+ * IPC
+ *
+ * every mosaic session listens on a unix socket named after the session
+ * these sockets live in a directory only the current user can access:
+ * $XDG_RUNTIME_DIR/mosaic, or /tmp/mosaic-<uid> if XDG_RUNTIME_DIR is not set
+ *
+ * every connection starts with an ApiCommand. most of them are one-off requests, but a connection
+ * that starts with ApiCommand::AttachClient stays open: the client then sends ClientToServerMsgs
+ * and the server answers with ServerToClientMsgs until the client detaches or the session ends
+ *
+ */
+
+// this is set in the environment of every pane, so that running mosaic inside a pane talks to the
+// session that pane belongs to
+pub const SESSION_NAME_ENV_KEY: &str = "MOSAIC_SESSION";
+// and this to the id of the pane itself, so that scripts can target the pane they're running in
+pub const PANE_ID_ENV_KEY: &str = "MOSAIC_PANE_ID";
+
+// the connections made to a session's socket, in the order they were made
+pub type ClientConnections = Box<dyn Iterator<Item = UnixStream> + Send>;
+
+#[derive(Serialize, Deserialize, Debug)]
+pub enum ApiCommand {
+    OpenFile(PathBuf),
+    RunCommand(RunCommand, Option<SplitDirection>), // without a direction, like a new pane
+    SplitHorizontally,
+    SplitVertically,
+    MoveFocus,
+    MoveFocusTowards(Direction),
+    FocusPreviousPane,
+    // these move the focused pane
+    SwapPane(Direction),
+    SwapWithNextPane,
+    MovePaneToPosition(usize), // in the order of the layout, from 0
+    ToggleZoom,
+    ToggleFloatingPanes, // opens one if the tab has none
+    NewFloatingPane(Option<RunCommand>), // without a command, a shell
+    NewTab,
+    CloseTab, // and all of its panes
+    RenameTab(String),
+    NextTab,
+    PreviousTab,
+    GetSessionInfo,
+    KillSession,
+    AttachClient(TerminalSize, AttachOptions),
+    // these reply with a Result<(), String>, the error being why the action couldn't be done
+    WriteChars(PaneSelector, String),
+    SendKeys(PaneSelector, Vec<Key>),
+    RenamePane(PaneSelector, String),
+    GoToTab(usize), // in the order of the tab bar, from 0
+    // replies with a Result<String, String>, the pane's contents or why they couldn't be captured
+    CapturePane(PaneSelector, CaptureOptions),
+    GetState,
+    // replies with the arrangement of the panes, as the yaml of a layout file
+    GetLayout,
+    // keeps the connection open and streams Events to it until the session ends
+    Subscribe,
+}
+
+// something that happened in the session, sent to the subscribed connections as it happens
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum Event {
+    PaneOpened { id: RawFd, x: u16, y: u16, rows: u16, cols: u16 },
+    PaneClosed { id: RawFd },
+    PaneFocused { id: RawFd },
+    PaneResized { id: RawFd, x: u16, y: u16, rows: u16, cols: u16 },
+    TitleChanged { id: RawFd, title: String },
+    Bell { id: RawFd },
+    ProcessExited { id: RawFd, exit_code: i32 },
+    ModeChanged { mode: InputMode },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum InputMode {
+    Normal,
+    Command, // after the command prefix (ctrl-g)
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")] // as written in layout files
+pub enum SplitDirection {
+    Horizontal,
+    Vertical,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum Direction {
+    Left,
+    Right,
+    Up,
+    Down,
+}
+
+impl FromStr for Direction {
+    type Err = String;
+    fn from_str(direction: &str) -> Result<Self, Self::Err> {
+        match direction {
+            "left" => Ok(Direction::Left),
+            "right" => Ok(Direction::Right),
+            "up" => Ok(Direction::Up),
+            "down" => Ok(Direction::Down),
+            _ => Err(format!("unknown direction \"{}\", expected left, right, up or down", direction)),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum PaneSelector {
+    Focused,
+    Id(RawFd),
+    Name(String), // if more than one pane has this name, the first of them
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default)]
+pub struct CaptureOptions {
+    pub ansi: bool, // keep the styles of the characters as ANSI escape codes
+    pub history: bool, // the whole scrollback rather than only what's on screen
+}
+
+// what the session currently shows, as a reply to ApiCommand::GetState
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SessionState {
+    pub name: String,
+    pub size: TerminalSize,
+    pub tabs: Vec<TabState>,
+    pub panes: Vec<PaneState>, // of all tabs
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TabState {
+    pub name: Option<String>, // given with mosaic action rename-tab
+    pub active: bool, // the tab that is shown
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PaneState {
+    pub id: RawFd,
+    pub name: Option<String>, // given with mosaic action rename-pane
+    pub title: Option<String>, // set by the application running in the pane
+    pub tab: usize, // the index of the pane's tab in tabs
+    pub x: u16,
+    pub y: u16,
+    pub rows: u16,
+    pub cols: u16,
+    pub cwd: Option<PathBuf>,
+    pub process: Option<String>, // the pane's foreground process (eg. the shell, or vim)
+    pub exit_code: Option<i32>, // once the command the pane is kept open for exited
+    pub floating: bool, // over the tiled panes, rather than in the layout
+    pub focused: bool, // only the focused pane of the tab that is shown
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub struct TerminalSize {
+    pub rows: u16,
+    pub cols: u16,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default)]
+pub struct AttachOptions {
+    pub read_only: bool, // everything but detaching is ignored
+    pub ignore_size: bool, // the screen is not shrunk to fit this client
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub enum ClientToServerMsg {
+    Input(Vec<u8>),
+    Resize(TerminalSize), // the client's terminal was resized to this
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum ServerToClientMsg {
+    Render(String),
+    Exit(ExitReason),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum ExitReason {
+    Quit,
+    Detached,
+    LostConnection, // the server went away without telling us why
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SessionInfo {
+    pub name: String,
+    pub pane_count: usize,
+    pub created_at: u64, // seconds since the unix epoch
+    pub attached_clients: usize,
+}
+
+impl SessionInfo {
+    pub fn created_ago(&self) -> String {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+        let seconds = now.saturating_sub(self.created_at);
+        if seconds < 60 {
+            format!("{}s", seconds)
+        } else if seconds < 60 * 60 {
+            format!("{}m", seconds / 60)
+        } else if seconds < 60 * 60 * 24 {
+            format!("{}h", seconds / (60 * 60))
+        } else {
+            format!("{}d", seconds / (60 * 60 * 24))
+        }
+    }
+}
+
+pub fn get_socket_dir() -> PathBuf {
+    match env::var_os("XDG_RUNTIME_DIR") {
+        Some(runtime_dir) => PathBuf::from(runtime_dir).join("mosaic"),
+        None => PathBuf::from(format!("/tmp/mosaic-{}", getuid())),
+    }
+}
+
+pub fn get_session_socket_path(session_name: &str) -> PathBuf {
+    get_socket_dir().join(session_name)
+}
+
+// a session's socket is named after it, so its name can't be a path that leads out of the socket
+// directory
+pub fn parse_session_name(session_name: &str) -> Result<String, String> {
+    if session_name.is_empty() || session_name == "." || session_name == ".." || session_name.contains('/') {
+        return Err(format!("invalid session name \"{}\", it can't be empty, \".\", \"..\" or contain \"/\"", session_name));
+    }
+    Ok(session_name.to_string())
+}
+
+fn create_socket_dir() -> io::Result<PathBuf> {
+    let socket_dir = get_socket_dir();
+    fs::DirBuilder::new().recursive(true).mode(0o700).create(&socket_dir)?;
+    let metadata = fs::metadata(&socket_dir)?;
+    if metadata.uid() != getuid().as_raw() {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("{:?} is owned by another user", socket_dir)
+        ));
+    }
+    if metadata.mode() & 0o077 != 0 {
+        // the directory existed before with looser permissions (or was created with a umask we
+        // don't control), other users should not be able to reach our sockets
+        fs::set_permissions(&socket_dir, fs::Permissions::from_mode(0o700))?;
+    }
+    Ok(socket_dir)
+}
+
+fn session_is_alive(socket_path: &PathBuf) -> bool {
+    // a socket file without a process listening on it is left over from a session that did not
+    // exit cleanly
+    UnixStream::connect(socket_path).is_ok()
+}
+
+pub fn session_exists(session_name: &str) -> bool {
+    session_is_alive(&get_session_socket_path(session_name))
+}
+
+pub fn bind_session_socket(session_name: &str) -> io::Result<UnixListener> {
+    let socket_path = create_socket_dir()?.join(session_name);
+    if socket_path.exists() {
+        if session_is_alive(&socket_path) {
+            return Err(io::Error::new(
+                io::ErrorKind::AddrInUse,
+                format!("session \"{}\" already exists", session_name)
+            ));
+        }
+        fs::remove_file(&socket_path)?;
+    }
+    // other users can't reach the socket through its directory (see create_socket_dir), so it
+    // can be bound with the default permissions and only then be made private as well
+    let listener = UnixListener::bind(&socket_path)?;
+    fs::set_permissions(&socket_path, fs::Permissions::from_mode(0o600))?;
+    Ok(listener)
+}
+
+pub fn remove_session_socket(session_name: &str) {
+    fs::remove_file(get_session_socket_path(session_name)).ok();
+}
+
+pub fn list_sessions() -> Vec<String> {
+    let mut sessions = vec![];
+    let entries = match fs::read_dir(get_socket_dir()) {
+        Ok(entries) => entries,
+        Err(_) => return sessions, // no session was ever started by this user
+    };
+    for entry in entries.filter_map(|entry| entry.ok()) {
+        let is_socket = entry.file_type().map(|file_type| file_type.is_socket()).unwrap_or(false);
+        if !is_socket {
+            continue;
+        }
+        let socket_path = entry.path();
+        if session_is_alive(&socket_path) {
+            sessions.push(entry.file_name().to_string_lossy().to_string());
+        } else {
+            fs::remove_file(&socket_path).ok();
+        }
+    }
+    sessions.sort();
+    sessions
+}
+
+pub fn next_available_session_name() -> String {
+    // like tmux, sessions that were not explicitly named are numbered from 0
+    let existing_sessions = list_sessions();
+    let mut session_index = 0;
+    while existing_sessions.contains(&session_index.to_string()) {
+        session_index += 1;
+    }
+    session_index.to_string()
+}
+
+pub fn resolve_session_name(session_name: Option<String>) -> Result<String, String> {
+    if let Some(session_name) = session_name {
+        return Ok(session_name);
+    }
+    if let Ok(session_name) = env::var(SESSION_NAME_ENV_KEY) {
+        return parse_session_name(&session_name);
+    }
+    let mut sessions = list_sessions();
+    match sessions.len() {
+        0 => Err(String::from("no active mosaic sessions")),
+        1 => Ok(sessions.remove(0)),
+        _ => Err(format!(
+            "more than one active session ({}), choose one with --session or {}",
+            sessions.join(", "),
+            SESSION_NAME_ENV_KEY
+        )),
+    }
+}
+
+pub fn connect_to_session(session_name: &str) -> io::Result<UnixStream> {
+    UnixStream::connect(get_session_socket_path(session_name)).map_err(|e| {
+        io::Error::new(e.kind(), format!("could not connect to session \"{}\": {}", session_name, e))
+    })
+}
+
+pub fn send_api_command(session_name: &str, api_command: &ApiCommand) -> io::Result<UnixStream> {
+    let mut stream = connect_to_session(session_name)?;
+    bincode::serialize_into(&mut stream, api_command).map_err(io::Error::other)?;
+    Ok(stream)
+}
+
+pub fn send_api_request<T: DeserializeOwned>(session_name: &str, api_command: &ApiCommand) -> io::Result<T> {
+    let mut stream = send_api_command(session_name, api_command)?;
+    bincode::deserialize_from(&mut stream).map_err(io::Error::other)
+}
@@ -0,0 +1,435 @@
+use std::os::unix::io::RawFd;
+
+use crate::ipc::SplitDirection;
+
+/*
+ * Layout
+ *
+ * how the panes are arranged on screen, as a tree of splits: each split lays out its parts
+ * either one above the other (horizontally) or side by side (vertically) with a one cell border
+ * between every two of them, and each part takes its share of the split according to its ratio
+ * the coordinates and sizes of the panes are computed from this tree rather than stored, so that
+ * splitting, closing and resizing panes only ever changes the tree
+ *
+ * splits and resizes that would leave a pane smaller than the minimum pane size are refused (a
+ * screen that shrinks can still squash panes below it, until it grows back)
+ *
+ */
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PaneGeometry {
+    pub x: u16,
+    pub y: u16,
+    pub rows: u16,
+    pub cols: u16,
+}
+
+impl PaneGeometry {
+    fn length (&self, direction: SplitDirection) -> u16 {
+        match direction {
+            SplitDirection::Horizontal => self.rows,
+            SplitDirection::Vertical => self.cols,
+        }
+    }
+    fn part (&self, direction: SplitDirection, offset: u16, length: u16) -> PaneGeometry {
+        match direction {
+            SplitDirection::Horizontal => PaneGeometry { y: self.y + offset, rows: length, ..*self },
+            SplitDirection::Vertical => PaneGeometry { x: self.x + offset, cols: length, ..*self },
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MinPaneSize {
+    pub rows: u16,
+    pub cols: u16,
+}
+
+impl MinPaneSize {
+    fn length (&self, direction: SplitDirection) -> u16 {
+        match direction {
+            SplitDirection::Horizontal => self.rows,
+            SplitDirection::Vertical => self.cols,
+        }
+    }
+}
+
+impl Default for MinPaneSize {
+    fn default () -> Self {
+        MinPaneSize { rows: 1, cols: 1 }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Edge {
+    Start, // top or left
+    End, // bottom or right
+}
+
+pub enum LayoutNode {
+    Pane(RawFd),
+    Split(SplitDirection, Vec<LayoutPart>),
+}
+
+pub struct LayoutPart {
+    pub ratio: u32, // relative to the other parts of the same split
+    pub node: LayoutNode,
+}
+
+pub struct Layout {
+    root: Option<LayoutNode>,
+    min_pane_size: MinPaneSize,
+}
+
+impl Layout {
+    pub fn new (min_pane_size: MinPaneSize) -> Self {
+        Layout { root: None, min_pane_size }
+    }
+    pub fn with_root (root: LayoutNode, min_pane_size: MinPaneSize) -> Self {
+        Layout { root: Some(root), min_pane_size }
+    }
+    pub fn root (&self) -> Option<&LayoutNode> {
+        self.root.as_ref()
+    }
+    pub fn add_only_pane (&mut self, id: RawFd) {
+        self.root = Some(LayoutNode::Pane(id));
+    }
+    pub fn pane_geometries (&self, cols: u16, rows: u16) -> Option<Vec<(RawFd, PaneGeometry)>> {
+        // None if the panes can't all fit in this size
+        let mut geometries = vec![];
+        if let Some(root) = &self.root {
+            collect_geometries(root, PaneGeometry { x: 0, y: 0, rows, cols }, &mut geometries)?;
+        }
+        Some(geometries)
+    }
+    pub fn split_pane (&mut self, id: RawFd, new_id: RawFd, direction: SplitDirection, cols: u16, rows: u16) -> bool {
+        // the new pane takes the second half of the pane being split
+        let length = match self.pane_geometry(id, cols, rows) {
+            Some(geometry) => geometry.length(direction),
+            None => return false,
+        };
+        if length < self.min_pane_size.length(direction) * 2 + 1 {
+            return false; // no room for two panes and a border between them
+        }
+        let (first_length, second_length) = split_with_gap(length);
+        self.set_ratios_to_lengths(cols, rows);
+        let path = self.path_to_pane(id).unwrap();
+        if let Some((index, parent_path)) = path.split_last() {
+            if let LayoutNode::Split(parent_direction, parts) = self.node_at(parent_path) {
+                if *parent_direction == direction {
+                    // a split in the same direction as its parent would only be a part of it
+                    parts[*index].ratio = first_length as u32;
+                    parts.insert(index + 1, LayoutPart { ratio: second_length as u32, node: LayoutNode::Pane(new_id) });
+                    return true;
+                }
+            }
+        }
+        *self.node_at(&path) = LayoutNode::Split(direction, vec![
+            LayoutPart { ratio: first_length as u32, node: LayoutNode::Pane(id) },
+            LayoutPart { ratio: second_length as u32, node: LayoutNode::Pane(new_id) },
+        ]);
+        true
+    }
+    pub fn remove_pane (&mut self, id: RawFd, cols: u16, rows: u16) -> Option<RawFd> {
+        // the part before the pane (or after it, if it's the first) takes over its space
+        // returns the pane next to where the removed one was, None if it was the only pane left
+        self.set_ratios_to_lengths(cols, rows);
+        let path = self.path_to_pane(id)?;
+        let (index, parent_path) = path.split_last()?;
+        let parent = self.node_at(parent_path);
+        let (direction, parts) = match parent {
+            LayoutNode::Split(direction, parts) => (*direction, parts),
+            LayoutNode::Pane(_) => unreachable!(),
+        };
+        let removed_part = parts.remove(*index);
+        let (heir_index, edge) = if *index > 0 { (index - 1, Edge::End) } else { (0, Edge::Start) };
+        let heir = &mut parts[heir_index];
+        let growth = removed_part.ratio as i32 + 1; // 1 for the border
+        heir.ratio = add_to_ratio(heir.ratio, growth);
+        move_edge(&mut heir.node, direction, edge, if edge == Edge::End { growth } else { -growth });
+        let next_to_removed_pane = panes_at_edge(&heir.node, direction, edge).into_iter().max();
+        if parts.len() == 1 {
+            let only_part = parts.pop().unwrap();
+            *parent = only_part.node;
+            self.merge_into_parent(parent_path);
+        }
+        next_to_removed_pane
+    }
+    pub fn replace_pane (&mut self, id: RawFd, new_id: RawFd) {
+        if let Some(path) = self.path_to_pane(id) {
+            *self.node_at(&path) = LayoutNode::Pane(new_id);
+        }
+    }
+    pub fn resize_pane (&mut self, id: RawFd, direction: SplitDirection, preferred_edge: Edge, count: i32, cols: u16, rows: u16) -> bool {
+        // moves a border of the pane by count cells (towards the bottom/right if positive), the
+        // one on its preferred edge if it has a border there, otherwise the one on the other edge
+        // returns false if there was no border to move or no room to move it
+        self.set_ratios_to_lengths(cols, rows);
+        let path = match self.path_to_pane(id) {
+            Some(path) => path,
+            None => return false,
+        };
+        let other_edge = if preferred_edge == Edge::Start { Edge::End } else { Edge::Start };
+        let border = self.find_border(&path, direction, preferred_edge)
+            .or_else(|| self.find_border(&path, direction, other_edge));
+        let (split_path, border_index) = match border {
+            Some(border) => border,
+            None => return false,
+        };
+        let min_length = self.min_pane_size.length(direction);
+        let parts = match self.node_at(&split_path) {
+            LayoutNode::Split(_, parts) => parts,
+            LayoutNode::Pane(_) => unreachable!(),
+        };
+        let (before, after) = parts.split_at_mut(border_index + 1);
+        let (part_before, part_after) = (before.last_mut().unwrap(), after.first_mut().unwrap());
+        let count = if count > 0 {
+            count.min(room_to_shrink(&part_after.node, part_after.ratio as u16, direction, Edge::Start, min_length) as i32)
+        } else {
+            count.max(-(room_to_shrink(&part_before.node, part_before.ratio as u16, direction, Edge::End, min_length) as i32))
+        };
+        if count == 0 {
+            return false;
+        }
+        part_before.ratio = add_to_ratio(part_before.ratio, count);
+        move_edge(&mut part_before.node, direction, Edge::End, count);
+        part_after.ratio = add_to_ratio(part_after.ratio, -count);
+        move_edge(&mut part_after.node, direction, Edge::Start, count);
+        true
+    }
+    pub fn pane_ids (&self) -> Vec<RawFd> {
+        // in the order of the layout: top to bottom, left to right in each split
+        let mut pane_ids = vec![];
+        if let Some(root) = &self.root {
+            collect_pane_ids(root, &mut pane_ids);
+        }
+        pane_ids
+    }
+    pub fn set_pane_ids (&mut self, pane_ids: &[RawFd]) {
+        // puts these panes in the places of the layout, in the order of pane_ids()
+        if let Some(root) = &mut self.root {
+            set_pane_ids(root, &mut pane_ids.iter().copied());
+        }
+    }
+    pub fn pane_geometry (&self, id: RawFd, cols: u16, rows: u16) -> Option<PaneGeometry> {
+        self.pane_geometries(cols, rows)?.into_iter()
+            .find(|(pane_id, _)| *pane_id == id)
+            .map(|(_, geometry)| geometry)
+    }
+    fn set_ratios_to_lengths (&mut self, cols: u16, rows: u16) {
+        // the ratios are kept as they are across changes to the screen size, so that growing the
+        // screen back restores the layout - until the layout itself changes, and then we start
+        // over from the sizes it has now, so that the change is exactly as many cells as asked for
+        if let Some(root) = &mut self.root {
+            set_ratios_to_lengths(root, PaneGeometry { x: 0, y: 0, rows, cols });
+        }
+    }
+    fn path_to_pane (&self, id: RawFd) -> Option<Vec<usize>> {
+        let mut path = vec![];
+        if path_to_pane(self.root.as_ref()?, id, &mut path) {
+            Some(path)
+        } else {
+            None
+        }
+    }
+    fn node_at (&mut self, path: &[usize]) -> &mut LayoutNode {
+        let mut node = self.root.as_mut().unwrap();
+        for index in path {
+            node = match node {
+                LayoutNode::Split(_, parts) => &mut parts[*index].node,
+                LayoutNode::Pane(_) => panic!("no layout node at path"),
+            };
+        }
+        node
+    }
+    fn find_border (&self, path: &[usize], direction: SplitDirection, edge: Edge) -> Option<(Vec<usize>, usize)> {
+        // the closest split in this direction with a part on that edge of the pane
+        // returns the path to the split, and the index of the part before the border
+        let mut node = self.root.as_ref().unwrap();
+        let mut border = None;
+        for (depth, index) in path.iter().enumerate() {
+            let (split_direction, parts) = match node {
+                LayoutNode::Split(split_direction, parts) => (*split_direction, parts),
+                LayoutNode::Pane(_) => unreachable!(),
+            };
+            if split_direction == direction {
+                match edge {
+                    Edge::Start if *index > 0 => border = Some((path[..depth].to_vec(), index - 1)),
+                    Edge::End if *index < parts.len() - 1 => border = Some((path[..depth].to_vec(), *index)),
+                    _ => {}
+                }
+            }
+            node = &parts[*index].node;
+        }
+        border
+    }
+    fn merge_into_parent (&mut self, path: &[usize]) {
+        // a split that ended up inside a split in the same direction becomes part of it
+        let (index, parent_path) = match path.split_last() {
+            Some(index_and_parent_path) => index_and_parent_path,
+            None => return,
+        };
+        let parent_direction = match self.node_at(parent_path) {
+            LayoutNode::Split(direction, _) => *direction,
+            LayoutNode::Pane(_) => unreachable!(),
+        };
+        let parts = match self.node_at(path) {
+            LayoutNode::Split(direction, parts) if *direction == parent_direction => ::std::mem::take(parts),
+            _ => return,
+        };
+        if let LayoutNode::Split(_, parent_parts) = self.node_at(parent_path) {
+            parent_parts.splice(index..=index, parts);
+        }
+    }
+}
+
+fn split_with_gap (length: u16) -> (u16, u16) {
+    let length_of_each_half = (length - 1) / 2;
+    if length.is_multiple_of(2) {
+        (length_of_each_half + 1, length_of_each_half)
+    } else {
+        (length_of_each_half, length_of_each_half)
+    }
+}
+
+fn add_to_ratio (ratio: u32, count: i32) -> u32 {
+    // count can be negative, but a ratio can't
+    (ratio as i64 + count as i64).max(0) as u32
+}
+
+fn part_lengths (parts: &[LayoutPart], length: u16) -> Option<Vec<u16>> {
+    let borders = parts.len() as u16 - 1;
+    let available = length.checked_sub(borders)? as u64;
+    let total_ratio: u64 = parts.iter().map(|part| part.ratio as u64).sum();
+    let mut lengths = vec![];
+    let mut ratio_so_far = 0;
+    let mut length_so_far = 0;
+    for part in parts {
+        ratio_so_far += part.ratio as u64;
+        let end = (ratio_so_far * available).checked_div(total_ratio).unwrap_or(available);
+        lengths.push((end - length_so_far) as u16);
+        length_so_far = end;
+    }
+    Some(lengths)
+}
+
+fn collect_geometries (node: &LayoutNode, geometry: PaneGeometry, geometries: &mut Vec<(RawFd, PaneGeometry)>) -> Option<()> {
+    match node {
+        LayoutNode::Pane(id) => {
+            if geometry.rows == 0 || geometry.cols == 0 {
+                return None;
+            }
+            geometries.push((*id, geometry));
+        }
+        LayoutNode::Split(direction, parts) => {
+            let lengths = part_lengths(parts, geometry.length(*direction))?;
+            let mut offset = 0;
+            for (part, length) in parts.iter().zip(lengths) {
+                collect_geometries(&part.node, geometry.part(*direction, offset, length), geometries)?;
+                offset += length + 1; // 1 for the border
+            }
+        }
+    }
+    Some(())
+}
+
+fn collect_pane_ids (node: &LayoutNode, pane_ids: &mut Vec<RawFd>) {
+    match node {
+        LayoutNode::Pane(id) => pane_ids.push(*id),
+        LayoutNode::Split(_, parts) => {
+            for part in parts {
+                collect_pane_ids(&part.node, pane_ids);
+            }
+        }
+    }
+}
+
+fn set_pane_ids (node: &mut LayoutNode, pane_ids: &mut dyn Iterator<Item = RawFd>) {
+    match node {
+        LayoutNode::Pane(id) => *id = pane_ids.next().expect("not enough panes for layout"),
+        LayoutNode::Split(_, parts) => {
+            for part in parts.iter_mut() {
+                set_pane_ids(&mut part.node, pane_ids);
+            }
+        }
+    }
+}
+
+fn set_ratios_to_lengths (node: &mut LayoutNode, geometry: PaneGeometry) {
+    if let LayoutNode::Split(direction, parts) = node {
+        if let Some(lengths) = part_lengths(parts, geometry.length(*direction)) {
+            let mut offset = 0;
+            for (part, length) in parts.iter_mut().zip(lengths) {
+                part.ratio = length as u32;
+                set_ratios_to_lengths(&mut part.node, geometry.part(*direction, offset, length));
+                offset += length + 1; // 1 for the border
+            }
+        }
+    }
+}
+
+fn path_to_pane (node: &LayoutNode, id: RawFd, path: &mut Vec<usize>) -> bool {
+    match node {
+        LayoutNode::Pane(pane_id) => *pane_id == id,
+        LayoutNode::Split(_, parts) => {
+            for (index, part) in parts.iter().enumerate() {
+                path.push(index);
+                if path_to_pane(&part.node, id, path) {
+                    return true;
+                }
+                path.pop();
+            }
+            false
+        }
+    }
+}
+
+fn edge_part (parts: &mut [LayoutPart], edge: Edge) -> &mut LayoutPart {
+    match edge {
+        Edge::Start => parts.first_mut().unwrap(),
+        Edge::End => parts.last_mut().unwrap(),
+    }
+}
+
+fn move_edge (node: &mut LayoutNode, direction: SplitDirection, edge: Edge, count: i32) {
+    // only the panes along that edge change size, the rest of the node stays as it is
+    let growth = if edge == Edge::End { count } else { -count };
+    if let LayoutNode::Split(split_direction, parts) = node {
+        if *split_direction == direction {
+            let part = edge_part(parts, edge);
+            part.ratio = add_to_ratio(part.ratio, growth);
+            move_edge(&mut part.node, direction, edge, count);
+        } else {
+            for part in parts.iter_mut() {
+                move_edge(&mut part.node, direction, edge, count);
+            }
+        }
+    }
+}
+
+fn room_to_shrink (node: &LayoutNode, length: u16, direction: SplitDirection, edge: Edge, min_length: u16) -> u16 {
+    // how many cells the node can give up on that edge, leaving every pane at least min_length
+    match node {
+        LayoutNode::Pane(_) => length.saturating_sub(min_length),
+        LayoutNode::Split(split_direction, parts) if *split_direction == direction => {
+            let part = if edge == Edge::Start { parts.first().unwrap() } else { parts.last().unwrap() };
+            room_to_shrink(&part.node, part.ratio as u16, direction, edge, min_length)
+        }
+        LayoutNode::Split(_, parts) => {
+            parts.iter().map(|part| room_to_shrink(&part.node, length, direction, edge, min_length)).min().unwrap()
+        }
+    }
+}
+
+fn panes_at_edge (node: &LayoutNode, direction: SplitDirection, edge: Edge) -> Vec<RawFd> {
+    match node {
+        LayoutNode::Pane(id) => vec![*id],
+        LayoutNode::Split(split_direction, parts) if *split_direction == direction => {
+            let part = if edge == Edge::Start { parts.first().unwrap() } else { parts.last().unwrap() };
+            panes_at_edge(&part.node, direction, edge)
+        }
+        LayoutNode::Split(_, parts) => {
+            parts.iter().flat_map(|part| panes_at_edge(&part.node, direction, edge)).collect()
+        }
+    }
+}
@@ -0,0 +1,209 @@
+use ::std::convert::TryFrom;
+use ::std::fs;
+use ::std::os::unix::io::RawFd;
+use ::std::path::Path;
+use ::std::path::PathBuf;
+use ::serde::{Serialize, Deserialize};
+
+use crate::ipc::SplitDirection;
+use crate::layout::{LayoutNode, LayoutPart};
+use crate::pty_bus::{RunCommand, TerminalAction};
+
+/*
+ * LayoutFile
+ *
+ * a workspace described in a yaml file, to start a session with (mosaic --layout) or to save the
+ * arrangement of a running one to (mosaic save-layout). it is the same tree of splits the panes
+ * are laid out with, where each part can be given a size, and each pane a name, a directory and
+ * a command to run instead of the shell:
+ *
+ *   direction: vertical        # side by side, horizontal is one above the other
+ *   parts:
+ *     - size: 75%              # of the split, a number without % is a fixed amount of cells
+ *       name: editor
+ *       command: vim
+ *     - direction: horizontal
+ *       parts:
+ *         - cwd: src           # relative to the directory of the layout file
+ *         - size: 5
+ *           command: cargo
+ *           args: [watch, -x, test]
+ *
+ * parts without a size share whatever the sized parts left of their split
+ *
+ */
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct LayoutFile {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<PartSize>, // ignored for the whole layout, it always takes the whole screen
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub direction: Option<SplitDirection>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub parts: Vec<LayoutFile>, // a pane if there are none
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub command: Option<PathBuf>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub args: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cwd: Option<PathBuf>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(try_from = "SizeInFile", into = "SizeInFile")]
+pub enum PartSize {
+    Percent(u16),
+    Fixed(u16), // rows or columns, depending on the direction of the split
+}
+
+// eg. 10 or "25%"
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum SizeInFile {
+    Fixed(u16),
+    Percent(String),
+}
+
+impl TryFrom<SizeInFile> for PartSize {
+    type Error = String;
+    fn try_from(size: SizeInFile) -> Result<Self, Self::Error> {
+        match size {
+            SizeInFile::Fixed(cells) => Ok(PartSize::Fixed(cells)),
+            SizeInFile::Percent(text) => {
+                let percent = text.trim().strip_suffix('%')
+                    .and_then(|percent| percent.trim().parse::<u16>().ok())
+                    .filter(|percent| *percent <= 100)
+                    .ok_or_else(|| format!("invalid size \"{}\", expected a number of cells or a percentage (eg. 25%)", text))?;
+                Ok(PartSize::Percent(percent))
+            }
+        }
+    }
+}
+
+impl From<PartSize> for SizeInFile {
+    fn from(size: PartSize) -> Self {
+        match size {
+            PartSize::Percent(percent) => SizeInFile::Percent(format!("{}%", percent)),
+            PartSize::Fixed(cells) => SizeInFile::Fixed(cells),
+        }
+    }
+}
+
+impl LayoutFile {
+    pub fn load (path: &Path) -> Result<Self, String> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format!("could not read layout file {}: {}", path.display(), e))?;
+        let mut layout_file: LayoutFile = serde_yaml::from_str(&contents)
+            .map_err(|e| format!("could not parse layout file {}: {}", path.display(), e))?;
+        layout_file.validate()
+            .map_err(|e| format!("invalid layout file {}: {}", path.display(), e))?;
+        // so that the layout works the same wherever it's started from
+        if let Some(directory) = path.parent() {
+            layout_file.resolve_cwds(directory);
+        }
+        Ok(layout_file)
+    }
+    pub fn to_yaml (&self) -> String {
+        serde_yaml::to_string(self).expect("failed to serialize layout file")
+    }
+    pub fn from_layout_node (node: &LayoutNode, pane: &dyn Fn(RawFd) -> LayoutFile) -> Self {
+        // pane gives the name, command and cwd of each pane in the layout
+        match node {
+            LayoutNode::Pane(id) => pane(*id),
+            LayoutNode::Split(direction, parts) => {
+                let total_ratio: u32 = parts.iter().map(|part| part.ratio).sum();
+                let parts = parts.iter().map(|part| {
+                    let percent = (part.ratio * 100 + total_ratio / 2).checked_div(total_ratio).unwrap_or(0);
+                    LayoutFile {
+                        size: Some(PartSize::Percent(percent as u16)),
+                        ..LayoutFile::from_layout_node(&part.node, pane)
+                    }
+                }).collect();
+                LayoutFile {
+                    direction: Some(*direction),
+                    parts,
+                    ..LayoutFile::default()
+                }
+            }
+        }
+    }
+    pub fn panes (&self) -> Vec<&LayoutFile> {
+        // in the order they appear in the file
+        if self.parts.is_empty() {
+            vec![self]
+        } else {
+            self.parts.iter().flat_map(|part| part.panes()).collect()
+        }
+    }
+    pub fn terminal_action (&self) -> Option<TerminalAction> {
+        match (&self.command, &self.cwd) {
+            (Some(command), cwd) => Some(TerminalAction::RunCommand(RunCommand {
+                command: command.clone(),
+                args: self.args.clone(),
+                cwd: cwd.clone(),
+                env: vec![],
+            })),
+            (None, Some(cwd)) => Some(TerminalAction::Shell(cwd.clone())),
+            (None, None) => None,
+        }
+    }
+    pub fn layout_node (&self, pane_ids: &mut dyn Iterator<Item = RawFd>, cols: u16, rows: u16) -> LayoutNode {
+        // pane_ids are the ids of the panes, in the same order as panes() returns them
+        let direction = match self.direction {
+            Some(direction) if !self.parts.is_empty() => direction,
+            _ => return LayoutNode::Pane(pane_ids.next().expect("not enough panes for layout")),
+        };
+        let length = match direction {
+            SplitDirection::Horizontal => rows,
+            SplitDirection::Vertical => cols,
+        };
+        let available = length.saturating_sub(self.parts.len() as u16 - 1);
+        let sized_lengths: Vec<Option<u16>> = self.parts.iter().map(|part| {
+            match part.size? {
+                PartSize::Percent(percent) => Some((available as u32 * percent as u32 / 100) as u16),
+                PartSize::Fixed(cells) => Some(cells),
+            }
+        }).collect();
+        let unsized_parts = sized_lengths.iter().filter(|length| length.is_none()).count() as u16;
+        let left_for_unsized = available.saturating_sub(sized_lengths.iter().flatten().sum());
+        let unsized_length = left_for_unsized.checked_div(unsized_parts).unwrap_or(0);
+        let parts = self.parts.iter().zip(sized_lengths).map(|(part, sized_length)| {
+            let length = sized_length.unwrap_or(unsized_length).max(1);
+            let (cols, rows) = match direction {
+                SplitDirection::Horizontal => (cols, length),
+                SplitDirection::Vertical => (length, rows),
+            };
+            LayoutPart {
+                ratio: length as u32,
+                node: part.layout_node(pane_ids, cols, rows),
+            }
+        }).collect();
+        LayoutNode::Split(direction, parts)
+    }
+    fn validate (&self) -> Result<(), String> {
+        if self.parts.is_empty() {
+            if !self.args.is_empty() && self.command.is_none() {
+                return Err(String::from("a pane has args but no command"));
+            }
+            return Ok(());
+        }
+        if self.direction.is_none() {
+            return Err(String::from("a part with parts needs a direction (horizontal or vertical)"));
+        }
+        if self.name.is_some() || self.command.is_some() || !self.args.is_empty() || self.cwd.is_some() {
+            return Err(String::from("only panes can have a name, command, args or cwd, not parts with parts"));
+        }
+        self.parts.iter().try_for_each(|part| part.validate())
+    }
+    fn resolve_cwds (&mut self, directory: &Path) {
+        if let Some(cwd) = &self.cwd {
+            self.cwd = Some(directory.join(cwd));
+        }
+        for part in self.parts.iter_mut() {
+            part.resolve_cwds(directory);
+        }
+    }
+}
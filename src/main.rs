@@ -5,30 +5,200 @@ mod os_input_output;
 mod terminal_pane;
 mod pty_bus;
 mod screen;
+mod tab;
 mod boundaries;
+mod output_grid;
+mod layout;
+mod layout_file;
+mod ipc;
+mod input;
+mod server;
+mod client;
 
-use std::io::{Read, Write};
-use std::thread;
-use std::os::unix::net::UnixStream;
+use std::fmt::Display;
+use std::fs;
+use std::io::{self, Write};
+use std::process;
+use std::str::FromStr;
 use std::path::PathBuf;
-use std::sync::mpsc::{channel, Sender, Receiver};
 
-use serde::{Serialize, Deserialize};
 use structopt::StructOpt;
 
-use crate::os_input_output::{get_os_input, OsApi};
-use crate::pty_bus::{VteEvent, PtyBus, PtyInstruction};
-use crate::screen::{Screen, ScreenInstruction};
+use crate::os_input_output::{get_os_input, ClientOsApi};
+use crate::ipc::{ApiCommand, AttachOptions, CaptureOptions, Direction, Event, PaneSelector, SessionInfo, SessionState, SplitDirection};
+use crate::pty_bus::RunCommand;
+use crate::layout_file::LayoutFile;
+use crate::input::{CommandBinding, Key};
+use crate::server::start_server;
+use crate::client::start_client;
 
-#[derive(Serialize, Deserialize, Debug)]
-enum ApiCommand {
-    OpenFile(PathBuf),
-    SplitHorizontally,
-    SplitVertically,
-    MoveFocus,
+#[derive(StructOpt, Debug, Clone)]
+pub enum Command {
+    /// List running sessions
+    #[structopt(alias = "ls")]
+    ListSessions,
+    /// Attach to a running session
+    Attach {
+        #[structopt(parse(try_from_str = ipc::parse_session_name))]
+        session_name: String,
+        #[structopt(long)]
+        /// Only watch the session, all input other than detaching is ignored
+        read_only: bool,
+        #[structopt(long)]
+        /// Don't shrink the session's screen to fit this terminal
+        ignore_size: bool,
+    },
+    /// Close all panes of a running session and end it
+    KillSession {
+        #[structopt(parse(try_from_str = ipc::parse_session_name))]
+        session_name: String,
+    },
+    /// Send an action to a running session (see --session)
+    Action(Action),
+    /// List the panes of a running session (see --session)
+    ListPanes {
+        #[structopt(long)]
+        /// Print the whole state of the session (its size, and the geometry, focus, title, cwd
+        /// and process of each pane) as JSON
+        json: bool,
+    },
+    /// Run a command in a new pane of a running session (see --session), eg. mosaic run -- cargo watch -x test
+    #[structopt(setting = structopt::clap::AppSettings::TrailingVarArg)]
+    Run {
+        #[structopt(short, long)]
+        /// Split the focused pane (h == horizontally / v == vertically) rather than the largest one
+        split: Option<char>,
+        #[structopt(long, conflicts_with = "split")]
+        /// Run the command in a floating pane, over the others
+        floating: bool,
+        #[structopt(long)]
+        /// Directory to run the command in, defaults to the current directory
+        cwd: Option<PathBuf>,
+        #[structopt(long, parse(try_from_str = parse_env_var), number_of_values = 1)]
+        /// Environment variable to set for the command, as NAME=VALUE (can be repeated)
+        env: Vec<(String, String)>,
+        #[structopt(required = true)]
+        command: Vec<String>,
+    },
+    /// Print the events of a running session as they happen, one JSON object per line (see --session)
+    Subscribe,
+    /// Print the contents of a pane of a running session (see --session)
+    CapturePane {
+        #[structopt(long)]
+        /// Id of the pane to capture, defaults to the focused pane
+        pane: Option<i32>,
+        #[structopt(long)]
+        /// Keep colors and other styles, as ANSI escape codes
+        ansi: bool,
+        #[structopt(long)]
+        /// Capture the whole scrollback of the pane rather than only what is on screen
+        history: bool,
+    },
+    /// Save the arrangement of the panes of a running session to a layout file (see --session and --layout)
+    SaveLayout {
+        path: PathBuf,
+    },
 }
 
-#[derive(StructOpt, Debug, Default)]
+#[derive(StructOpt, Debug, Clone)]
+pub enum Action {
+    /// Write characters to a pane, as if they were typed in it
+    WriteChars {
+        chars: String,
+        #[structopt(flatten)]
+        pane: PaneOpt,
+    },
+    /// Send keys to a pane, by name (eg. Enter, Up, C-c, M-x) or as literal text
+    SendKeys {
+        #[structopt(required = true)]
+        keys: Vec<Key>,
+        #[structopt(flatten)]
+        pane: PaneOpt,
+    },
+    /// Name a pane, so that it can be targeted with --pane-name
+    RenamePane {
+        name: String,
+        #[structopt(flatten)]
+        pane: PaneOpt,
+    },
+    /// Move focus to the pane next to the focused one (left, right, up or down)
+    MoveFocus {
+        direction: Direction,
+    },
+    /// Move focus back to the pane that was focused before the focused one
+    FocusPreviousPane,
+    /// Swap the focused pane with the one next to it (left, right, up or down), or with the next
+    /// one in the layout (next)
+    SwapPane {
+        direction: SwapTarget,
+    },
+    /// Move the focused pane to a position in the layout (from 1, top to bottom and left to
+    /// right in each split), the panes in between move one place to make room
+    MovePane {
+        position: usize,
+    },
+    /// Make the focused pane take the whole screen, or put it back in its place if it already does
+    ToggleZoom,
+    /// Show the floating panes of the tab, or hide them if they are shown (opens one if there are none)
+    ToggleFloatingPanes,
+    /// Open a floating pane, over the others
+    NewFloatingPane,
+    /// Open a new tab, with a single pane
+    NewTab,
+    /// Close the tab that is shown, with all of its panes
+    CloseTab,
+    /// Name the tab that is shown, as it appears on the tab bar
+    RenameTab {
+        name: String,
+    },
+    /// Show the next tab
+    NextTab,
+    /// Show the previous tab
+    PreviousTab,
+    /// Show a tab, by its position on the tab bar (from 1)
+    GoToTab {
+        position: usize,
+    },
+}
+
+#[derive(StructOpt, Debug, Clone)]
+pub struct PaneOpt {
+    #[structopt(long, conflicts_with = "pane-name")]
+    /// Id of the pane to act on (panes have theirs in $MOSAIC_PANE_ID), defaults to the focused pane
+    pane_id: Option<i32>,
+    #[structopt(long)]
+    /// Name of the pane to act on, defaults to the focused pane
+    pane_name: Option<String>,
+}
+
+impl PaneOpt {
+    pub fn pane_selector(self) -> PaneSelector {
+        match (self.pane_id, self.pane_name) {
+            (Some(pane_id), _) => PaneSelector::Id(pane_id),
+            (None, Some(pane_name)) => PaneSelector::Name(pane_name),
+            (None, None) => PaneSelector::Focused,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum SwapTarget {
+    Direction(Direction),
+    Next,
+}
+
+impl FromStr for SwapTarget {
+    type Err = String;
+    fn from_str(target: &str) -> Result<Self, Self::Err> {
+        match target {
+            "next" => Ok(SwapTarget::Next),
+            direction => direction.parse().map(SwapTarget::Direction)
+                .map_err(|_| format!("unknown direction \"{}\", expected left, right, up, down or next", direction)),
+        }
+    }
+}
+
+#[derive(StructOpt, Debug, Default, Clone)]
 #[structopt(name = "mosaic")]
 pub struct Opt {
     #[structopt(short, long)]
@@ -42,7 +212,35 @@ pub struct Opt {
     open_file: Option<PathBuf>,
     #[structopt(long)]
     /// Maximum panes on screen, caution: opening more panes will close old ones
-    max_panes: Option<usize>
+    max_panes: Option<usize>,
+    #[structopt(long)]
+    /// Minimum width of a pane, in columns: splits and resizes that would leave a pane narrower
+    /// are refused
+    min_pane_cols: Option<u16>,
+    #[structopt(long)]
+    /// Minimum height of a pane, in rows: splits and resizes that would leave a pane shorter are
+    /// refused
+    min_pane_rows: Option<u16>,
+    #[structopt(long)]
+    /// Maximum output of each pane, in bytes per second: panes that write more are paused until
+    /// the next second (eg. to keep a runaway `cat` from taking over the session)
+    max_output_rate: Option<usize>,
+    #[structopt(long)]
+    /// Layout file to start the session with: a tree of splits, with the size of each part and
+    /// the name, cwd and command of each pane (see save-layout)
+    layout: Option<PathBuf>,
+    #[structopt(long, number_of_values = 1)]
+    /// Key that opens a pane running a command when pressed after ctrl-g, as KEY=COMMAND (eg.
+    /// t='cargo watch -x test'). can be given more than once
+    bind_command: Vec<CommandBinding>,
+    #[structopt(long, parse(try_from_str = ipc::parse_session_name))]
+    /// Name of the session to start, or to send commands to (defaults to $MOSAIC_SESSION)
+    session: Option<String>,
+    #[structopt(long, hidden = true)]
+    /// Run in the background as the server of the session given with --session
+    server: bool,
+    #[structopt(subcommand)]
+    command: Option<Command>,
 }
 
 fn _debug_log_to_file (message: String) {
@@ -53,234 +251,262 @@ fn _debug_log_to_file (message: String) {
     file.write_all("\n".as_bytes()).unwrap();
 }
 
+fn exit_with_error (message: impl Display) -> ! {
+    eprintln!("mosaic: {}", message);
+    process::exit(1);
+}
+
+fn send_api_command (session_name: Option<String>, api_command: &ApiCommand) {
+    let session_name = ipc::resolve_session_name(session_name).unwrap_or_else(|e| exit_with_error(e));
+    ipc::send_api_command(&session_name, api_command).unwrap_or_else(|e| exit_with_error(e));
+}
+
+fn parse_env_var (env_var: &str) -> Result<(String, String), String> {
+    match env_var.find('=') {
+        Some(index) => Ok((env_var[..index].to_string(), env_var[index + 1..].to_string())),
+        None => Err(format!("expected NAME=VALUE, got \"{}\"", env_var)),
+    }
+}
+
+fn run_command (session_name: Option<String>, split: Option<char>, floating: bool, cwd: Option<PathBuf>, env: Vec<(String, String)>, mut command: Vec<String>) {
+    let split_direction = match split {
+        None => None,
+        Some('h') => Some(SplitDirection::Horizontal),
+        Some('v') => Some(SplitDirection::Vertical),
+        Some(other) => exit_with_error(format!("unknown split direction '{}', expected h or v", other)),
+    };
+    // relative to where we are, rather than to where the server happens to be
+    let current_dir = std::env::current_dir().unwrap_or_else(|e| exit_with_error(e));
+    let run_command = RunCommand {
+        command: PathBuf::from(command.remove(0)),
+        args: command,
+        cwd: Some(cwd.map(|cwd| current_dir.join(cwd)).unwrap_or(current_dir)),
+        env,
+    };
+    let api_command = if floating {
+        ApiCommand::NewFloatingPane(Some(run_command))
+    } else {
+        ApiCommand::RunCommand(run_command, split_direction)
+    };
+    send_api_command(session_name, &api_command);
+}
+
+fn send_action (session_name: Option<String>, action: Action) {
+    let api_command = match action {
+        Action::MoveFocus { direction } => {
+            // like --move-focus, there is nothing to answer
+            send_api_command(session_name, &ApiCommand::MoveFocusTowards(direction));
+            return;
+        }
+        Action::FocusPreviousPane => {
+            send_api_command(session_name, &ApiCommand::FocusPreviousPane);
+            return;
+        }
+        Action::SwapPane { direction } => {
+            let api_command = match direction {
+                SwapTarget::Direction(direction) => ApiCommand::SwapPane(direction),
+                SwapTarget::Next => ApiCommand::SwapWithNextPane,
+            };
+            send_api_command(session_name, &api_command);
+            return;
+        }
+        Action::MovePane { position } => {
+            if position == 0 {
+                exit_with_error("positions start from 1");
+            }
+            send_api_command(session_name, &ApiCommand::MovePaneToPosition(position - 1));
+            return;
+        }
+        Action::ToggleZoom => {
+            send_api_command(session_name, &ApiCommand::ToggleZoom);
+            return;
+        }
+        Action::ToggleFloatingPanes => {
+            send_api_command(session_name, &ApiCommand::ToggleFloatingPanes);
+            return;
+        }
+        Action::NewFloatingPane => {
+            send_api_command(session_name, &ApiCommand::NewFloatingPane(None));
+            return;
+        }
+        Action::NewTab => {
+            send_api_command(session_name, &ApiCommand::NewTab);
+            return;
+        }
+        Action::CloseTab => {
+            send_api_command(session_name, &ApiCommand::CloseTab);
+            return;
+        }
+        Action::RenameTab { name } => {
+            send_api_command(session_name, &ApiCommand::RenameTab(name));
+            return;
+        }
+        Action::NextTab => {
+            send_api_command(session_name, &ApiCommand::NextTab);
+            return;
+        }
+        Action::PreviousTab => {
+            send_api_command(session_name, &ApiCommand::PreviousTab);
+            return;
+        }
+        Action::GoToTab { position } => {
+            if position == 0 {
+                exit_with_error("positions start from 1");
+            }
+            ApiCommand::GoToTab(position - 1)
+        }
+        Action::WriteChars { chars, pane } => ApiCommand::WriteChars(pane.pane_selector(), chars),
+        Action::SendKeys { keys, pane } => ApiCommand::SendKeys(pane.pane_selector(), keys),
+        Action::RenamePane { name, pane } => ApiCommand::RenamePane(pane.pane_selector(), name),
+    };
+    let session_name = ipc::resolve_session_name(session_name).unwrap_or_else(|e| exit_with_error(e));
+    let result: Result<(), String> = ipc::send_api_request(&session_name, &api_command)
+        .unwrap_or_else(|e| exit_with_error(e));
+    result.unwrap_or_else(|e| exit_with_error(e));
+}
+
+fn capture_pane (session_name: Option<String>, pane: Option<i32>, capture_options: CaptureOptions) {
+    let pane_selector = pane.map(PaneSelector::Id).unwrap_or(PaneSelector::Focused);
+    let session_name = ipc::resolve_session_name(session_name).unwrap_or_else(|e| exit_with_error(e));
+    let result: Result<String, String> = ipc::send_api_request(&session_name, &ApiCommand::CapturePane(pane_selector, capture_options))
+        .unwrap_or_else(|e| exit_with_error(e));
+    let captured = result.unwrap_or_else(|e| exit_with_error(e));
+    // the output is often piped into head or grep, which can stop reading early
+    let _ = writeln!(io::stdout(), "{}", captured);
+}
+
+fn list_panes (session_name: Option<String>, json: bool) {
+    let session_name = ipc::resolve_session_name(session_name).unwrap_or_else(|e| exit_with_error(e));
+    let session_state: SessionState = ipc::send_api_request(&session_name, &ApiCommand::GetState)
+        .unwrap_or_else(|e| exit_with_error(e));
+    if json {
+        let _ = writeln!(io::stdout(), "{}", serde_json::to_string_pretty(&session_state).unwrap());
+        return;
+    }
+    // once there is more than one tab, the panes are listed under the tab they're in
+    let with_tabs = session_state.tabs.len() > 1;
+    let mut listed_tab = None;
+    for pane in session_state.panes {
+        if with_tabs && listed_tab != Some(pane.tab) {
+            listed_tab = Some(pane.tab);
+            let tab = &session_state.tabs[pane.tab];
+            let _ = writeln!(
+                io::stdout(),
+                "tab {}{}{}:",
+                pane.tab + 1,
+                tab.name.as_ref().map(|name| format!(" \"{}\"", name)).unwrap_or_default(),
+                if tab.active { " (shown)" } else { "" },
+            );
+        }
+        let _ = writeln!(
+            io::stdout(),
+            "{}{}: {}x{} at {},{}{}{}{}{}",
+            if with_tabs { "  " } else { "" },
+            pane.id,
+            pane.cols,
+            pane.rows,
+            pane.x,
+            pane.y,
+            pane.name.map(|name| format!(" \"{}\"", name)).unwrap_or_default(),
+            pane.process.map(|process| format!(" ({})", process)).unwrap_or_default(),
+            if pane.floating { " (floating)" } else { "" },
+            if pane.focused { " (focused)" } else { "" },
+        );
+    }
+}
+
+fn subscribe (session_name: Option<String>) {
+    let session_name = ipc::resolve_session_name(session_name).unwrap_or_else(|e| exit_with_error(e));
+    let mut stream = ipc::send_api_command(&session_name, &ApiCommand::Subscribe).unwrap_or_else(|e| exit_with_error(e));
+    // this ends with the session, which closes the connection
+    while let Ok(event) = bincode::deserialize_from::<_, Event>(&mut stream) {
+        if writeln!(io::stdout(), "{}", serde_json::to_string(&event).unwrap()).is_err() {
+            break;
+        }
+    }
+}
+
+fn save_layout (session_name: Option<String>, path: PathBuf) {
+    let session_name = ipc::resolve_session_name(session_name).unwrap_or_else(|e| exit_with_error(e));
+    let layout: String = ipc::send_api_request(&session_name, &ApiCommand::GetLayout)
+        .unwrap_or_else(|e| exit_with_error(e));
+    fs::write(&path, layout).unwrap_or_else(|e| exit_with_error(format!("could not write {}: {}", path.display(), e)));
+}
+
+fn list_sessions () {
+    let sessions = ipc::list_sessions();
+    if sessions.is_empty() {
+        exit_with_error("no active mosaic sessions");
+    }
+    for session_name in sessions {
+        // a session can end between listing and querying it, in which case we just skip it
+        if let Ok(session_info) = ipc::send_api_request::<SessionInfo>(&session_name, &ApiCommand::GetSessionInfo) {
+            println!(
+                "{}: {} pane{} (created {} ago){}",
+                session_info.name,
+                session_info.pane_count,
+                if session_info.pane_count == 1 { "" } else { "s" },
+                session_info.created_ago(),
+                match session_info.attached_clients {
+                    0 => String::new(),
+                    1 => String::from(" (attached)"),
+                    attached_clients => format!(" ({} clients attached)", attached_clients),
+                }
+            );
+        }
+    }
+}
+
 pub fn main() {
     let os_input = get_os_input();
-    let opts = Opt::from_args();
-    if opts.split.is_some() {
+    let mut opts = Opt::from_args();
+    if let Some(command) = opts.command.take() {
+        match command {
+            Command::ListSessions => list_sessions(),
+            Command::Attach { session_name, read_only, ignore_size } => {
+                let attach_options = AttachOptions { read_only, ignore_size };
+                start_client(Box::new(os_input), &session_name, attach_options);
+            }
+            Command::KillSession { session_name } => send_api_command(Some(session_name), &ApiCommand::KillSession),
+            Command::Action(action) => send_action(opts.session, action),
+            Command::ListPanes { json } => list_panes(opts.session, json),
+            Command::Subscribe => subscribe(opts.session),
+            Command::Run { split, floating, cwd, env, command } => run_command(opts.session, split, floating, cwd, env, command),
+            Command::CapturePane { pane, ansi, history } => capture_pane(opts.session, pane, CaptureOptions { ansi, history }),
+            Command::SaveLayout { path } => save_layout(opts.session, path),
+        }
+    } else if opts.split.is_some() {
         match opts.split {
             Some('h') => {
-                let mut stream = UnixStream::connect("/tmp/mosaic").unwrap();
-                let api_command = bincode::serialize(&ApiCommand::SplitHorizontally).unwrap();
-                stream.write_all(&api_command).unwrap();
+                send_api_command(opts.session, &ApiCommand::SplitHorizontally);
             },
             Some('v') => {
-                let mut stream = UnixStream::connect("/tmp/mosaic").unwrap();
-                let api_command = bincode::serialize(&ApiCommand::SplitVertically).unwrap();
-                stream.write_all(&api_command).unwrap();
+                send_api_command(opts.session, &ApiCommand::SplitVertically);
             },
             _ => {}
         };
     } else if opts.move_focus {
-        let mut stream = UnixStream::connect("/tmp/mosaic").unwrap();
-        let api_command = bincode::serialize(&ApiCommand::MoveFocus).unwrap();
-        stream.write_all(&api_command).unwrap();
+        send_api_command(opts.session, &ApiCommand::MoveFocus);
     } else if opts.open_file.is_some() {
-        let mut stream = UnixStream::connect("/tmp/mosaic").unwrap();
         let file_to_open = opts.open_file.unwrap();
-        let api_command = bincode::serialize(&ApiCommand::OpenFile(file_to_open)).unwrap();
-        stream.write_all(&api_command).unwrap();
+        let session_name = ipc::resolve_session_name(opts.session).unwrap_or_else(|e| exit_with_error(e));
+        let result: Result<(), String> = ipc::send_api_request(&session_name, &ApiCommand::OpenFile(file_to_open))
+            .unwrap_or_else(|e| exit_with_error(e));
+        result.unwrap_or_else(|e| exit_with_error(e));
+    } else if opts.server {
+        start_server(Box::new(os_input), opts);
     } else {
         start(Box::new(os_input), opts);
     }
 }
 
-pub fn start(mut os_input: Box<dyn OsApi>, opts: Opt) {
-    let mut active_threads = vec![];
-
-    let full_screen_ws = os_input.get_terminal_size_using_fd(0);
-    os_input.into_raw_mode(0);
-    let (send_screen_instructions, receive_screen_instructions): (Sender<ScreenInstruction>, Receiver<ScreenInstruction>) = channel();
-    let (send_pty_instructions, receive_pty_instructions): (Sender<PtyInstruction>, Receiver<PtyInstruction>) = channel();
-    let mut screen = Screen::new(receive_screen_instructions, send_pty_instructions.clone(), &full_screen_ws, os_input.clone(), opts.max_panes);
-    let mut pty_bus = PtyBus::new(receive_pty_instructions, send_screen_instructions.clone(), os_input.clone());
-
-    active_threads.push(
-        thread::Builder::new()
-            .name("pty".to_string())
-            .spawn({
-                move || {
-                    pty_bus.spawn_terminal_vertically(None);
-                    loop {
-                        let event = pty_bus.receive_pty_instructions
-                            .recv()
-                            .expect("failed to receive event on channel");
-                        match event {
-                            PtyInstruction::SpawnTerminal(file_to_open) => {
-                                pty_bus.spawn_terminal(file_to_open);
-                            }
-                            PtyInstruction::SpawnTerminalVertically(file_to_open) => {
-                                pty_bus.spawn_terminal_vertically(file_to_open);
-                            }
-                            PtyInstruction::SpawnTerminalHorizontally(file_to_open) => {
-                                pty_bus.spawn_terminal_horizontally(file_to_open);
-                            }
-                            PtyInstruction::ClosePane(id) => {
-                                pty_bus.close_pane(id);
-                            }
-                            PtyInstruction::Quit => {
-                                break;
-                            }
-                        }
-                    }
-                }
-            }).unwrap()
-    );
-
-    active_threads.push(
-        thread::Builder::new()
-            .name("screen".to_string())
-            .spawn({
-                move || {
-                    loop {
-                        let event = screen.receiver
-                            .recv()
-                            .expect("failed to receive event on channel");
-                        match event {
-                            ScreenInstruction::Pty(pid, vte_event) => {
-                                screen.handle_pty_event(pid, vte_event);
-                            },
-                            ScreenInstruction::Render => {
-                                screen.render();
-                            },
-                            ScreenInstruction::NewPane(pid) => {
-                                screen.new_pane(pid);
-                            }
-                            ScreenInstruction::HorizontalSplit(pid) => {
-                                screen.horizontal_split(pid);
-                            }
-                            ScreenInstruction::VerticalSplit(pid) => {
-                                screen.vertical_split(pid);
-                            }
-                            ScreenInstruction::WriteCharacter(byte) => {
-                                screen.write_to_active_terminal(byte);
-                            }
-                            ScreenInstruction::ResizeLeft => {
-                                screen.resize_left();
-                            }
-                            ScreenInstruction::ResizeRight => {
-                                screen.resize_right();
-                            }
-                            ScreenInstruction::ResizeDown => {
-                                screen.resize_down();
-                            }
-                            ScreenInstruction::ResizeUp => {
-                                screen.resize_up();
-                            }
-                            ScreenInstruction::MoveFocus => {
-                                screen.move_focus();
-                            }
-                            ScreenInstruction::ScrollUp => {
-                                screen.scroll_active_terminal_up();
-                            }
-                            ScreenInstruction::ScrollDown => {
-                                screen.scroll_active_terminal_down();
-                            }
-                            ScreenInstruction::ClearScroll => {
-                                screen.clear_active_terminal_scroll();
-                            }
-                            ScreenInstruction::CloseFocusedPane => {
-                                screen.close_focused_pane();
-                            }
-                            ScreenInstruction::ClosePane(id) => {
-                                screen.close_pane(id);
-                            }
-                            ScreenInstruction::Quit => {
-                                break;
-                            }
-                        }
-                    }
-                }
-            }).unwrap()
-    );
-
-    // TODO: currently we don't push this into active_threads
-    // because otherwise the app will hang. Need to fix this so it both
-    // listens to the ipc-bus and is able to quit cleanly
-    #[cfg(not(test))]
-    let _ipc_thread = thread::Builder::new()
-        .name("ipc_server".to_string())
-        .spawn({
-            let send_pty_instructions = send_pty_instructions.clone();
-            let send_screen_instructions = send_screen_instructions.clone();
-            move || {
-                ::std::fs::remove_file("/tmp/mosaic").ok();
-                let listener = ::std::os::unix::net::UnixListener::bind("/tmp/mosaic").expect("could not listen on ipc socket");
-
-                for stream in listener.incoming() {
-                    match stream {
-                        Ok(mut stream) => {
-                            let mut buffer = [0; 65535]; // TODO: more accurate
-                            stream.read(&mut buffer).expect("failed to parse ipc message");
-                            let decoded: ApiCommand = bincode::deserialize(&buffer).expect("failed to deserialize ipc message");
-                            match &decoded {
-                                ApiCommand::OpenFile(file_name) => {
-                                    let path = PathBuf::from(file_name);
-                                    send_pty_instructions.send(PtyInstruction::SpawnTerminal(Some(path))).unwrap();
-                                }
-                                ApiCommand::SplitHorizontally => {
-                                    send_pty_instructions.send(PtyInstruction::SpawnTerminalHorizontally(None)).unwrap();
-                                }
-                                ApiCommand::SplitVertically => {
-                                    send_pty_instructions.send(PtyInstruction::SpawnTerminalVertically(None)).unwrap();
-                                }
-                                ApiCommand::MoveFocus => {
-                                    send_screen_instructions.send(ScreenInstruction::MoveFocus).unwrap();
-                                }
-                            }
-                        }
-                        Err(err) => {
-                            panic!("err {:?}", err);
-                        }
-                    }
-                }
-            }
-        }).unwrap();
-
-    let mut stdin = os_input.get_stdin_reader();
-    loop {
-		let mut buffer = [0; 1];
-        stdin.read(&mut buffer).expect("failed to read stdin");
-        if buffer[0] == 10 { // ctrl-j
-            send_screen_instructions.send(ScreenInstruction::ResizeDown).unwrap();
-        } else if buffer[0] == 11 { // ctrl-k
-            send_screen_instructions.send(ScreenInstruction::ResizeUp).unwrap();
-        } else if buffer[0] == 16 { // ctrl-p
-            send_screen_instructions.send(ScreenInstruction::MoveFocus).unwrap();
-        } else if buffer[0] == 8 { // ctrl-h
-            send_screen_instructions.send(ScreenInstruction::ResizeLeft).unwrap();
-        } else if buffer[0] == 12 { // ctrl-l
-            send_screen_instructions.send(ScreenInstruction::ResizeRight).unwrap();
-        } else if buffer[0] == 26 { // ctrl-z
-            send_pty_instructions.send(PtyInstruction::SpawnTerminal(None)).unwrap();
-        } else if buffer[0] == 14 { // ctrl-n
-            send_pty_instructions.send(PtyInstruction::SpawnTerminalVertically(None)).unwrap();
-        } else if buffer[0] == 2 { // ctrl-b
-            send_pty_instructions.send(PtyInstruction::SpawnTerminalHorizontally(None)).unwrap();
-        } else if buffer[0] == 17 { // ctrl-q
-            send_screen_instructions.send(ScreenInstruction::Quit).unwrap();
-            send_pty_instructions.send(PtyInstruction::Quit).unwrap();
-            break;
-        } else if buffer[0] == 27 { // ctrl-[
-            send_screen_instructions.send(ScreenInstruction::ScrollUp).unwrap();
-        } else if buffer[0] == 29 { // ctrl-]
-            send_screen_instructions.send(ScreenInstruction::ScrollDown).unwrap();
-        } else if buffer[0] == 24 { // ctrl-x
-            send_screen_instructions.send(ScreenInstruction::CloseFocusedPane).unwrap();
-        } else {
-            // println!("\r buffer {:?}   ", buffer[0]);
-            send_screen_instructions.send(ScreenInstruction::ClearScroll).unwrap();
-            send_screen_instructions.send(ScreenInstruction::WriteCharacter(buffer[0])).unwrap();
-        }
-    };
-    
-    for thread_handler in active_threads {
-        thread_handler.join().unwrap();
+pub fn start(mut os_input: Box<dyn ClientOsApi>, mut opts: Opt) {
+    let session_name = opts.session.get_or_insert_with(ipc::next_available_session_name).clone();
+    if let Some(layout) = opts.layout.as_mut() {
+        // the server could only tell its null stderr what's wrong with the file, so we check it here
+        *layout = std::env::current_dir().unwrap_or_else(|e| exit_with_error(e)).join(&layout);
+        LayoutFile::load(layout).unwrap_or_else(|e| exit_with_error(e));
     }
-    // cleanup();
-    let reset_style = "\u{1b}[m";
-    let goto_start_of_last_line = format!("\u{1b}[{};{}H", full_screen_ws.ws_row, 1);
-    let goodbye_message = format!("{}\n{}Bye from Mosaic!", goto_start_of_last_line, reset_style);
-
-    os_input.get_stdout_writer().write(goodbye_message.as_bytes()).unwrap();
-    os_input.get_stdout_writer().flush().unwrap();
+    os_input.spawn_server(opts).unwrap_or_else(|e| exit_with_error(e));
+    start_client(os_input, &session_name, AttachOptions::default());
 }
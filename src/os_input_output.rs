@@ -1,30 +1,64 @@
-use nix::unistd::{read, write, ForkResult, Pid};
+use nix::unistd::{close, fork, read, write, setsid, tcgetpgrp, ForkResult, Pid};
 use nix::fcntl::{fcntl, FcntlArg, OFlag};
+use nix::poll::{poll, PollFd, PollFlags};
 use nix::sys::termios::{
     tcgetattr,
     cfmakeraw,
     tcsetattr,
     SetArg,
     tcdrain,
+    Termios,
 };
-use nix::sys::wait::waitpid;
-use nix::sys::signal::{kill, Signal};
-use nix::pty::{forkpty, Winsize};
-use std::os::unix::io::RawFd;
-use std::process::{Command, Child};
-use std::io::{Read, Write};
+use nix::sys::wait::{waitpid, WaitPidFlag, WaitStatus};
+use nix::sys::signal::{killpg, Signal};
+use nix::pty::{openpty, Winsize};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::os::unix::net::UnixStream;
+use std::os::unix::process::CommandExt;
+use std::process::{Command, Stdio};
+use std::io::{self, Read, Write};
 use std::path::PathBuf;
+use std::ffi::{CString, OsStr, OsString};
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::os::unix::fs::PermissionsExt;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex, Once};
+use std::time::{Duration, Instant};
+use std::thread;
+use std::future::Future;
+use std::pin::Pin;
+use signal_hook::iterator::Signals;
+use smol::Async;
+use futures::channel::oneshot;
 
 use std::env;
 
-fn into_raw_mode(pid: RawFd) {
-    let mut tio = tcgetattr(pid).expect("could not get terminal attribute");
+use crate::Opt;
+use crate::ipc::{self, ClientConnections};
+use crate::pty_bus::TerminalAction;
+
+// the process group in the foreground of this pty, eg. the shell, or whatever it's running
+fn foreground_process_dir(fd: RawFd) -> Option<PathBuf> {
+    let process_group = tcgetpgrp(fd).ok()?;
+    Some(PathBuf::from(format!("/proc/{}", process_group)))
+}
+
+fn into_raw_mode(pid: RawFd) -> Termios {
+    let orig_termios = tcgetattr(pid).expect("could not get terminal attribute");
+    let mut tio = orig_termios.clone();
     cfmakeraw(&mut tio);
     match tcsetattr(pid, SetArg::TCSANOW, &mut tio) {
         Ok(_) => {},
         Err(e) => panic!("error {:?}", e)
     };
+    orig_termios
+}
 
+fn unset_raw_mode(pid: RawFd, orig_termios: &Termios) {
+    match tcsetattr(pid, SetArg::TCSANOW, orig_termios) {
+        Ok(_) => {},
+        Err(e) => panic!("error {:?}", e)
+    };
 }
 
 pub fn get_terminal_size_using_fd(fd: RawFd) -> Winsize {
@@ -65,116 +99,428 @@ fn _debug_log_to_file (message: String) {
     file.write_all("\n".as_bytes()).unwrap();
 }
 
-fn handle_command_exit(mut child: Child) {
-    let signals = ::signal_hook::iterator::Signals::new(&[::signal_hook::SIGINT]).unwrap();
-    'handle_exit: loop {
-        match child.try_wait() {
-            Ok(Some(_status)) => {
-                // TODO: handle errors?
-                break;
+// how long the process of a closed pane gets to exit after its SIGHUP, before it's killed
+const KILL_TIMEOUT: Duration = Duration::from_secs(2);
+
+// the exit codes of the panes' processes, sent to their panes as soon as they are reaped, rather
+// than once their pty closes: a job the process left in the background can keep it open for much
+// longer. the processes are reaped as soon as they exit so that none of them is left a zombie.
+// like SIGCHLD, there is one for the whole process: any reaper would reap the children of all
+struct ChildExits {
+    exited: Mutex<BTreeMap<RawFd, i32>>, // before their pane waited for them, right after spawning them
+    waiting: Mutex<BTreeMap<RawFd, oneshot::Sender<i32>>>,
+}
+
+static CHILD_EXITS: ChildExits = ChildExits {
+    exited: Mutex::new(BTreeMap::new()),
+    waiting: Mutex::new(BTreeMap::new()),
+};
+static REAPING_CHILDREN: Once = Once::new(); // started along with the first pane
+
+impl ChildExits {
+    fn reap_children(&self) {
+        // a single SIGCHLD can stand for several children that exited
+        loop {
+            let (pid, exit_code) = match waitpid(Pid::from_raw(-1), Some(WaitPidFlag::WNOHANG)) {
+                Ok(WaitStatus::Exited(pid, exit_code)) => (pid, exit_code),
+                // like shells do, a process killed by a signal exits with 128 + the signal
+                Ok(WaitStatus::Signaled(pid, signal, _)) => (pid, 128 + signal as i32),
+                Ok(WaitStatus::StillAlive) | Err(_) => break,
+                Ok(_) => continue,
+            };
+            // locked in the same order as in exit_code, so that the two can't wait on each other
+            let mut exited = self.exited.lock().unwrap();
+            match self.waiting.lock().unwrap().remove(&pid.as_raw()) {
+                Some(send_exit_code) => {
+                    send_exit_code.send(exit_code).ok();
+                }
+                None => {
+                    exited.insert(pid.as_raw(), exit_code);
+                }
             }
-            Ok(None) => {
-                ::std::thread::sleep(::std::time::Duration::from_millis(100));
+        }
+    }
+    fn exit_code(&self, pid: RawFd) -> oneshot::Receiver<i32> {
+        let (send_exit_code, receive_exit_code) = oneshot::channel();
+        let mut exited = self.exited.lock().unwrap();
+        match exited.remove(&pid) {
+            Some(exit_code) => {
+                send_exit_code.send(exit_code).ok();
+            }
+            None => {
+                self.waiting.lock().unwrap().insert(pid, send_exit_code);
             }
-            Err(e) => panic!("error attempting to wait: {}", e),
         }
+        receive_exit_code
+    }
+}
 
-        for signal in signals.pending() {
-            match signal {
-                signal_hook::SIGINT => {
-                    child.kill().unwrap();
-                    child.wait().unwrap();
-                    break 'handle_exit;
-                }
-                _ => {}
-            }
-        };
+fn reap_children() {
+    // registering for SIGCHLD before looking for children that already exited, so that none are missed
+    let signals = Signals::new([signal_hook::SIGCHLD]).unwrap();
+    CHILD_EXITS.reap_children();
+    for _signal in signals.forever() {
+        CHILD_EXITS.reap_children();
     }
 }
 
-fn spawn_terminal (file_to_open: Option<PathBuf>) -> (RawFd, RawFd) {
-    let (pid_primary, pid_secondary): (RawFd, RawFd) = {
-        match forkpty(None, None) {
-            Ok(fork_pty_res) => {
-                let pid_primary = fork_pty_res.master;
-                let pid_secondary = match fork_pty_res.fork_result {
-                    ForkResult::Parent { child } => {
-                        // fcntl(pid_primary, FcntlArg::F_SETFL(OFlag::empty())).expect("could not fcntl");
-                        fcntl(pid_primary, FcntlArg::F_SETFL(OFlag::O_NONBLOCK)).expect("could not fcntl");
-                        child
-                    },
-                    ForkResult::Child => {
-                        match file_to_open {
-                            Some(file_to_open) => {
-                                if env::var("EDITOR").is_err() && env::var("VISUAL").is_err() {
-                                    panic!("Can't edit files if an editor is not defined. To fix: define the EDITOR or VISUAL environment variables with the path to your editor (eg. /usr/bin/vim)");
-                                }
-                                let editor = env::var("EDITOR").unwrap_or_else(|_| env::var("VISUAL").unwrap());
-
-                                let child = Command::new(editor).args(&[file_to_open]).spawn().expect("failed to spawn");
-                                handle_command_exit(child);
-                                ::std::process::exit(0);
-                            },
-                            None => {
-                                let child = Command::new(env::var("SHELL").unwrap()).spawn().expect("failed to spawn");
-                                handle_command_exit(child);
-                                ::std::process::exit(0);
-                            }
-                        }
-                    },
-                };
-                (pid_primary, pid_secondary.as_raw())
+fn kill_if_still_running(pid: RawFd) {
+    let deadline = Instant::now() + KILL_TIMEOUT;
+    // the process group is gone once all of its processes exited and were reaped
+    while killpg(Pid::from_raw(pid), None).is_ok() {
+        if Instant::now() > deadline {
+            killpg(Pid::from_raw(pid), Signal::SIGKILL).ok();
+            return;
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+}
+
+// what a pane runs, all of it made before its process is forked: the server has other threads, one
+// of which could hold a lock (eg. the allocator's) that the forked process would then wait on
+// forever. so the forked process only makes the pty its terminal, changes its directory and execs
+struct PaneCommand {
+    program: CString, // already looked up in PATH, since execvp isn't safe to call after forking
+    // only read through the pointers to them below, which are null terminated
+    _argv: Vec<CString>,
+    _envp: Vec<CString>,
+    argv_pointers: Vec<*const libc::c_char>,
+    envp_pointers: Vec<*const libc::c_char>,
+    cwd: Option<CString>,
+    exec_failed: CString, // what is printed in the pane if it can't be run, before the errno
+}
+
+impl PaneCommand {
+    fn new(terminal_action: Option<TerminalAction>, pane_id: RawFd) -> io::Result<Self> {
+        let mut env: HashMap<OsString, OsString> = env::vars_os().collect();
+        let (program, args, cwd) = match terminal_action {
+            Some(TerminalAction::OpenFile(file_to_open)) => {
+                let editor = env.get(OsStr::new("EDITOR")).or_else(|| env.get(OsStr::new("VISUAL"))).cloned().ok_or_else(|| io::Error::new(
+                    io::ErrorKind::NotFound,
+                    "can't edit files if an editor is not defined. To fix: define the EDITOR or VISUAL environment variables with the path to your editor (eg. /usr/bin/vim)",
+                ))?;
+                (PathBuf::from(editor), vec![file_to_open.into_os_string()], None)
             }
-            Err(e) => {
-                panic!("failed to fork {:?}", e);
+            Some(TerminalAction::RunCommand(run_command)) => {
+                env.extend(run_command.env.into_iter().map(|(key, value)| (key.into(), value.into())));
+                (run_command.command, run_command.args.into_iter().map(OsString::from).collect(), run_command.cwd)
             }
+            Some(TerminalAction::Shell(cwd)) => (shell(&env)?, vec![], Some(cwd)),
+            None => (shell(&env)?, vec![], None),
+        };
+        env.insert(ipc::PANE_ID_ENV_KEY.into(), pane_id.to_string().into());
+        let exec_failed = format!("mosaic: failed to run {:?}, errno ", program);
+        let program = find_in_path(program, env.get(OsStr::new("PATH")));
+        let argv: Vec<CString> = ::std::iter::once(program.clone().into_os_string()).chain(args).map(to_c_string).collect::<io::Result<_>>()?;
+        let envp: Vec<CString> = env.into_iter().map(|(key, value)| {
+            let mut entry = key;
+            entry.push("=");
+            entry.push(value);
+            to_c_string(entry)
+        }).collect::<io::Result<_>>()?;
+        Ok(PaneCommand {
+            program: to_c_string(program.into_os_string())?,
+            argv_pointers: null_terminated_pointers(&argv),
+            envp_pointers: null_terminated_pointers(&envp),
+            _argv: argv,
+            _envp: envp,
+            cwd: cwd.map(|cwd| to_c_string(cwd.into_os_string())).transpose()?,
+            exec_failed: to_c_string(exec_failed.into())?,
+        })
+    }
+    // in the forked process: only calls that are safe to make there, and never returns
+    unsafe fn exec(&self, pty_secondary: RawFd) -> ! {
+        // like forkpty does, the pty becomes the terminal of a new session the process leads
+        libc::setsid();
+        libc::ioctl(pty_secondary, libc::TIOCSCTTY, 0);
+        for fd in 0..3 {
+            libc::dup2(pty_secondary, fd);
+        }
+        if pty_secondary > 2 {
+            libc::close(pty_secondary);
+        }
+        if let Some(cwd) = &self.cwd {
+            if libc::chdir(cwd.as_ptr()) == -1 {
+                self.exit_with_errno();
+            }
+        }
+        libc::execve(self.program.as_ptr(), self.argv_pointers.as_ptr(), self.envp_pointers.as_ptr());
+        self.exit_with_errno();
+    }
+    unsafe fn exit_with_errno(&self) -> ! {
+        // this is printed in the pane
+        let errno = *libc::__errno_location();
+        let message = self.exec_failed.as_bytes();
+        libc::write(2, message.as_ptr() as *const libc::c_void, message.len());
+        let mut digits = [b'\n'; 12];
+        let mut start = digits.len() - 1;
+        let mut rest = errno.max(0);
+        loop {
+            start -= 1;
+            digits[start] = b'0' + (rest % 10) as u8;
+            rest /= 10;
+            if rest == 0 {
+                break;
+            }
+        }
+        libc::write(2, digits[start..].as_ptr() as *const libc::c_void, digits.len() - start);
+        libc::_exit(127); // like shells do when they can't run a command
+    }
+}
+
+fn shell(env: &HashMap<OsString, OsString>) -> io::Result<PathBuf> {
+    env.get(OsStr::new("SHELL")).map(PathBuf::from).ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "SHELL is not set"))
+}
+
+fn find_in_path(program: PathBuf, path: Option<&OsString>) -> PathBuf {
+    if program.as_os_str().as_bytes().contains(&b'/') {
+        return program;
+    }
+    let is_executable = |candidate: &PathBuf| {
+        candidate.metadata().map(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0).unwrap_or(false)
+    };
+    path.and_then(|path| env::split_paths(path).map(|dir| dir.join(&program)).find(is_executable))
+        .unwrap_or(program) // execve fails with ENOENT, which is printed in the pane
+}
+
+fn to_c_string(string: OsString) -> io::Result<CString> {
+    CString::new(string.into_vec()).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+}
+
+fn null_terminated_pointers(strings: &[CString]) -> Vec<*const libc::c_char> {
+    strings.iter().map(|string| string.as_ptr()).chain(::std::iter::once(::std::ptr::null())).collect()
+}
+
+fn spawn_terminal (terminal_action: Option<TerminalAction>) -> io::Result<(RawFd, RawFd)> {
+    let pty = openpty(None, None).map_err(|e| io::Error::other(format!("could not open a pty: {}", e)))?;
+    let pane_command = match PaneCommand::new(terminal_action, pty.master) {
+        Ok(pane_command) => pane_command,
+        Err(e) => {
+            close(pty.master).ok();
+            close(pty.slave).ok();
+            return Err(e);
         }
     };
-    (pid_primary, pid_secondary)
+    match fork() {
+        Ok(ForkResult::Child) => unsafe {
+            libc::close(pty.master);
+            pane_command.exec(pty.slave)
+        },
+        Ok(ForkResult::Parent { child }) => {
+            close(pty.slave).ok();
+            fcntl(pty.master, FcntlArg::F_SETFL(OFlag::O_NONBLOCK)).expect("could not fcntl");
+            Ok((pty.master, child.as_raw()))
+        }
+        Err(e) => {
+            close(pty.master).ok();
+            close(pty.slave).ok();
+            Err(io::Error::other(format!("failed to fork: {}", e)))
+        }
+    }
+}
+
+fn spawn_server(opts: Opt) -> io::Result<()> {
+    let session_name = opts.session.expect("session name was not set before spawning its server");
+    if ipc::session_exists(&session_name) {
+        return Err(io::Error::new(
+            io::ErrorKind::AddrInUse,
+            format!("session \"{}\" already exists", session_name)
+        ));
+    }
+    let mut command = Command::new(env::current_exe()?);
+    command.args(["--server", "--session", &session_name]);
+    if let Some(max_panes) = opts.max_panes {
+        command.args(["--max-panes", &max_panes.to_string()]);
+    }
+    if let Some(min_pane_cols) = opts.min_pane_cols {
+        command.arg("--min-pane-cols").arg(min_pane_cols.to_string());
+    }
+    if let Some(min_pane_rows) = opts.min_pane_rows {
+        command.arg("--min-pane-rows").arg(min_pane_rows.to_string());
+    }
+    if let Some(max_output_rate) = opts.max_output_rate {
+        command.arg("--max-output-rate").arg(max_output_rate.to_string());
+    }
+    if let Some(layout) = opts.layout {
+        command.arg("--layout").arg(layout);
+    }
+    for command_binding in opts.bind_command {
+        command.arg("--bind-command").arg(command_binding.to_string());
+    }
+    command
+        .env(ipc::SESSION_NAME_ENV_KEY, &session_name)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+    unsafe {
+        // the server gets its own session so that it doesn't receive a SIGHUP when the terminal
+        // that started it is closed
+        command.pre_exec(|| {
+            setsid().map_err(|_| io::Error::last_os_error())?;
+            Ok(())
+        });
+    }
+    let mut server = command.spawn()?;
+    loop {
+        if ipc::session_exists(&session_name) {
+            return Ok(());
+        }
+        if let Some(exit_status) = server.try_wait()? {
+            return Err(io::Error::other(
+                format!("server of session \"{}\" exited before it could be attached to ({})", session_name, exit_status)
+            ));
+        }
+        ::std::thread::sleep(::std::time::Duration::from_millis(10));
+    }
+}
+
+// waits for a pane's pty to have something to read, so that its idle panes don't need polling
+pub trait PtyReadiness: Send {
+    fn readable(&self) -> Pin<Box<dyn Future<Output = ()> + Send + '_>>;
+}
+
+// the pty's fd as is, it's not closed along with this
+struct PtyFd(RawFd);
+
+impl AsRawFd for PtyFd {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0
+    }
+}
+
+impl PtyReadiness for Async<PtyFd> {
+    fn readable(&self) -> Pin<Box<dyn Future<Output = ()> + Send + '_>> {
+        // on errors, the next read of the pty fails too and ends its pane's stream
+        Box::pin(async move { Async::readable(self).await.ok(); })
+    }
 }
 
 #[derive(Clone)]
-pub struct OsInputOutput {}
+pub struct OsInputOutput {
+    orig_termios: Arc<Mutex<Option<Termios>>>,
+}
 
-pub trait OsApi: Send + Sync {
-    fn get_terminal_size_using_fd(&self, pid: RawFd) -> Winsize;
+/*
+ * the server owns the panes and the screen, the client only owns the user's terminal:
+ * it forwards the user's input to the server and draws whatever the server renders
+ */
+
+pub trait ServerOsApi: Send + Sync {
     fn set_terminal_size_using_fd(&mut self, pid: RawFd, cols: u16, rows: u16);
-    fn into_raw_mode(&mut self, pid: RawFd);
-    fn spawn_terminal(&mut self, file_to_open: Option<PathBuf>) -> (RawFd, RawFd);
+    fn spawn_terminal(&mut self, terminal_action: Option<TerminalAction>) -> io::Result<(RawFd, RawFd)>;
     fn read_from_tty_stdout(&mut self, pid: RawFd, buf: &mut [u8]) -> Result<usize, nix::Error>;
+    fn tty_readiness(&self, pid: RawFd) -> Box<dyn PtyReadiness>;
     fn write_to_tty_stdin(&mut self, pid: RawFd, buf: &mut [u8]) -> Result<usize, nix::Error>;
+    // for a pane whose pty is full of what its process didn't read, returns after the timeout if it still is
+    fn wait_until_tty_writable(&mut self, pid: RawFd, timeout: Duration);
     fn tcdrain(&mut self, pid: RawFd) -> Result<(), nix::Error>;
     fn kill(&mut self, pid: RawFd) -> Result<(), nix::Error>;
+    // sent once the process exited and was reaped, never for the ones that keep running
+    fn exit_code(&mut self, pid: RawFd) -> oneshot::Receiver<i32>;
+    fn close_tty(&mut self, pid: RawFd);
+    fn get_foreground_process_name(&self, pid: RawFd) -> Option<String>;
+    fn get_foreground_process_cwd(&self, pid: RawFd) -> Option<PathBuf>;
+    fn bind_session_socket(&mut self, session_name: &str) -> io::Result<ClientConnections>;
+    fn remove_session_socket(&mut self, session_name: &str);
+    fn box_clone(&self) -> Box<dyn ServerOsApi>;
+}
+
+pub trait ClientOsApi: Send + Sync {
+    fn get_terminal_size_using_fd(&self, pid: RawFd) -> Winsize;
+    fn into_raw_mode(&mut self, pid: RawFd);
+    fn unset_raw_mode(&mut self, pid: RawFd);
     fn get_stdin_reader(&self) -> Box<dyn Read>;
     fn get_stdout_writer(&self) -> Box<dyn Write>;
-    fn box_clone(&self) -> Box<dyn OsApi>;
+    fn spawn_server(&mut self, opts: Opt) -> io::Result<()>;
+    fn connect_to_server(&mut self, session_name: &str) -> io::Result<UnixStream>;
+    // calls on_resize whenever the terminal is resized, never returns
+    fn receive_sigwinch(&self, on_resize: Box<dyn Fn() + Send>);
+    fn box_clone(&self) -> Box<dyn ClientOsApi>;
 }
 
-impl OsApi for OsInputOutput {
-    fn get_terminal_size_using_fd(&self, pid: RawFd) -> Winsize {
-        get_terminal_size_using_fd(pid)
-    }
+impl ServerOsApi for OsInputOutput {
     fn set_terminal_size_using_fd(&mut self, pid: RawFd, cols: u16, rows: u16) {
         set_terminal_size_using_fd(pid, cols, rows);
     }
-    fn into_raw_mode(&mut self, pid: RawFd) {
-        into_raw_mode(pid);
-    }
-    fn spawn_terminal(&mut self, file_to_open: Option<PathBuf>) -> (RawFd, RawFd) {
-        spawn_terminal(file_to_open)
+    fn spawn_terminal(&mut self, terminal_action: Option<TerminalAction>) -> io::Result<(RawFd, RawFd)> {
+        REAPING_CHILDREN.call_once(|| {
+            thread::Builder::new()
+                .name("reap_children".to_string())
+                .spawn(reap_children)
+                .unwrap();
+        });
+        spawn_terminal(terminal_action)
     }
     fn read_from_tty_stdout(&mut self, pid: RawFd, buf: &mut [u8]) -> Result<usize, nix::Error> {
         read(pid, buf)
     }
+    fn tty_readiness(&self, pid: RawFd) -> Box<dyn PtyReadiness> {
+        // this registers the pty with the reactor that also runs async-std's own io
+        Box::new(Async::new(PtyFd(pid)).expect("could not register pty with the reactor"))
+    }
     fn write_to_tty_stdin(&mut self, pid: RawFd, buf: &mut [u8]) -> Result<usize, nix::Error> {
         write(pid, buf)
     }
+    fn wait_until_tty_writable(&mut self, pid: RawFd, timeout: Duration) {
+        let mut poll_fds = [PollFd::new(pid, PollFlags::POLLOUT)];
+        poll(&mut poll_fds, timeout.as_millis() as i32).ok();
+    }
     fn tcdrain(&mut self, pid: RawFd) -> Result<(), nix::Error> {
         tcdrain(pid)
     }
-    fn box_clone(&self) -> Box<dyn OsApi> {
+    fn kill(&mut self, pid: RawFd) -> Result<(), nix::Error> {
+        // the pane's process leads its own session and process group (see forkpty), which get
+        // a SIGHUP just like when the terminal they run in is closed
+        match killpg(Pid::from_raw(pid), Signal::SIGHUP) {
+            Err(nix::Error::Sys(nix::errno::Errno::ESRCH)) => return Ok(()), // it already exited
+            result => result?,
+        }
+        thread::Builder::new()
+            .name("kill_pane_process".to_string())
+            .spawn(move || kill_if_still_running(pid))
+            .unwrap();
+        Ok(())
+    }
+    fn exit_code(&mut self, pid: RawFd) -> oneshot::Receiver<i32> {
+        CHILD_EXITS.exit_code(pid)
+    }
+    fn close_tty(&mut self, pid: RawFd) {
+        close(pid).ok();
+    }
+    fn get_foreground_process_name(&self, fd: RawFd) -> Option<String> {
+        let comm = ::std::fs::read_to_string(foreground_process_dir(fd)?.join("comm")).ok()?;
+        Some(comm.trim_end().to_string())
+    }
+    fn get_foreground_process_cwd(&self, fd: RawFd) -> Option<PathBuf> {
+        ::std::fs::read_link(foreground_process_dir(fd)?.join("cwd")).ok()
+    }
+    fn bind_session_socket(&mut self, session_name: &str) -> io::Result<ClientConnections> {
+        let listener = ipc::bind_session_socket(session_name)?;
+        Ok(Box::new(::std::iter::from_fn(move || {
+            listener.accept().ok().map(|(stream, _address)| stream)
+        })))
+    }
+    fn remove_session_socket(&mut self, session_name: &str) {
+        ipc::remove_session_socket(session_name);
+    }
+    fn box_clone(&self) -> Box<dyn ServerOsApi> {
         Box::new((*self).clone())
     }
+}
+
+impl ClientOsApi for OsInputOutput {
+    fn get_terminal_size_using_fd(&self, pid: RawFd) -> Winsize {
+        get_terminal_size_using_fd(pid)
+    }
+    fn into_raw_mode(&mut self, pid: RawFd) {
+        let orig_termios = into_raw_mode(pid);
+        *self.orig_termios.lock().unwrap() = Some(orig_termios);
+    }
+    fn unset_raw_mode(&mut self, pid: RawFd) {
+        if let Some(orig_termios) = self.orig_termios.lock().unwrap().as_ref() {
+            unset_raw_mode(pid, orig_termios);
+        }
+    }
     fn get_stdin_reader(&self) -> Box<dyn Read> {
         // TODO: stdin lock, right now it's not done because we don't have where to put it
         // if we put it on the struct, we won't be able to clone the struct
@@ -186,20 +532,39 @@ impl OsApi for OsInputOutput {
         let stdout = ::std::io::stdout();
         Box::new(stdout)
     }
-    fn kill(&mut self, fd: RawFd) -> Result<(), nix::Error> {
-        kill(Pid::from_raw(fd), Some(Signal::SIGINT)).unwrap();
-        waitpid(Pid::from_raw(fd), None).unwrap();
-        Ok(())
+    fn spawn_server(&mut self, opts: Opt) -> io::Result<()> {
+        spawn_server(opts)
+    }
+    fn connect_to_server(&mut self, session_name: &str) -> io::Result<UnixStream> {
+        ipc::connect_to_session(session_name)
+    }
+    fn receive_sigwinch(&self, on_resize: Box<dyn Fn() + Send>) {
+        let signals = Signals::new([signal_hook::SIGWINCH]).unwrap();
+        for _ in signals.forever() {
+            on_resize();
+        }
+    }
+    fn box_clone(&self) -> Box<dyn ClientOsApi> {
+        Box::new((*self).clone())
     }
 }
 
-impl Clone for Box<dyn OsApi>
+impl Clone for Box<dyn ServerOsApi>
 {
-    fn clone(&self) -> Box<dyn OsApi> {
+    fn clone(&self) -> Box<dyn ServerOsApi> {
+        self.box_clone()
+    }
+}
+
+impl Clone for Box<dyn ClientOsApi>
+{
+    fn clone(&self) -> Box<dyn ClientOsApi> {
         self.box_clone()
     }
 }
 
 pub fn get_os_input () -> OsInputOutput {
-    OsInputOutput {}
+    OsInputOutput {
+        orig_termios: Arc::new(Mutex::new(None)),
+    }
 }
@@ -0,0 +1,111 @@
+use ::std::fmt::Write;
+
+use crate::terminal_pane::{AnsiCode, CharacterStyles, TerminalCharacter, EMPTY_TERMINAL_CHARACTER};
+
+/*
+ * OutputGrid
+ *
+ * the whole screen as cells, composed from the panes and the boundaries between them
+ * it keeps a copy of the frame it last rendered, so that rendering only outputs the cells that
+ * changed since then rather than redrawing everything
+ *
+ */
+
+// moving the cursor takes at least this many bytes, so when the next changed cell is only a few
+// cells away on the same line, we print the unchanged ones in between instead
+const MAX_CELLS_TO_PRINT_INSTEAD_OF_MOVING: usize = 4;
+
+pub struct OutputGrid {
+    pub columns: usize,
+    pub rows: usize,
+    cells: Vec<TerminalCharacter>,
+    rendered_cells: Option<Vec<TerminalCharacter>>, // None when we don't know what the clients show
+}
+
+impl OutputGrid {
+    pub fn new (columns: usize, rows: usize) -> Self {
+        OutputGrid {
+            columns,
+            rows,
+            cells: vec![EMPTY_TERMINAL_CHARACTER; columns * rows],
+            rendered_cells: None,
+        }
+    }
+    pub fn clear (&mut self) {
+        for cell in self.cells.iter_mut() {
+            *cell = EMPTY_TERMINAL_CHARACTER;
+        }
+    }
+    pub fn set_cell (&mut self, x: usize, y: usize, terminal_character: TerminalCharacter) {
+        if x < self.columns && y < self.rows {
+            // styles that were never set and styles that were reset look the same, so they should
+            // also compare the same
+            let styles = with_unset_styles_reset(terminal_character.styles);
+            self.cells[y * self.columns + x] = TerminalCharacter { character: terminal_character.character, styles };
+        }
+    }
+    pub fn forget_rendered_frame (&mut self) {
+        // the next render will clear the screen and draw everything
+        self.rendered_cells = None;
+    }
+    pub fn render_changes (&mut self) -> String {
+        let mut output = String::new();
+        let rendered_cells = match self.rendered_cells.take() {
+            Some(rendered_cells) => rendered_cells,
+            None => {
+                output.push_str("\u{1b}[m\u{1b}[2J");
+                vec![EMPTY_TERMINAL_CHARACTER; self.cells.len()]
+            }
+        };
+        // we don't know which styles the clients' terminals were left with, so we start over
+        output.push_str("\u{1b}[m");
+        let mut current_styles = EMPTY_TERMINAL_CHARACTER.styles;
+        let mut cursor_position: Option<(usize, usize)> = None; // (x, y), where the next character we print will go
+        for y in 0..self.rows {
+            for x in 0..self.columns {
+                let index = y * self.columns + x;
+                if self.cells[index] == rendered_cells[index] {
+                    continue;
+                }
+                match cursor_position {
+                    Some((cursor_x, cursor_y)) if cursor_y == y && cursor_x <= x && x - cursor_x <= MAX_CELLS_TO_PRINT_INSTEAD_OF_MOVING => {
+                        for index_in_between in (index - (x - cursor_x))..index {
+                            print_cell(&mut output, &mut current_styles, &self.cells[index_in_between]);
+                        }
+                    }
+                    _ => {
+                        write!(output, "\u{1b}[{};{}H", y + 1, x + 1).unwrap(); // goto row/col
+                    }
+                }
+                print_cell(&mut output, &mut current_styles, &self.cells[index]);
+                cursor_position = Some((x + 1, y));
+            }
+        }
+        self.rendered_cells = Some(self.cells.clone());
+        output
+    }
+}
+
+fn print_cell (output: &mut String, current_styles: &mut CharacterStyles, terminal_character: &TerminalCharacter) {
+    if let Some(new_styles) = current_styles.update_and_return_diff(&terminal_character.styles) {
+        write!(output, "{}", new_styles).unwrap();
+    }
+    output.push(terminal_character.character);
+}
+
+fn with_unset_styles_reset (styles: CharacterStyles) -> CharacterStyles {
+    let reset = Some(AnsiCode::Reset);
+    CharacterStyles {
+        foreground: styles.foreground.or(reset),
+        background: styles.background.or(reset),
+        strike: styles.strike.or(reset),
+        hidden: styles.hidden.or(reset),
+        reverse: styles.reverse.or(reset),
+        slow_blink: styles.slow_blink.or(reset),
+        fast_blink: styles.fast_blink.or(reset),
+        underline: styles.underline.or(reset),
+        bold: styles.bold.or(reset),
+        dim: styles.dim.or(reset),
+        italic: styles.italic.or(reset),
+    }
+}
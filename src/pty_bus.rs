@@ -3,22 +3,27 @@ use ::async_std::stream::*;
 use ::async_std::task;
 use ::async_std::task::*;
 use ::std::pin::*;
-use ::std::sync::mpsc::{Sender, Receiver};
+use ::std::sync::mpsc::{Receiver, SendError, Sender};
 use ::std::time::{Instant, Duration};
 use ::std::collections::HashMap;
+use ::std::sync::Arc;
+use ::std::io;
 use std::path::PathBuf;
-use ::vte;
+use ::serde::{Serialize, Deserialize};
+use ::futures::channel::oneshot;
+use ::futures::future::{select, Either};
 
-use crate::os_input_output::OsApi;
-use crate::ScreenInstruction;
+use crate::os_input_output::ServerOsApi;
+use crate::layout_file::LayoutFile;
+use crate::screen::ScreenInstruction;
 
 pub struct ReadFromPid {
     pid: RawFd,
-    os_input: Box<dyn OsApi>,
+    os_input: Box<dyn ServerOsApi>,
 }
 
 impl ReadFromPid {
-    pub fn new(pid: &RawFd, os_input: Box<dyn OsApi>) -> ReadFromPid {
+    pub fn new(pid: &RawFd, os_input: Box<dyn ServerOsApi>) -> ReadFromPid {
         ReadFromPid {
             pid: *pid,
             os_input,
@@ -46,7 +51,7 @@ impl Stream for ReadFromPid {
                     // indicates end of file
                     return Poll::Ready(None);
                 } else {
-                    let res = Some(read_buffer[..=*res].to_vec());
+                    let res = Some(read_buffer[..*res].to_vec());
                     return Poll::Ready(res);
                 }
             },
@@ -54,7 +59,8 @@ impl Stream for ReadFromPid {
                 match e {
                     nix::Error::Sys(errno) => {
                         if *errno == nix::errno::Errno::EAGAIN {
-                            return Poll::Ready(Some(vec![])) // TODO: better with timeout waker somehow
+                            // nothing to read right now, the stream waits for the pty to be readable
+                            return Poll::Ready(Some(vec![]))
                         } else {
                             Poll::Ready(None)
                         }
@@ -66,95 +72,128 @@ impl Stream for ReadFromPid {
     }
 }
 
-#[derive(Debug)]
-pub enum VteEvent { // TODO: try not to allocate Vecs
-    Print(char),
-    Execute(u8), // byte
-    Hook(Vec<i64>, Vec<u8>, bool, char), // params, intermediates, ignore, char
-    Put(u8), // byte
-    Unhook,
-    OscDispatch(Vec<Vec<u8>>, bool), // params, bell_terminated
-    CsiDispatch(Vec<i64>, Vec<u8>, bool, char), // params, intermediates, ignore, char
-    EscDispatch(Vec<u8>, bool, u8), // intermediates, ignore, byte
+// what a new pane runs, rather than the user's $SHELL
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum TerminalAction {
+    OpenFile(PathBuf), // in $EDITOR
+    RunCommand(RunCommand),
+    Shell(PathBuf), // the user's $SHELL, started in this directory
 }
 
-struct VteEventSender {
-    id: RawFd,
-    sender: Sender<ScreenInstruction>,
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct RunCommand {
+    pub command: PathBuf,
+    pub args: Vec<String>,
+    pub cwd: Option<PathBuf>, // defaults to the cwd of the server
+    pub env: Vec<(String, String)>, // on top of the server's environment
 }
 
-impl VteEventSender {
-    pub fn new (id: RawFd, sender: Sender<ScreenInstruction>) -> Self {
-        VteEventSender { id, sender }
-    }
+pub enum PtyInstruction {
+    SpawnTerminal(Option<TerminalAction>),
+    SpawnTerminalVertically(Option<TerminalAction>),
+    SpawnTerminalHorizontally(Option<TerminalAction>),
+    SpawnTerminalInNewTab(Option<TerminalAction>),
+    SpawnFloatingTerminal(Option<TerminalAction>),
+    OpenFile(PathBuf, Sender<Result<(), String>>), // in a new pane, whether it could be
+    RerunCommand(RawFd), // in place of the pane it exited in
+    ClosePane(RawFd),
+    ProcessExited(RawFd), // and was reaped, so there is nothing left to kill
+    GetRunCommands(Sender<HashMap<RawFd, RunCommand>>), // of the panes running a command
+    Quit
 }
 
-impl vte::Perform for VteEventSender {
-    fn print(&mut self, c: char) {
-        self.sender.send(
-            ScreenInstruction::Pty(self.id, VteEvent::Print(c))
-        ).unwrap();
-    }
-    fn execute(&mut self, byte: u8) {
-        self.sender.send(ScreenInstruction::Pty(self.id, VteEvent::Execute(byte))).unwrap();
-    }
-
-    fn hook(&mut self, params: &[i64], intermediates: &[u8], ignore: bool, c: char) {
-        let params = params.iter().copied().collect();
-        let intermediates = intermediates.iter().copied().collect();
-        let instruction = ScreenInstruction::Pty(self.id, VteEvent::Hook(params, intermediates, ignore, c));
-        self.sender.send(instruction).unwrap();
-    }
-
-    fn put(&mut self, byte: u8) {
-        self.sender.send(ScreenInstruction::Pty(self.id, VteEvent::Put(byte))).unwrap();
-    }
+pub struct PtyBus {
+    pub send_screen_instructions: Sender<ScreenInstruction>,
+    pub receive_pty_instructions: Receiver<PtyInstruction>,
+    pub id_to_child_pid: HashMap<RawFd, RawFd>,
+    id_to_run_command: HashMap<RawFd, RunCommand>, // so that they can be re-run
+    id_to_tty: HashMap<RawFd, Arc<PaneTty>>, // of the panes that are open
+    os_input: Box<dyn ServerOsApi>,
+    output_rate: Option<OutputRate>, // of each pane
+}
 
-    fn unhook(&mut self) {
-        self.sender.send(ScreenInstruction::Pty(self.id, VteEvent::Unhook)).unwrap();
-    }
+// how much of its output a pane is allowed each second, the rest of it waits in its pty
+#[derive(Debug, Clone, Copy)]
+pub struct OutputRate {
+    pub max_bytes_per_second: usize,
+}
 
-    fn osc_dispatch(&mut self, params: &[&[u8]], bell_terminated: bool) {
-        let params = params.iter().map(|p| p.to_vec()).collect();
-        let instruction = ScreenInstruction::Pty(self.id, VteEvent::OscDispatch(params, bell_terminated));
-        self.sender.send(instruction).unwrap();
-    }
+struct OutputRateLimit {
+    output_rate: OutputRate,
+    window_start: Instant,
+    bytes_in_window: usize,
+}
 
-    fn csi_dispatch(&mut self, params: &[i64], intermediates: &[u8], ignore: bool, c: char) {
-        let params = params.iter().copied().collect();
-        let intermediates = intermediates.iter().copied().collect();
-        let instruction = ScreenInstruction::Pty(self.id, VteEvent::CsiDispatch(params, intermediates, ignore, c));
-        self.sender.send(instruction).unwrap();
+impl OutputRateLimit {
+    fn new(output_rate: OutputRate) -> Self {
+        OutputRateLimit {
+            output_rate,
+            window_start: Instant::now(),
+            bytes_in_window: 0,
+        }
     }
-
-    fn esc_dispatch(&mut self, intermediates: &[u8], ignore: bool, byte: u8) {
-        let intermediates = intermediates.iter().copied().collect();
-        let instruction = ScreenInstruction::Pty(self.id, VteEvent::EscDispatch(intermediates, ignore, byte));
-        self.sender.send(instruction).unwrap();
+    // how long to stop reading from the pty after reading this many bytes from it
+    fn pause_after(&mut self, byte_count: usize) -> Option<Duration> {
+        let window = Duration::from_secs(1);
+        if self.window_start.elapsed() > window {
+            self.window_start = Instant::now();
+            self.bytes_in_window = 0;
+        }
+        self.bytes_in_window += byte_count;
+        if self.bytes_in_window > self.output_rate.max_bytes_per_second {
+            // the next window starts once the pause is over
+            let pause = window.checked_sub(self.window_start.elapsed());
+            self.window_start = Instant::now() + pause.unwrap_or_default();
+            self.bytes_in_window = 0;
+            pause
+        } else {
+            None
+        }
     }
 }
 
-pub enum PtyInstruction {
-    SpawnTerminal(Option<PathBuf>),
-    SpawnTerminalVertically(Option<PathBuf>),
-    SpawnTerminalHorizontally(Option<PathBuf>),
-    ClosePane(RawFd),
-    Quit
+// a pane's pty, closed once neither its pane nor the task reading from it need it anymore. until
+// then its fd can't be reused by another pane's pty, which would then be read or written instead
+pub struct PaneTty {
+    pid: RawFd,
+    os_input: Box<dyn ServerOsApi>,
 }
 
-pub struct PtyBus {
-    pub send_screen_instructions: Sender<ScreenInstruction>,
-    pub receive_pty_instructions: Receiver<PtyInstruction>,
-    pub id_to_child_pid: HashMap<RawFd, RawFd>,
-    os_input: Box<dyn OsApi>,
+impl Drop for PaneTty {
+    fn drop(&mut self) {
+        self.os_input.close_tty(self.pid);
+    }
 }
 
-fn stream_terminal_bytes(pid: RawFd, send_screen_instructions: Sender<ScreenInstruction>, os_input: Box<dyn OsApi>) {
+// how long what is left in the pty is read after its process exited, which a job the process left
+// in the background could otherwise keep writing to forever
+const DRAIN_AFTER_EXIT: Duration = Duration::from_millis(100);
+
+pub fn stream_terminal_bytes(
+    pid: RawFd,
+    child_pid: RawFd,
+    hold_on_exit: bool,
+    send_screen_instructions: Sender<ScreenInstruction>,
+    mut os_input: Box<dyn ServerOsApi>,
+    output_rate: Option<OutputRate>,
+) -> Arc<PaneTty> {
+    let pane_tty = Arc::new(PaneTty { pid, os_input: os_input.clone() });
     task::spawn({
+        let pane_tty = pane_tty.clone();
         async move {
-            let mut vte_parser = vte::Parser::new();
-            let mut vte_event_sender = VteEventSender::new(pid, send_screen_instructions.clone());
-            let mut terminal_bytes = ReadFromPid::new(&pid, os_input);
+            // the task ends once the screen is gone (eg. when the pane was still being read as its
+            // session ended), rather than panicking the runtime it shares with the other panes
+            let mut terminal_bytes = ReadFromPid::new(&pid, os_input.clone());
+            let tty_readiness = os_input.tty_readiness(pid);
+            let mut output_rate_limit = output_rate.map(OutputRateLimit::new);
+            let mut receive_exit_code = os_input.exit_code(child_pid);
+            let mut exited: Option<(Result<i32, oneshot::Canceled>, Instant)> = None;
+
+            // each pane has at most one batch of bytes waiting to be applied by the screen, and
+            // stops reading from its pty until then. so a pane flooding us with output only slows
+            // down itself (its process blocks on a full pty) rather than the screen, and every
+            // other pane and client along with it
+            let mut previous_batch_applied: Option<oneshot::Receiver<()>> = None;
 
             let mut last_byte_receive_time: Option<Instant> = None;
             let mut pending_render = false;
@@ -162,8 +201,19 @@ fn stream_terminal_bytes(pid: RawFd, send_screen_instructions: Sender<ScreenInst
 
             while let Some(bytes) = terminal_bytes.next().await {
                 let bytes_is_empty = bytes.is_empty();
-                for byte in bytes {
-                    vte_parser.advance(&mut vte_event_sender, byte);
+                if !bytes_is_empty {
+                    let byte_count = bytes.len();
+                    if let Some(previous_batch_applied) = previous_batch_applied.take() {
+                        // this is an error if the screen dropped the batch, eg. when its pane was closed
+                        previous_batch_applied.await.ok();
+                    }
+                    let (batch_applied, receive_batch_applied) = oneshot::channel();
+                    send_screen_instructions.send(ScreenInstruction::PtyBytes(pid, bytes, batch_applied))?;
+                    previous_batch_applied = Some(receive_batch_applied);
+                    if let Some(pause) = output_rate_limit.as_mut().and_then(|limit| limit.pause_after(byte_count)) {
+                        send_screen_instructions.send(ScreenInstruction::Render)?;
+                        task::sleep(pause).await;
+                    }
                 }
                 if !bytes_is_empty {
                     // for UX reasons, if we got something on the wire, we only send the render notice if:
@@ -176,7 +226,7 @@ fn stream_terminal_bytes(pid: RawFd, send_screen_instructions: Sender<ScreenInst
                         Some(receive_time) => {
                             if receive_time.elapsed() > max_render_pause {
                                 pending_render = false;
-                                send_screen_instructions.send(ScreenInstruction::Render).unwrap();
+                                send_screen_instructions.send(ScreenInstruction::Render)?;
                                 last_byte_receive_time = Some(Instant::now());
                             } else {
                                 pending_render = true;
@@ -189,53 +239,146 @@ fn stream_terminal_bytes(pid: RawFd, send_screen_instructions: Sender<ScreenInst
                         }
                     };
                 } else {
+                    if exited.is_some() {
+                        break; // everything the process wrote before exiting was read
+                    }
                     if pending_render {
                         pending_render = false;
-                        send_screen_instructions.send(ScreenInstruction::Render).unwrap();
+                        send_screen_instructions.send(ScreenInstruction::Render)?;
                     }
                     last_byte_receive_time = None;
-                    task::sleep(::std::time::Duration::from_millis(10)).await;
+                    if let Either::Left((exit_code, _)) = select(&mut receive_exit_code, tty_readiness.readable()).await {
+                        exited = Some((exit_code, Instant::now()));
+                    }
                 }
+                match exited {
+                    Some((_, exited_at)) => {
+                        if exited_at.elapsed() > DRAIN_AFTER_EXIT {
+                            break;
+                        }
+                    }
+                    None => {
+                        // the pty might never stop having something to read
+                        if let Ok(Some(exit_code)) = receive_exit_code.try_recv() {
+                            exited = Some((Ok(exit_code), Instant::now()));
+                        }
+                    }
+                }
+            }
+            send_screen_instructions.send(ScreenInstruction::Render)?;
+            let exit_code = match exited {
+                Some((exit_code, _)) => exit_code,
+                None => receive_exit_code.await,
+            };
+            drop(pane_tty);
+            if let Ok(exit_code) = exit_code {
+                send_screen_instructions.send(ScreenInstruction::ProcessExited(pid, exit_code, hold_on_exit))?;
             }
-            send_screen_instructions.send(ScreenInstruction::Render).unwrap();
-            #[cfg(not(test))]
-            // this is a little hacky, and is because the tests end the file as soon as
-            // we read everything, rather than hanging until there is new data
-            // a better solution would be to fix the test fakes, but this will do for now
-            send_screen_instructions.send(ScreenInstruction::ClosePane(pid)).unwrap();
+            Ok::<_, SendError<ScreenInstruction>>(())
         }
     });
+    pane_tty
 }
 
 impl PtyBus {
-    pub fn new (receive_pty_instructions: Receiver<PtyInstruction>, send_screen_instructions: Sender<ScreenInstruction>, os_input: Box<dyn OsApi>) -> Self {
+    pub fn new (
+        receive_pty_instructions: Receiver<PtyInstruction>,
+        send_screen_instructions: Sender<ScreenInstruction>,
+        os_input: Box<dyn ServerOsApi>,
+        output_rate: Option<OutputRate>,
+    ) -> Self {
         PtyBus {
             send_screen_instructions,
             receive_pty_instructions,
             os_input,
+            output_rate,
             id_to_child_pid: HashMap::new(),
+            id_to_run_command: HashMap::new(),
+            id_to_tty: HashMap::new(),
         }
     }
-    pub fn spawn_terminal(&mut self, file_to_open: Option<PathBuf>) {
-        let (pid_primary, pid_secondary): (RawFd, RawFd) = self.os_input.spawn_terminal(file_to_open);
-        stream_terminal_bytes(pid_primary, self.send_screen_instructions.clone(), self.os_input.clone());
-        self.id_to_child_pid.insert(pid_primary, pid_secondary);
+    pub fn spawn_terminal(&mut self, terminal_action: Option<TerminalAction>) -> io::Result<()> {
+        let pid_primary = self.spawn_terminal_process(terminal_action)?;
         self.send_screen_instructions.send(ScreenInstruction::NewPane(pid_primary)).unwrap();
+        Ok(())
     }
-    pub fn spawn_terminal_vertically(&mut self, file_to_open: Option<PathBuf>) {
-        let (pid_primary, pid_secondary): (RawFd, RawFd) = self.os_input.spawn_terminal(file_to_open);
-        stream_terminal_bytes(pid_primary, self.send_screen_instructions.clone(), self.os_input.clone());
-        self.id_to_child_pid.insert(pid_primary, pid_secondary);
+    pub fn spawn_terminal_vertically(&mut self, terminal_action: Option<TerminalAction>) -> io::Result<()> {
+        let pid_primary = self.spawn_terminal_process(terminal_action)?;
         self.send_screen_instructions.send(ScreenInstruction::VerticalSplit(pid_primary)).unwrap();
+        Ok(())
     }
-    pub fn spawn_terminal_horizontally(&mut self, file_to_open: Option<PathBuf>) {
-        let (pid_primary, pid_secondary): (RawFd, RawFd) = self.os_input.spawn_terminal(file_to_open);
-        stream_terminal_bytes(pid_primary, self.send_screen_instructions.clone(), self.os_input.clone());
-        self.id_to_child_pid.insert(pid_primary, pid_secondary);
+    pub fn spawn_terminal_horizontally(&mut self, terminal_action: Option<TerminalAction>) -> io::Result<()> {
+        let pid_primary = self.spawn_terminal_process(terminal_action)?;
         self.send_screen_instructions.send(ScreenInstruction::HorizontalSplit(pid_primary)).unwrap();
+        Ok(())
+    }
+    pub fn spawn_terminal_in_new_tab(&mut self, terminal_action: Option<TerminalAction>) -> io::Result<()> {
+        let pid_primary = self.spawn_terminal_process(terminal_action)?;
+        self.send_screen_instructions.send(ScreenInstruction::NewTab(pid_primary)).unwrap();
+        Ok(())
+    }
+    pub fn spawn_floating_terminal(&mut self, terminal_action: Option<TerminalAction>) -> io::Result<()> {
+        let pid_primary = self.spawn_terminal_process(terminal_action)?;
+        self.send_screen_instructions.send(ScreenInstruction::NewFloatingPane(pid_primary)).unwrap();
+        Ok(())
+    }
+    pub fn spawn_layout(&mut self, layout_file: LayoutFile) -> io::Result<()> {
+        let pane_ids = layout_file.panes().into_iter()
+            .map(|pane| self.spawn_terminal_process(pane.terminal_action()))
+            .collect::<io::Result<_>>()?;
+        self.send_screen_instructions.send(ScreenInstruction::OpenLayout(layout_file, pane_ids)).unwrap();
+        Ok(())
+    }
+    pub fn run_commands(&self) -> HashMap<RawFd, RunCommand> {
+        self.id_to_run_command.clone()
+    }
+    pub fn rerun_command(&mut self, id: RawFd) -> io::Result<()> {
+        if let Some(run_command) = self.id_to_run_command.get(&id).cloned() {
+            // if it can't be, the pane stays as it is and can be re-run again
+            let pid_primary = self.spawn_terminal_process(Some(TerminalAction::RunCommand(run_command)))?;
+            self.id_to_run_command.remove(&id);
+            self.id_to_child_pid.remove(&id);
+            self.id_to_tty.remove(&id);
+            self.send_screen_instructions.send(ScreenInstruction::ReplacePane(id, pid_primary)).unwrap();
+        }
+        Ok(())
+    }
+    fn spawn_terminal_process(&mut self, terminal_action: Option<TerminalAction>) -> io::Result<RawFd> {
+        // panes running a command stay open when it exits, so that its output can still be read
+        let run_command = match &terminal_action {
+            Some(TerminalAction::RunCommand(run_command)) => Some(run_command.clone()),
+            _ => None,
+        };
+        let (pid_primary, pid_secondary): (RawFd, RawFd) = self.os_input.spawn_terminal(terminal_action)?;
+        let pane_tty = stream_terminal_bytes(
+            pid_primary,
+            pid_secondary,
+            run_command.is_some(),
+            self.send_screen_instructions.clone(),
+            self.os_input.clone(),
+            self.output_rate,
+        );
+        self.id_to_child_pid.insert(pid_primary, pid_secondary);
+        self.id_to_tty.insert(pid_primary, pane_tty);
+        if let Some(run_command) = run_command {
+            self.id_to_run_command.insert(pid_primary, run_command);
+        }
+        Ok(pid_primary)
+    }
+    pub fn process_exited(&mut self, id: RawFd) {
+        // its pid might be reused by then, so it must not be killed anymore
+        self.id_to_child_pid.remove(&id);
+        if !self.id_to_run_command.contains_key(&id) {
+            // the pane closes along with its process, unlike the ones kept open to show its output
+            self.id_to_tty.remove(&id);
+        }
     }
     pub fn close_pane(&mut self, id: RawFd) {
-        let child_pid = self.id_to_child_pid.get(&id).unwrap();
-        self.os_input.kill(*child_pid).unwrap();
+        self.id_to_run_command.remove(&id);
+        self.id_to_tty.remove(&id);
+        // there is nothing left to kill in panes that were kept open after their process exited
+        if let Some(child_pid) = self.id_to_child_pid.remove(&id) {
+            self.os_input.kill(child_pid).unwrap();
+        }
     }
 }
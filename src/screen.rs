@@ -1,19 +1,32 @@
-use std::io::Write;
-use std::collections::{HashSet, BTreeMap};
+use std::collections::{BTreeMap, HashMap};
 use nix::pty::Winsize;
 use std::os::unix::io::RawFd;
+use std::sync::{Arc, Mutex};
 use std::sync::mpsc::{Sender, Receiver};
+use std::thread;
+use std::time::Duration;
+use futures::channel::oneshot;
 
-use crate::os_input_output::OsApi;
+use crate::os_input_output::ServerOsApi;
 use crate::terminal_pane::TerminalPane;
-use crate::pty_bus::{VteEvent, PtyInstruction};
-use crate::boundaries::Boundaries;
+use crate::pty_bus::{PtyInstruction, RunCommand};
+use crate::output_grid::OutputGrid;
+use crate::layout::{Edge, MinPaneSize, PaneGeometry};
+use crate::layout_file::LayoutFile;
+use crate::tab::Tab;
+use crate::terminal_pane::{AnsiCode, CharacterStyles, TerminalCharacter, EMPTY_TERMINAL_CHARACTER};
+use crate::server::ServerInstruction;
+use crate::ipc::{CaptureOptions, Direction, Event, PaneSelector, PaneState, SplitDirection, TabState, TerminalSize};
 
 /*
  * Screen
  *
- * this holds multiple panes (currently terminal panes) which are currently displayed on screen
- * it tracks their coordinates (x/y) and size, as well as how they should be resized
+ * this holds the tabs of the session and shows one of them at a time. each tab has its own panes
+ * (currently terminal panes), whose coordinates (x/y) and size come from the tab's layout, which
+ * is what splitting, closing and resizing them changes
+ *
+ * once there is more than one tab, the first row of the screen is the tab bar and the panes are
+ * laid out below it
  *
  */
 
@@ -25,40 +38,13 @@ fn _debug_log_to_file (message: String) {
     file.write_all("\n".as_bytes()).unwrap();
 }
 
-const CURSOR_HEIGHT_WIDGH_RATIO: u16 = 4; // this is not accurate and kind of a magic number, TODO: look into this
-
-type BorderAndPaneIds = (u16, Vec<RawFd>);
-
-fn split_vertically_with_gap (rect: &Winsize) -> (Winsize, Winsize) {
-    let width_of_each_half = (rect.ws_col - 1) / 2;
-    let mut first_rect = rect.clone();
-    let mut second_rect = rect.clone();
-    if rect.ws_col % 2 == 0 {
-        first_rect.ws_col = width_of_each_half + 1;
-    } else {
-        first_rect.ws_col = width_of_each_half;
-    }
-    second_rect.ws_col = width_of_each_half;
-    (first_rect, second_rect)
-}
-
-fn split_horizontally_with_gap (rect: &Winsize) -> (Winsize, Winsize) {
-    let height_of_each_half = (rect.ws_row - 1) / 2;
-    let mut first_rect = rect.clone();
-    let mut second_rect = rect.clone();
-    if rect.ws_row % 2 == 0 {
-        first_rect.ws_row = height_of_each_half + 1;
-    } else {
-        first_rect.ws_row = height_of_each_half;
-    }
-    second_rect.ws_row = height_of_each_half;
-    (first_rect, second_rect)
-}
 
 #[derive(Debug)]
 pub enum ScreenInstruction {
-    Pty(RawFd, VteEvent),
+    PtyBytes(RawFd, Vec<u8>, oneshot::Sender<()>), // what a single read got, and who to tell once it was applied
     Render,
+    ForceRender,
+    TerminalResize(Winsize),
     NewPane(RawFd),
     HorizontalSplit(RawFd),
     VerticalSplit(RawFd),
@@ -68,991 +54,649 @@ pub enum ScreenInstruction {
     ResizeDown,
     ResizeUp,
     MoveFocus,
+    MoveFocusLeft,
+    MoveFocusRight,
+    MoveFocusUp,
+    MoveFocusDown,
+    FocusPreviousPane,
+    SwapPaneLeft,
+    SwapPaneRight,
+    SwapPaneUp,
+    SwapPaneDown,
+    SwapWithNextPane,
+    MovePaneToPosition(usize), // in the order of the layout, from 0
+    ToggleZoom,
+    ToggleFloatingPanes,
+    NewFloatingPane(RawFd),
+    NewTab(RawFd), // its first pane
+    CloseTab,
+    RenameTab(String),
+    NextTab,
+    PreviousTab,
+    GoToTab(usize, Sender<Result<(), String>>), // in the order of the tab bar, from 0
     Quit,
     ScrollUp,
     ScrollDown,
     ClearScroll,
     CloseFocusedPane,
-    ClosePane(RawFd),
+    ProcessExited(RawFd, i32, bool), // exit code, whether to keep the pane open
+    RerunFocusedPane,
+    ReplacePane(RawFd, RawFd), // old, new
+    GetPaneCount(Sender<usize>),
+    WriteToPane(PaneSelector, Vec<u8>, Sender<Result<(), String>>),
+    RenamePane(PaneSelector, String, Sender<Result<(), String>>),
+    CapturePane(PaneSelector, CaptureOptions, Sender<Result<String, String>>),
+    GetState(Sender<(TerminalSize, Vec<TabState>, Vec<PaneState>)>),
+    OpenLayout(LayoutFile, Vec<RawFd>), // the ids of its panes, in the order of the file
+    GetLayout(HashMap<RawFd, RunCommand>, Sender<String>), // what the panes run, and who to send the yaml to
 }
 
+impl ScreenInstruction {
+    // something the user did (rather than eg. a pane's output), which makes the notice about what
+    // they did before irrelevant
+    pub fn is_user_action(&self) -> bool {
+        !matches!(self,
+            ScreenInstruction::PtyBytes(..) | ScreenInstruction::Render | ScreenInstruction::ForceRender |
+            ScreenInstruction::TerminalResize(_) | ScreenInstruction::Quit | ScreenInstruction::ProcessExited(..) |
+            ScreenInstruction::ReplacePane(..) | ScreenInstruction::GetPaneCount(_) | ScreenInstruction::CapturePane(..) |
+            ScreenInstruction::GetState(_) | ScreenInstruction::GetLayout(..)
+        )
+    }
+}
+
+
 pub struct Screen {
     pub receiver: Receiver<ScreenInstruction>,
-    max_panes: Option<usize>,
+    max_panes: Option<usize>, // in each tab
+    min_pane_size: MinPaneSize,
     send_pty_instructions: Sender<PtyInstruction>,
+    send_server_instructions: Sender<ServerInstruction>,
     full_screen_ws: Winsize,
-    terminals: BTreeMap<RawFd, TerminalPane>, // BTreeMap because we need a predictable order when changing focus
-    active_terminal: Option<RawFd>,
-    os_api: Box<dyn OsApi>,
+    display_ws: Winsize, // what the clients can show, smaller than full_screen_ws when the panes couldn't fit in it
+    tabs: Vec<Tab>, // in the order of the tab bar
+    active_tab_index: usize,
+    os_api: Box<dyn ServerOsApi>,
+    reported_panes: BTreeMap<RawFd, ReportedPane>, // as subscribers last heard of them
+    reported_focus: Option<RawFd>,
+    output_grid: OutputGrid,
+    drawn_layout: Vec<(RawFd, u16, u16, u16, u16)>, // id, x, y, rows, cols of the panes on the output grid
+    pending_input: HashMap<RawFd, Arc<Mutex<PendingInput>>>, // of the panes that didn't read all that was written to them
+}
+
+// what is still to be written to a pane once its process reads what was written to it before.
+// a thread of its own writes it, so that the screen (and every other pane) doesn't wait for that
+struct PendingInput {
+    bytes: Vec<u8>,
+    writing: bool, // false once the thread is done, what is written to the pane after that goes straight to it
+    pane_closed: bool,
+}
+
+#[derive(PartialEq)]
+struct ReportedPane {
+    geometry: (u16, u16, u16, u16), // x, y, rows, cols
+    title: Option<String>,
 }
 
 impl Screen {
     pub fn new (
         receive_screen_instructions: Receiver<ScreenInstruction>,
         send_pty_instructions: Sender<PtyInstruction>,
+        send_server_instructions: Sender<ServerInstruction>,
         full_screen_ws: &Winsize,
-        os_api: Box<dyn OsApi>,
+        os_api: Box<dyn ServerOsApi>,
         max_panes: Option<usize>,
+        min_pane_size: MinPaneSize,
     ) -> Self {
+        let first_tab = Tab::new(tab_area(full_screen_ws, 1), send_pty_instructions.clone(), os_api.clone(), max_panes, min_pane_size);
         Screen {
             receiver: receive_screen_instructions,
             max_panes,
+            min_pane_size,
             send_pty_instructions,
+            send_server_instructions,
             full_screen_ws: full_screen_ws.clone(),
-            terminals: BTreeMap::new(),
-            active_terminal: None,
+            display_ws: *full_screen_ws,
+            tabs: vec![first_tab],
+            active_tab_index: 0,
             os_api,
+            reported_panes: BTreeMap::new(),
+            reported_focus: None,
+            output_grid: OutputGrid::new(full_screen_ws.ws_col as usize, full_screen_ws.ws_row as usize),
+            drawn_layout: vec![],
+            pending_input: HashMap::new(),
         }
     }
+    fn active_tab(&self) -> &Tab {
+        &self.tabs[self.active_tab_index]
+    }
+    fn active_tab_mut(&mut self) -> &mut Tab {
+        &mut self.tabs[self.active_tab_index]
+    }
+    fn tab_index_with_pane(&self, id: RawFd) -> Option<usize> {
+        self.tabs.iter().position(|tab| tab.get_terminal(id).is_some())
+    }
+    fn get_terminal(&self, id: RawFd) -> Option<&TerminalPane> {
+        self.tabs.iter().find_map(|tab| tab.get_terminal(id))
+    }
+    fn get_terminal_mut(&mut self, id: RawFd) -> Option<&mut TerminalPane> {
+        self.tabs.iter_mut().find_map(|tab| tab.get_terminal_mut(id))
+    }
     pub fn new_pane(&mut self, pid: RawFd) {
-        self.close_down_to_max_terminals();
-        if self.terminals.is_empty() {
-            let x = 0;
-            let y = 0;
-            let new_terminal = TerminalPane::new(pid, self.full_screen_ws.clone(), x, y);
-            self.os_api.set_terminal_size_using_fd(new_terminal.pid, new_terminal.display_cols, new_terminal.display_rows);
-            self.terminals.insert(pid, new_terminal);
-            self.active_terminal = Some(pid);
-        } else {
-            // TODO: check minimum size of active terminal
-
-            let (_longest_edge, terminal_id_to_split) = self.terminals.iter().fold((0, 0), |(current_longest_edge, current_terminal_id_to_split), id_and_terminal_to_check| {
-                let (id_of_terminal_to_check, terminal_to_check) = id_and_terminal_to_check;
-                let terminal_size = (terminal_to_check.display_rows * CURSOR_HEIGHT_WIDGH_RATIO) * terminal_to_check.display_cols;
-                if terminal_size > current_longest_edge {
-                    (terminal_size, *id_of_terminal_to_check)
-                } else {
-                    (current_longest_edge, current_terminal_id_to_split)
-                }
-            });
-            let terminal_to_split = self.terminals.get_mut(&terminal_id_to_split).unwrap();
-            let terminal_ws = Winsize {
-                ws_row: terminal_to_split.display_rows,
-                ws_col: terminal_to_split.display_cols,
-                ws_xpixel: terminal_to_split.x_coords,
-                ws_ypixel: terminal_to_split.y_coords,
-            };
-            if terminal_to_split.display_rows * CURSOR_HEIGHT_WIDGH_RATIO > terminal_to_split.display_cols {
-                let (top_winsize, bottom_winsize) = split_horizontally_with_gap(&terminal_ws);
-                let bottom_half_y = terminal_ws.ws_ypixel + top_winsize.ws_row + 1;
-                let new_terminal = TerminalPane::new(pid, bottom_winsize, terminal_ws.ws_xpixel, bottom_half_y);
-                self.os_api.set_terminal_size_using_fd(new_terminal.pid, bottom_winsize.ws_col, bottom_winsize.ws_row);
-                terminal_to_split.change_size(&top_winsize);
-                self.terminals.insert(pid, new_terminal);
-                self.os_api.set_terminal_size_using_fd(terminal_id_to_split, top_winsize.ws_col, top_winsize.ws_row);
-                self.active_terminal = Some(pid);
-            } else {
-                let (left_winszie, right_winsize) = split_vertically_with_gap(&terminal_ws);
-                let right_side_x = terminal_ws.ws_xpixel + left_winszie.ws_col + 1;
-                let new_terminal = TerminalPane::new(pid, right_winsize, right_side_x, terminal_ws.ws_ypixel);
-                self.os_api.set_terminal_size_using_fd(new_terminal.pid, right_winsize.ws_col, right_winsize.ws_row);
-                terminal_to_split.change_size(&left_winszie);
-                self.terminals.insert(pid, new_terminal);
-                self.os_api.set_terminal_size_using_fd(terminal_id_to_split, left_winszie.ws_col, left_winszie.ws_row);
-            }
-            self.active_terminal = Some(pid);
+        if self.active_tab_mut().new_pane(pid) {
             self.render();
         }
     }
     pub fn horizontal_split(&mut self, pid: RawFd) {
-        self.close_down_to_max_terminals();
-        if self.terminals.is_empty() {
-            let x = 0;
-            let y = 0;
-            let new_terminal = TerminalPane::new(pid, self.full_screen_ws.clone(), x, y);
-            self.os_api.set_terminal_size_using_fd(new_terminal.pid, new_terminal.display_cols, new_terminal.display_rows);
-            self.terminals.insert(pid, new_terminal);
-            self.active_terminal = Some(pid);
-        } else {
-            // TODO: check minimum size of active terminal
-            let (active_terminal_ws, active_terminal_x_coords, active_terminal_y_coords) = {
-                let active_terminal = &self.get_active_terminal().unwrap();
-                (
-                    Winsize {
-                        ws_row: active_terminal.display_rows,
-                        ws_col: active_terminal.display_cols,
-                        ws_xpixel: 0,
-                        ws_ypixel: 0,
-                    },
-                    active_terminal.x_coords,
-                    active_terminal.y_coords
-                )
-            };
-            let (top_winsize, bottom_winsize) = split_horizontally_with_gap(&active_terminal_ws);
-            let bottom_half_y = active_terminal_y_coords + top_winsize.ws_row + 1;
-            let new_terminal = TerminalPane::new(pid, bottom_winsize, active_terminal_x_coords, bottom_half_y);
-            self.os_api.set_terminal_size_using_fd(new_terminal.pid, bottom_winsize.ws_col, bottom_winsize.ws_row);
-
-            {
-                let active_terminal_id = &self.get_active_terminal_id().unwrap();
-                let active_terminal = &mut self.terminals.get_mut(&active_terminal_id).unwrap();
-                active_terminal.change_size(&top_winsize);
-            }
-
-            self.terminals.insert(pid, new_terminal);
-            let active_terminal_pid = self.get_active_terminal_id().unwrap();
-            self.os_api.set_terminal_size_using_fd(active_terminal_pid, top_winsize.ws_col, top_winsize.ws_row);
-            self.active_terminal = Some(pid);
+        if self.active_tab_mut().horizontal_split(pid) {
             self.render();
         }
     }
     pub fn vertical_split(&mut self, pid: RawFd) {
-        self.close_down_to_max_terminals();
-        if self.terminals.is_empty() {
-            let x = 0;
-            let y = 0;
-            let new_terminal = TerminalPane::new(pid, self.full_screen_ws.clone(), x, y);
-            self.os_api.set_terminal_size_using_fd(new_terminal.pid, new_terminal.display_cols, new_terminal.display_rows);
-            self.terminals.insert(pid, new_terminal);
-            self.active_terminal = Some(pid);
-        } else {
-            // TODO: check minimum size of active terminal
-            let (active_terminal_ws, active_terminal_x_coords, active_terminal_y_coords) = {
-                let active_terminal = &self.get_active_terminal().unwrap();
-                (
-                    Winsize {
-                        ws_row: active_terminal.display_rows,
-                        ws_col: active_terminal.display_cols,
-                        ws_xpixel: 0,
-                        ws_ypixel: 0,
-                    },
-                    active_terminal.x_coords,
-                    active_terminal.y_coords
-                )
-            };
-            let (left_winszie, right_winsize) = split_vertically_with_gap(&active_terminal_ws);
-            let right_side_x = active_terminal_x_coords + left_winszie.ws_col + 1;
-            let new_terminal = TerminalPane::new(pid, right_winsize, right_side_x, active_terminal_y_coords);
-            self.os_api.set_terminal_size_using_fd(new_terminal.pid, right_winsize.ws_col, right_winsize.ws_row);
-
-            {
-                let active_terminal_id = &self.get_active_terminal_id().unwrap();
-                let active_terminal = &mut self.terminals.get_mut(&active_terminal_id).unwrap();
-                active_terminal.change_size(&left_winszie);
-            }
-
-            self.terminals.insert(pid, new_terminal);
-            let active_terminal_pid = self.get_active_terminal_id().unwrap();
-            self.os_api.set_terminal_size_using_fd(active_terminal_pid, left_winszie.ws_col, left_winszie.ws_row);
-            self.active_terminal = Some(pid);
+        if self.active_tab_mut().vertical_split(pid) {
             self.render();
         }
     }
-    fn get_active_terminal (&self) -> Option<&TerminalPane> {
-        match self.active_terminal {
-            Some(active_terminal) => self.terminals.get(&active_terminal),
-            None => None
-        }
-    }
-    fn get_active_terminal_id (&self) -> Option<RawFd> {
-        match self.active_terminal {
-            Some(active_terminal) => Some(self.terminals.get(&active_terminal).unwrap().pid),
-            None => None
-        }
-    }
-    pub fn handle_pty_event(&mut self, pid: RawFd, event: VteEvent) {
-        let terminal_output = self.terminals.get_mut(&pid).unwrap();
-        terminal_output.handle_event(event);
+    pub fn open_layout(&mut self, layout_file: LayoutFile, pane_ids: Vec<RawFd>) {
+        self.active_tab_mut().open_layout(layout_file, pane_ids);
+        self.render();
     }
-    pub fn write_to_active_terminal(&mut self, byte: u8) {
-        if let Some(active_terminal_id) = &self.get_active_terminal_id() {
-            let mut buffer = [byte];
-            self.os_api.write_to_tty_stdin(*active_terminal_id, &mut buffer).expect("failed to write to terminal");
-            self.os_api.tcdrain(*active_terminal_id).expect("failed to drain terminal");
+    pub fn get_layout(&self, run_commands: &HashMap<RawFd, RunCommand>) -> String {
+        // layout files have no tabs, so this is the layout of the one that is shown
+        self.active_tab().get_layout(run_commands)
+    }
+    pub fn new_tab(&mut self, pid: RawFd) {
+        // new tabs go at the end of the tab bar
+        let area = tab_area(&self.full_screen_ws, self.tabs.len() + 1);
+        if !self.tabs.iter().all(|tab| tab.fits(area.cols, area.rows)) {
+            // the tab bar would take the room the panes of the other tabs need
+            self.send_pty_instructions.send(PtyInstruction::ClosePane(pid)).unwrap();
+            self.active_tab_mut().show_no_room_to_split();
+            self.render();
+            return;
         }
+        let mut tab = Tab::new(area, self.send_pty_instructions.clone(), self.os_api.clone(), self.max_panes, self.min_pane_size);
+        tab.add_only_pane(pid);
+        self.tabs.push(tab);
+        self.resize_tabs();
+        self.active_tab_index = self.tabs.len() - 1;
+        self.render();
     }
-    fn get_active_terminal_cursor_position(&self) -> (usize, usize) { // (x, y)
-        let active_terminal = &self.get_active_terminal().unwrap();
-        let (x_in_terminal, y_in_terminal) = active_terminal.cursor_coordinates();
-
-        let x = active_terminal.x_coords as usize + x_in_terminal;
-        let y = active_terminal.y_coords as usize + y_in_terminal;
-        (x, y)
-    }
-    pub fn render (&mut self) {
-        let mut stdout = self.os_api.get_stdout_writer();
-        let mut boundaries = Boundaries::new(self.full_screen_ws.ws_col, self.full_screen_ws.ws_row);
-        for (_pid, terminal) in self.terminals.iter_mut() {
-            boundaries.add_rect(&terminal);
-            if let Some(vte_output) = terminal.buffer_as_vte_output() {
-                stdout.write_all(&vte_output.as_bytes()).expect("cannot write to stdout");
+    pub fn close_tab(&mut self) {
+        // like the last pane, the last tab stays open
+        if self.tabs.len() > 1 {
+            let active_tab = self.active_tab_mut();
+            for id in active_tab.pane_ids() {
+                active_tab.close_pane_process(id);
             }
-        }
-
-        // TODO: only render (and calculate) boundaries if there was a resize
-        let vte_output = boundaries.vte_output();
-        stdout.write_all(&vte_output.as_bytes()).expect("cannot write to stdout");
-
-        let (cursor_position_x, cursor_position_y) = self.get_active_terminal_cursor_position();
-        let goto_cursor_position = format!("\u{1b}[{};{}H\u{1b}[m", cursor_position_y + 1, cursor_position_x + 1); // goto row/col
-        stdout.write_all(&goto_cursor_position.as_bytes()).expect("cannot write to stdout");
-        stdout.flush().expect("could not flush");
-    }
-    fn terminal_ids_directly_left_of(&self, id: &RawFd) -> Option<Vec<RawFd>> {
-        let mut ids = vec![];
-        let terminal_to_check = self.terminals.get(id).unwrap();
-        if terminal_to_check.x_coords == 0 {
-            return None;
-        }
-        for (pid, terminal) in self.terminals.iter() {
-            if terminal.x_coords + terminal.display_cols == terminal_to_check.x_coords - 1 {
-                ids.push(*pid);
-            }
-        }
-        if ids.is_empty() {
-            None
-        } else {
-            Some(ids)
+            self.remove_tab(self.active_tab_index);
+            self.render();
         }
     }
-    fn terminal_ids_directly_right_of(&self, id: &RawFd) -> Option<Vec<RawFd>> {
-        let mut ids = vec![];
-        let terminal_to_check = self.terminals.get(id).unwrap();
-        for (pid, terminal) in self.terminals.iter() {
-            if terminal.x_coords == terminal_to_check.x_coords + terminal_to_check.display_cols + 1 {
-                ids.push(*pid);
-            }
+    fn remove_tab(&mut self, index: usize) {
+        // if it was shown, the tab after it is shown instead (or the one before, if it was last)
+        for id in self.tabs[index].pane_ids() {
+            self.drop_pending_input(id);
         }
-        if ids.is_empty() {
-            None
-        } else {
-            Some(ids)
+        self.tabs.remove(index);
+        if self.active_tab_index > index || self.active_tab_index == self.tabs.len() {
+            self.active_tab_index -= 1;
         }
+        self.resize_tabs();
     }
-    fn terminal_ids_directly_below(&self, id: &RawFd) -> Option<Vec<RawFd>> {
-        let mut ids = vec![];
-        let terminal_to_check = self.terminals.get(id).unwrap();
-        for (pid, terminal) in self.terminals.iter() {
-            if terminal.y_coords == terminal_to_check.y_coords + terminal_to_check.display_rows + 1 {
-                ids.push(*pid);
-            }
-        }
-        if ids.is_empty() {
-            None
-        } else {
-            Some(ids)
+    fn resize_tabs(&mut self) {
+        // the tab bar comes and goes with the second tab
+        let area = tab_area(&self.full_screen_ws, self.tabs.len());
+        for tab in self.tabs.iter_mut() {
+            tab.resize(area);
         }
     }
-    fn terminal_ids_directly_above(&self, id: &RawFd) -> Option<Vec<RawFd>> {
-        let mut ids = vec![];
-        let terminal_to_check = self.terminals.get(id).unwrap();
-        for (pid, terminal) in self.terminals.iter() {
-            if terminal.y_coords + terminal.display_rows + 1 == terminal_to_check.y_coords {
-                ids.push(*pid);
-            }
-        }
-        if ids.is_empty() {
-            None
-        } else {
-            Some(ids)
+    pub fn rename_tab(&mut self, name: String) {
+        self.active_tab_mut().name = Some(name);
+        self.render();
+    }
+    pub fn next_tab(&mut self) {
+        self.switch_to_tab((self.active_tab_index + 1) % self.tabs.len());
+    }
+    pub fn previous_tab(&mut self) {
+        self.switch_to_tab((self.active_tab_index + self.tabs.len() - 1) % self.tabs.len());
+    }
+    pub fn go_to_tab(&mut self, index: usize) -> Result<(), String> {
+        if index >= self.tabs.len() {
+            return Err(format!("no tab {}, there {}", index + 1, match self.tabs.len() {
+                1 => String::from("is only 1"),
+                tab_count => format!("are only {}", tab_count),
+            }));
         }
+        self.switch_to_tab(index);
+        Ok(())
     }
-    fn panes_top_aligned_with_pane(&self, pane: &TerminalPane) -> Vec<&TerminalPane> {
-        self.terminals
-            .keys()
-            .map(|t_id| self.terminals.get(&t_id).unwrap())
-            .filter(|terminal| terminal.pid != pane.pid && terminal.y_coords == pane.y_coords)
-            .collect()
-    }
-    fn panes_bottom_aligned_with_pane(&self, pane: &TerminalPane) -> Vec<&TerminalPane> {
-        self.terminals
-            .keys()
-            .map(|t_id| self.terminals.get(&t_id).unwrap())
-            .filter(|terminal| terminal.pid != pane.pid && terminal.y_coords + terminal.display_rows == pane.y_coords + pane.display_rows)
-            .collect()
-    }
-    fn panes_right_aligned_with_pane(&self, pane: &TerminalPane) -> Vec<&TerminalPane> {
-        self.terminals
-            .keys()
-            .map(|t_id| self.terminals.get(&t_id).unwrap())
-            .filter(|terminal| terminal.pid != pane.pid && terminal.x_coords + terminal.display_cols == pane.x_coords + pane.display_cols)
-            .collect()
-    }
-    fn panes_left_aligned_with_pane(&self, pane: &TerminalPane) -> Vec<&TerminalPane> {
-        self.terminals
-            .keys()
-            .map(|t_id| self.terminals.get(&t_id).unwrap())
-            .filter(|terminal| terminal.pid != pane.pid && terminal.x_coords == pane.x_coords)
-            .collect()
-    }
-    fn right_aligned_contiguous_panes_above(&self, id: &RawFd, terminal_borders_to_the_right: &HashSet<u16>) -> BorderAndPaneIds {
-        let mut terminals = vec![];
-        let terminal_to_check = self.terminals.get(id).expect("terminal id does not exist");
-        let mut right_aligned_terminals = self.panes_right_aligned_with_pane(&terminal_to_check);
-        // terminals that are next to each other up to current
-        right_aligned_terminals.sort_by(|a, b| { b.y_coords.cmp(&a.y_coords)});
-        for terminal in right_aligned_terminals {
-            let terminal_to_check = terminals
-                .last()
-                .unwrap_or(&terminal_to_check);
-            if terminal.y_coords + terminal.display_rows + 1 == terminal_to_check.y_coords {
-                terminals.push(terminal);
-            }
+    fn switch_to_tab(&mut self, index: usize) {
+        if index != self.active_tab_index {
+            self.active_tab_index = index;
+            self.render();
         }
-        // top-most border aligned with a pane border to the right
-        let mut top_resize_border = 0;
-        for terminal in &terminals {
-            let bottom_terminal_boundary = terminal.y_coords + terminal.display_rows;
-            if terminal_borders_to_the_right.get(&(bottom_terminal_boundary + 1)).is_some() && top_resize_border < bottom_terminal_boundary {
-                top_resize_border = bottom_terminal_boundary + 1;
+    }
+    pub fn pane_count(&self) -> usize {
+        self.tabs.iter().map(|tab| tab.pane_count()).sum()
+    }
+    pub fn get_state(&self) -> (TerminalSize, Vec<TabState>, Vec<PaneState>) {
+        // what the clients show, even if the panes are laid out for a larger screen
+        let size = TerminalSize {
+            rows: self.display_ws.ws_row,
+            cols: self.display_ws.ws_col,
+        };
+        let tabs = self.tabs.iter().enumerate().map(|(index, tab)| {
+            TabState {
+                name: tab.name.clone(),
+                active: index == self.active_tab_index,
+            }
+        }).collect();
+        let panes = self.tabs.iter().enumerate()
+            .flat_map(|(index, tab)| tab.pane_states(index, index == self.active_tab_index))
+            .collect();
+        (size, tabs, panes)
+    }
+    pub fn handle_pty_bytes(&mut self, pid: RawFd, bytes: &[u8]) {
+        // the pane might have been closed since its process wrote these, if it's in a tab that
+        // isn't shown it will be drawn once it is
+        if let Some(terminal_output) = self.get_terminal_mut(pid) {
+            terminal_output.handle_pty_bytes(bytes);
+        }
+    }
+    fn get_terminal_id_by_selector (&self, pane_selector: &PaneSelector) -> Result<RawFd, String> {
+        match pane_selector {
+            PaneSelector::Focused => self.active_tab().get_active_terminal_id().ok_or_else(|| String::from("no pane is focused")),
+            PaneSelector::Id(id) => {
+                if self.get_terminal(*id).is_some() {
+                    Ok(*id)
+                } else {
+                    Err(format!("no pane with id {}", id))
+                }
             }
-        }
-        terminals.retain(|terminal| {
-            terminal.y_coords >= top_resize_border
-        });
-        // if there are no adjacent panes to resize, we use the border of the main pane we're
-        // resizing
-        let top_resize_border = if terminals.is_empty() { terminal_to_check.y_coords } else { top_resize_border };
-        let terminal_ids: Vec<RawFd> = terminals.iter().map(|t| t.pid).collect();
-        (top_resize_border, terminal_ids)
-    }
-    fn right_aligned_contiguous_panes_below(&self, id: &RawFd, terminal_borders_to_the_right: &HashSet<u16>) -> BorderAndPaneIds {
-        let mut terminals = vec![];
-        let terminal_to_check = self.terminals.get(id).expect("terminal id does not exist");
-        let mut right_aligned_terminals = self.panes_right_aligned_with_pane(&terminal_to_check);
-        // terminals that are next to each other up to current
-        right_aligned_terminals.sort_by(|a, b| { a.y_coords.cmp(&b.y_coords)});
-        for terminal in right_aligned_terminals {
-            let terminal_to_check = terminals
-                .last()
-                .unwrap_or(&terminal_to_check);
-            if terminal.y_coords == terminal_to_check.y_coords + terminal_to_check.display_rows + 1 {
-                terminals.push(terminal);
+            PaneSelector::Name(name) => {
+                self.tabs.iter()
+                    .flat_map(|tab| tab.terminals())
+                    .find(|terminal| terminal.name.as_ref() == Some(name))
+                    .map(|terminal| terminal.pid)
+                    .ok_or_else(|| format!("no pane named \"{}\"", name))
             }
         }
-        // bottom-most border aligned with a pane border to the right
-        let mut bottom_resize_border = self.full_screen_ws.ws_row;
-        for terminal in &terminals {
-            let top_terminal_boundary = terminal.y_coords;
-            if terminal_borders_to_the_right.get(&(top_terminal_boundary)).is_some() && top_terminal_boundary < bottom_resize_border {
-                bottom_resize_border = top_terminal_boundary;
+    }
+    fn write_to_terminal(&mut self, id: RawFd, bytes: &mut [u8]) -> Result<(), String> {
+        if let Some(pending_input) = self.pending_input.get(&id) {
+            let mut pending_input = pending_input.lock().unwrap();
+            if pending_input.writing {
+                // after what the pane's process didn't read yet
+                pending_input.bytes.extend_from_slice(bytes);
+                return Ok(());
             }
         }
-        terminals.retain(|terminal| {
-            terminal.y_coords + terminal.display_rows <= bottom_resize_border
-        });
-        // if there are no adjacent panes to resize, we use the border of the main pane we're
-        // resizing
-        let bottom_resize_border = if terminals.is_empty() { terminal_to_check.y_coords + terminal_to_check.display_rows } else { bottom_resize_border };
-        let terminal_ids: Vec<RawFd> = terminals.iter().map(|t| t.pid).collect();
-        (bottom_resize_border, terminal_ids)
-    }
-    fn left_aligned_contiguous_panes_above(&self, id: &RawFd, terminal_borders_to_the_left: &HashSet<u16>) -> BorderAndPaneIds {
-        let mut terminals = vec![];
-        let terminal_to_check = self.terminals.get(id).expect("terminal id does not exist");
-        let mut left_aligned_terminals = self.panes_left_aligned_with_pane(&terminal_to_check);
-        // terminals that are next to each other up to current
-        left_aligned_terminals.sort_by(|a, b| { b.y_coords.cmp(&a.y_coords)});
-        for terminal in left_aligned_terminals {
-            let terminal_to_check = terminals
-                .last()
-                .unwrap_or(&terminal_to_check);
-            if terminal.y_coords + terminal.display_rows + 1 == terminal_to_check.y_coords {
-                terminals.push(terminal);
-            }
+        let write_error = |error| format!("could not write to pane {}: {}", id, error);
+        let written = write_while_tty_takes_it(&mut self.os_api, id, bytes).map_err(write_error)?;
+        if written < bytes.len() {
+            let pending_input = Arc::new(Mutex::new(PendingInput {
+                bytes: bytes[written..].to_vec(),
+                writing: true,
+                pane_closed: false,
+            }));
+            self.pending_input.insert(id, pending_input.clone());
+            let os_api = self.os_api.clone();
+            thread::Builder::new()
+                .name("write_pending_input".to_string())
+                .spawn(move || write_pending_input(id, os_api, pending_input))
+                .unwrap();
+            return Ok(());
         }
-        // top-most border aligned with a pane border to the right
-        let mut top_resize_border = 0;
-        for terminal in &terminals {
-            let bottom_terminal_boundary = terminal.y_coords + terminal.display_rows;
-            if terminal_borders_to_the_left.get(&(bottom_terminal_boundary + 1)).is_some() && top_resize_border < bottom_terminal_boundary {
-                top_resize_border = bottom_terminal_boundary + 1;
-            }
+        self.os_api.tcdrain(id).map_err(write_error)
+    }
+    fn drop_pending_input(&mut self, id: RawFd) {
+        if let Some(pending_input) = self.pending_input.remove(&id) {
+            pending_input.lock().unwrap().pane_closed = true;
         }
-        terminals.retain(|terminal| {
-            terminal.y_coords >= top_resize_border
-        });
-        // if there are no adjacent panes to resize, we use the border of the main pane we're
-        // resizing
-        let top_resize_border = if terminals.is_empty() { terminal_to_check.y_coords } else { top_resize_border };
-        let terminal_ids: Vec<RawFd> = terminals.iter().map(|t| t.pid).collect();
-        (top_resize_border, terminal_ids)
-    }
-    fn left_aligned_contiguous_panes_below(&self, id: &RawFd, terminal_borders_to_the_left: &HashSet<u16>) -> BorderAndPaneIds {
-        let mut terminals = vec![];
-        let terminal_to_check = self.terminals.get(id).expect("terminal id does not exist");
-        let mut left_aligned_terminals = self.panes_left_aligned_with_pane(&terminal_to_check);
-        // terminals that are next to each other up to current
-        left_aligned_terminals.sort_by(|a, b| { a.y_coords.cmp(&b.y_coords)});
-        for terminal in left_aligned_terminals {
-            let terminal_to_check = terminals
-                .last()
-                .unwrap_or(&terminal_to_check);
-            if terminal.y_coords == terminal_to_check.y_coords + terminal_to_check.display_rows + 1 {
-                terminals.push(terminal);
-            }
+    }
+    pub fn write_to_active_terminal(&mut self, byte: u8) {
+        if let Some(active_terminal_id) = self.active_tab().get_active_terminal_id() {
+            // a pane whose pty can't be written to anymore is about to have its process exit,
+            // there's no one to tell that its keys are lost
+            self.write_to_terminal(active_terminal_id, &mut [byte]).ok();
         }
-        // bottom-most border aligned with a pane border to the left
-        let mut bottom_resize_border = self.full_screen_ws.ws_row;
-        for terminal in &terminals {
-            let top_terminal_boundary = terminal.y_coords;
-            if terminal_borders_to_the_left.get(&(top_terminal_boundary)).is_some() && top_terminal_boundary < bottom_resize_border {
-                bottom_resize_border = top_terminal_boundary;
+    }
+    pub fn write_to_pane(&mut self, pane_selector: &PaneSelector, mut bytes: Vec<u8>) -> Result<(), String> {
+        let terminal_id = self.get_terminal_id_by_selector(pane_selector)?;
+        self.write_to_terminal(terminal_id, &mut bytes)
+    }
+    pub fn rename_pane(&mut self, pane_selector: &PaneSelector, name: String) -> Result<(), String> {
+        let terminal_id = self.get_terminal_id_by_selector(pane_selector)?;
+        self.get_terminal_mut(terminal_id).unwrap().name = Some(name);
+        Ok(())
+    }
+    pub fn capture_pane(&self, pane_selector: &PaneSelector, capture_options: CaptureOptions) -> Result<String, String> {
+        let terminal_id = self.get_terminal_id_by_selector(pane_selector)?;
+        let terminal = self.get_terminal(terminal_id).unwrap();
+        Ok(terminal.capture(capture_options.ansi, capture_options.history))
+    }
+    pub fn render (&mut self) {
+        let output = self.vte_output();
+        self.send_server_instructions.send(ServerInstruction::Render(output)).unwrap();
+        self.report_changes();
+    }
+    fn report_changes (&mut self) {
+        // everything that changes the panes ends up rendering them, so rather than reporting each
+        // change where it's made, we compare the panes to what we last reported after rendering
+        let mut events = vec![];
+        for terminal in self.tabs.iter_mut().flat_map(|tab| tab.terminals_mut()) {
+            let id = &terminal.pid;
+            let (x, y, rows, cols) = (terminal.x_coords, terminal.y_coords, terminal.display_rows, terminal.display_cols);
+            let reported_pane = ReportedPane { geometry: (x, y, rows, cols), title: terminal.title.clone() };
+            match self.reported_panes.get(id) {
+                None => events.push(Event::PaneOpened { id: *id, x, y, rows, cols }),
+                Some(previous) if previous.geometry != reported_pane.geometry => {
+                    events.push(Event::PaneResized { id: *id, x, y, rows, cols });
+                }
+                _ => {}
             }
-        }
-        terminals.retain(|terminal| {
-            // terminal.y_coords + terminal.display_rows < bottom_resize_border
-            terminal.y_coords + terminal.display_rows <= bottom_resize_border
-        });
-        // if there are no adjacent panes to resize, we use the border of the main pane we're
-        // resizing
-        let bottom_resize_border = if terminals.is_empty() { terminal_to_check.y_coords + terminal_to_check.display_rows } else { bottom_resize_border };
-        let terminal_ids: Vec<RawFd> = terminals.iter().map(|t| t.pid).collect();
-        (bottom_resize_border, terminal_ids)
-    }
-    fn top_aligned_contiguous_panes_to_the_left(&self, id: &RawFd, terminal_borders_above: &HashSet<u16>) -> BorderAndPaneIds {
-        let mut terminals = vec![];
-        let terminal_to_check = self.terminals.get(id).expect("terminal id does not exist");
-        let mut top_aligned_terminals = self.panes_top_aligned_with_pane(&terminal_to_check);
-        // terminals that are next to each other up to current
-        top_aligned_terminals.sort_by(|a, b| { b.x_coords.cmp(&a.x_coords)});
-        for terminal in top_aligned_terminals {
-            let terminal_to_check = terminals
-                .last()
-                .unwrap_or(&terminal_to_check);
-            if terminal.x_coords + terminal.display_cols + 1 == terminal_to_check.x_coords {
-                terminals.push(terminal);
+            let previous_title = self.reported_panes.get(id).and_then(|previous| previous.title.as_ref());
+            if let Some(title) = terminal.title.as_ref().filter(|title| Some(*title) != previous_title) {
+                events.push(Event::TitleChanged { id: *id, title: title.clone() });
             }
-        }
-        // leftmost border aligned with a pane border above
-        let mut left_resize_border = 0;
-        for terminal in &terminals {
-            let right_terminal_boundary = terminal.x_coords + terminal.display_cols;
-            if terminal_borders_above.get(&(right_terminal_boundary + 1)).is_some() && left_resize_border < right_terminal_boundary {
-                left_resize_border = right_terminal_boundary + 1;
+            if terminal.rang_bell {
+                terminal.rang_bell = false;
+                events.push(Event::Bell { id: *id });
             }
+            self.reported_panes.insert(*id, reported_pane);
         }
-        terminals.retain(|terminal| {
-            terminal.x_coords >= left_resize_border
-        });
-        // if there are no adjacent panes to resize, we use the border of the main pane we're
-        // resizing
-        let left_resize_border = if terminals.is_empty() { terminal_to_check.x_coords } else { left_resize_border };
-        let terminal_ids: Vec<RawFd> = terminals.iter().map(|t| t.pid).collect();
-        (left_resize_border, terminal_ids)
-    }
-    fn top_aligned_contiguous_panes_to_the_right(&self, id: &RawFd, terminal_borders_above: &HashSet<u16>) -> BorderAndPaneIds {
-        let mut terminals = vec![];
-        let terminal_to_check = self.terminals.get(id).unwrap();
-        let mut top_aligned_terminals = self.panes_top_aligned_with_pane(&terminal_to_check);
-        // terminals that are next to each other up to current
-        top_aligned_terminals.sort_by(|a, b| { a.x_coords.cmp(&b.x_coords)});
-        for terminal in top_aligned_terminals {
-            let terminal_to_check = terminals
-                .last()
-                .unwrap_or(&terminal_to_check);
-            if terminal.x_coords == terminal_to_check.x_coords + terminal_to_check.display_cols + 1 {
-                terminals.push(terminal);
-            }
+        let tabs = &self.tabs;
+        let closed_panes: Vec<RawFd> = self.reported_panes.keys()
+            .filter(|id| tabs.iter().all(|tab| tab.get_terminal(**id).is_none()))
+            .copied()
+            .collect();
+        for id in closed_panes {
+            self.reported_panes.remove(&id);
+            events.push(Event::PaneClosed { id });
         }
-        // rightmost border aligned with a pane border above
-        let mut right_resize_border = self.full_screen_ws.ws_col;
-        for terminal in &terminals {
-
-            let left_terminal_boundary = terminal.x_coords;
-            if terminal_borders_above.get(&left_terminal_boundary).is_some() && right_resize_border > left_terminal_boundary {
-                right_resize_border = left_terminal_boundary;
+        let active_terminal = self.active_tab().get_active_terminal_id();
+        if active_terminal != self.reported_focus {
+            self.reported_focus = active_terminal;
+            if let Some(id) = active_terminal {
+                events.push(Event::PaneFocused { id });
             }
         }
-        terminals.retain(|terminal| {
-            terminal.x_coords + terminal.display_cols <= right_resize_border 
-        });
-        // if there are no adjacent panes to resize, we use the border of the main pane we're
-        // resizing
-        let right_resize_border = if terminals.is_empty() { terminal_to_check.x_coords + terminal_to_check.display_cols } else { right_resize_border };
-        let terminal_ids: Vec<RawFd> = terminals.iter().map(|t| t.pid).collect();
-        (right_resize_border, terminal_ids)
-    }
-    fn bottom_aligned_contiguous_panes_to_the_left(&self, id: &RawFd, terminal_borders_below: &HashSet<u16>) -> BorderAndPaneIds {
-        let mut terminals = vec![];
-        let terminal_to_check = self.terminals.get(id).unwrap();
-        let mut bottom_aligned_terminals = self.panes_bottom_aligned_with_pane(&terminal_to_check);
-        bottom_aligned_terminals.sort_by(|a, b| { b.x_coords.cmp(&a.x_coords)});
-        // terminals that are next to each other up to current
-        for terminal in bottom_aligned_terminals {
-            let terminal_to_check = terminals
-                .last()
-                .unwrap_or(&terminal_to_check);
-            if terminal.x_coords + terminal.display_cols + 1 == terminal_to_check.x_coords {
-                terminals.push(terminal);
-            }
+        for event in events {
+            self.send_server_instructions.send(ServerInstruction::Event(event)).unwrap();
         }
-        // leftmost border aligned with a pane border above
-        let mut left_resize_border = 0;
-        for terminal in &terminals {
-            let right_terminal_boundary = terminal.x_coords + terminal.display_cols;
-            if terminal_borders_below.get(&(right_terminal_boundary + 1)).is_some() && left_resize_border < right_terminal_boundary {
-                left_resize_border = right_terminal_boundary + 1;
-            }
+    }
+    pub fn handle_process_exited (&mut self, id: RawFd, exit_code: i32, hold_pane: bool) {
+        if self.get_terminal(id).is_none() {
+            return; // the pane was closed, which is what ended its process
         }
-        terminals.retain(|terminal| {
-            terminal.x_coords >= left_resize_border
-        });
-        // if there are no adjacent panes to resize, we use the border of the main pane we're
-        // resizing
-        let left_resize_border = if terminals.is_empty() { terminal_to_check.x_coords } else { left_resize_border };
-        let terminal_ids: Vec<RawFd> = terminals.iter().map(|t| t.pid).collect();
-        (left_resize_border, terminal_ids)
-    }
-    fn bottom_aligned_contiguous_panes_to_the_right(&self, id: &RawFd, terminal_borders_below: &HashSet<u16>) -> BorderAndPaneIds {
-        let mut terminals = vec![];
-        let terminal_to_check = self.terminals.get(id).unwrap();
-        let mut bottom_aligned_terminals = self.panes_bottom_aligned_with_pane(&terminal_to_check);
-        bottom_aligned_terminals.sort_by(|a, b| { a.x_coords.cmp(&b.x_coords)});
-        // terminals that are next to each other up to current
-        for terminal in bottom_aligned_terminals {
-            let terminal_to_check = terminals
-                .last()
-                .unwrap_or(&terminal_to_check);
-            if terminal.x_coords == terminal_to_check.x_coords + terminal_to_check.display_cols + 1 {
-                terminals.push(terminal);
-            }
+        self.send_pty_instructions.send(PtyInstruction::ProcessExited(id)).unwrap();
+        self.send_server_instructions.send(ServerInstruction::Event(Event::ProcessExited { id, exit_code })).unwrap();
+        if hold_pane {
+            self.get_terminal_mut(id).unwrap().hold_with_exit_code(exit_code);
+            self.render();
+        } else {
+            self.close_pane(id);
         }
-        // leftmost border aligned with a pane border above
-        let mut right_resize_border = self.full_screen_ws.ws_col;
-        for terminal in &terminals {
-            let left_terminal_boundary = terminal.x_coords;
-            if terminal_borders_below.get(&left_terminal_boundary).is_some() && right_resize_border > left_terminal_boundary {
-                right_resize_border = left_terminal_boundary;
-            }
+    }
+    fn vte_output (&mut self) -> String {
+        let (columns, rows) = (self.display_ws.ws_col as usize, self.display_ws.ws_row as usize);
+        if self.output_grid.columns != columns || self.output_grid.rows != rows {
+            // the clients' terminals were resized too, so we can't tell what they show anymore
+            self.output_grid = OutputGrid::new(columns, rows);
+            self.drawn_layout.clear();
         }
-        terminals.retain(|terminal| {
-            terminal.x_coords + terminal.display_cols <= right_resize_border 
-        });
-        let right_resize_border = if terminals.is_empty() { terminal_to_check.x_coords + terminal_to_check.display_cols } else { right_resize_border };
-        let terminal_ids: Vec<RawFd> = terminals.iter().map(|t| t.pid).collect();
-        (right_resize_border, terminal_ids)
-    }
-    fn reduce_pane_height_down(&mut self, id: &RawFd, count: u16) {
-        let terminal = self.terminals.get_mut(id).unwrap();
-        terminal.reduce_height_down(count);
-        self.os_api.set_terminal_size_using_fd(
-            *id,
-            terminal.display_cols,
-            terminal.display_rows
-        );
-    }
-    fn reduce_pane_height_up(&mut self, id: &RawFd, count: u16) {
-        let terminal = self.terminals.get_mut(id).unwrap();
-        terminal.reduce_height_up(count);
-        self.os_api.set_terminal_size_using_fd(
-            *id,
-            terminal.display_cols,
-            terminal.display_rows
-        );
-    }
-    fn increase_pane_height_down(&mut self, id: &RawFd, count: u16) {
-        let terminal = self.terminals.get_mut(&id).unwrap();
-        terminal.increase_height_down(count);
-        self.os_api.set_terminal_size_using_fd(
-            terminal.pid,
-            terminal.display_cols,
-            terminal.display_rows
-        );
-    }
-    fn increase_pane_height_up(&mut self, id: &RawFd, count: u16) {
-        let terminal = self.terminals.get_mut(&id).unwrap();
-        terminal.increase_height_up(count);
-        self.os_api.set_terminal_size_using_fd(
-            terminal.pid,
-            terminal.display_cols,
-            terminal.display_rows
-        );
-    }
-    fn increase_pane_width_right(&mut self, id: &RawFd, count: u16) {
-        let terminal = self.terminals.get_mut(&id).unwrap();
-        terminal.increase_width_right(count);
-        self.os_api.set_terminal_size_using_fd(
-            terminal.pid,
-            terminal.display_cols,
-            terminal.display_rows
-        );
-    }
-    fn increase_pane_width_left(&mut self, id: &RawFd, count: u16) {
-        let terminal = self.terminals.get_mut(&id).unwrap();
-        terminal.increase_width_left(count);
-        self.os_api.set_terminal_size_using_fd(
-            terminal.pid,
-            terminal.display_cols,
-            terminal.display_rows
-        );
-    }
-    fn reduce_pane_width_right(&mut self, id: &RawFd, count: u16) {
-        let terminal = self.terminals.get_mut(&id).unwrap();
-        terminal.reduce_width_right(count);
-        self.os_api.set_terminal_size_using_fd(
-            terminal.pid,
-            terminal.display_cols,
-            terminal.display_rows
-        );
-    }
-    fn reduce_pane_width_left(&mut self, id: &RawFd, count: u16) {
-        let terminal = self.terminals.get_mut(&id).unwrap();
-        terminal.reduce_width_left(count);
-        self.os_api.set_terminal_size_using_fd(
-            terminal.pid,
-            terminal.display_cols,
-            terminal.display_rows
-        );
-    }
-    fn pane_is_between_vertical_borders(&self, id: &RawFd, left_border_x: u16, right_border_x: u16) -> bool {
-        let terminal = self.terminals.get(id).expect("could not find terminal to check between borders");
-        terminal.x_coords >= left_border_x && terminal.x_coords + terminal.display_cols <= right_border_x
-    }
-    fn pane_is_between_horizontal_borders(&self, id: &RawFd, top_border_y: u16, bottom_border_y: u16) -> bool {
-        let terminal = self.terminals.get(id).expect("could not find terminal to check between borders");
-        terminal.y_coords >= top_border_y && terminal.y_coords + terminal.display_rows <= bottom_border_y
-    }
-    fn reduce_pane_and_surroundings_up(&mut self, id: &RawFd, count: u16) {
-        let mut terminals_below = self.terminal_ids_directly_below(&id).expect("can't reduce pane size up if there are no terminals below");
-        let terminal_borders_below: HashSet<u16> = terminals_below.iter().map(|t| self.terminals.get(t).unwrap().x_coords).collect();
-        let (left_resize_border, terminals_to_the_left) = self.bottom_aligned_contiguous_panes_to_the_left(&id, &terminal_borders_below);
-        let (right_resize_border, terminals_to_the_right) = self.bottom_aligned_contiguous_panes_to_the_right(&id, &terminal_borders_below);
-        terminals_below.retain(|t| self.pane_is_between_vertical_borders(t, left_resize_border, right_resize_border));
-        self.reduce_pane_height_up(&id, count);
-        for terminal_id in terminals_below {
-            self.increase_pane_height_up(&terminal_id, count);
+        let layout = self.active_tab().visible_layout();
+        let layout_changed = layout != self.drawn_layout;
+        if layout_changed {
+            // panes that were closed, moved or are in another tab might have left something behind
+            self.output_grid.clear();
+            self.drawn_layout = layout;
         }
-        for terminal_id in terminals_to_the_left.iter().chain(terminals_to_the_right.iter()) {
-            self.reduce_pane_height_up(&terminal_id, count);
+        self.tabs[self.active_tab_index].draw_on(&mut self.output_grid, layout_changed);
+        if self.tabs.len() > 1 {
+            draw_tab_bar(&mut self.output_grid, &self.tabs, self.active_tab_index);
         }
+
+        let mut output = self.output_grid.render_changes();
+        let (cursor_position_x, cursor_position_y) = self.active_tab().get_active_terminal_cursor_position();
+        let goto_cursor_position = format!("\u{1b}[{};{}H\u{1b}[m", cursor_position_y + 1, cursor_position_x + 1); // goto row/col
+        output.push_str(&goto_cursor_position);
+        output
+    }
+    pub fn force_render (&mut self) {
+        // a newly attached client (or one that is larger than the screen has just become) might
+        // have anything on its screen, so we clear it and draw everything rather than only what
+        // changed since the last render
+        self.output_grid.forget_rendered_frame();
+        self.render();
     }
-    fn reduce_pane_and_surroundings_down(&mut self, id: &RawFd, count: u16) {
-        let mut terminals_above = self.terminal_ids_directly_above(&id).expect("can't reduce pane size down if there are no terminals above");
-        let terminal_borders_above: HashSet<u16> = terminals_above.iter().map(|t| self.terminals.get(t).unwrap().x_coords).collect();
-        let (left_resize_border, terminals_to_the_left) = self.top_aligned_contiguous_panes_to_the_left(&id, &terminal_borders_above);
-        let (right_resize_border, terminals_to_the_right) = self.top_aligned_contiguous_panes_to_the_right(&id, &terminal_borders_above);
-        terminals_above.retain(|t| self.pane_is_between_vertical_borders(t, left_resize_border, right_resize_border));
-        self.reduce_pane_height_down(&id, count);
-        for terminal_id in terminals_above {
-            self.increase_pane_height_down(&terminal_id, count);
-        }
-        for terminal_id in terminals_to_the_left.iter().chain(terminals_to_the_right.iter()) {
-            self.reduce_pane_height_down(&terminal_id, count);
+    pub fn resize_to_screen (&mut self, new_screen_size: Winsize) {
+        // the layouts keep the ratios between the panes rather than their sizes, so they are
+        // resized in proportion to the screen
+        self.display_ws = new_screen_size;
+        let area = tab_area(&new_screen_size, self.tabs.len());
+        if !self.tabs.iter().all(|tab| tab.fits(area.cols, area.rows)) {
+            // the screen is too small for the current layouts, we keep them as they are rather
+            // than squash panes out of existence, and only show as much of them as fits
+            self.force_render();
+            return;
         }
+        self.full_screen_ws = new_screen_size;
+        self.resize_tabs();
+        self.force_render();
     }
-    fn reduce_pane_and_surroundings_right(&mut self, id: &RawFd, count: u16) {
-        let mut terminals_to_the_left = self.terminal_ids_directly_left_of(&id).expect("can't reduce pane size right if there are no terminals to the left");
-        let terminal_borders_to_the_left: HashSet<u16> = terminals_to_the_left.iter().map(|t| self.terminals.get(t).unwrap().y_coords).collect();
-        let (top_resize_border, terminals_above) = self.left_aligned_contiguous_panes_above(&id, &terminal_borders_to_the_left);
-        let (bottom_resize_border, terminals_below) = self.left_aligned_contiguous_panes_below(&id, &terminal_borders_to_the_left);
-        terminals_to_the_left.retain(|t| self.pane_is_between_horizontal_borders(t, top_resize_border, bottom_resize_border));
-        self.reduce_pane_width_right(&id, count);
-        for terminal_id in terminals_to_the_left {
-            self.increase_pane_width_right(&terminal_id, count);
-        }
-        for terminal_id in terminals_above.iter().chain(terminals_below.iter()) {
-            self.reduce_pane_width_right(&terminal_id, count);
+    fn resize_active_pane (&mut self, direction: SplitDirection, preferred_edge: Edge, count: i32) {
+        if self.active_tab_mut().resize_active_pane(direction, preferred_edge, count) {
+            self.render();
         }
     }
-    fn reduce_pane_and_surroundings_left(&mut self, id: &RawFd, count: u16) {
-        let mut terminals_to_the_right = self.terminal_ids_directly_right_of(&id).expect("can't reduce pane size left if there are no terminals to the right");
-        let terminal_borders_to_the_right: HashSet<u16> = terminals_to_the_right.iter().map(|t| self.terminals.get(t).unwrap().y_coords).collect();
-        let (top_resize_border, terminals_above) = self.right_aligned_contiguous_panes_above(&id, &terminal_borders_to_the_right);
-        let (bottom_resize_border, terminals_below) = self.right_aligned_contiguous_panes_below(&id, &terminal_borders_to_the_right);
-        terminals_to_the_right.retain(|t| self.pane_is_between_horizontal_borders(t, top_resize_border, bottom_resize_border));
-        self.reduce_pane_width_left(&id, count);
-        for terminal_id in terminals_to_the_right {
-            self.increase_pane_width_left(&terminal_id, count);
-        }
-        for terminal_id in terminals_above.iter().chain(terminals_below.iter()) {
-            self.reduce_pane_width_left(&terminal_id, count);
-        }
+    pub fn resize_right (&mut self) {
+        self.resize_active_pane(SplitDirection::Vertical, Edge::End, 10);
     }
-    fn increase_pane_and_surroundings_up(&mut self, id: &RawFd, count: u16) {
-        let mut terminals_above = self.terminal_ids_directly_above(&id).expect("can't increase pane size up if there are no terminals above");
-        let terminal_borders_above: HashSet<u16> = terminals_above.iter().map(|t| self.terminals.get(t).unwrap().x_coords).collect();
-        let (left_resize_border, terminals_to_the_left) = self.top_aligned_contiguous_panes_to_the_left(&id, &terminal_borders_above);
-        let (right_resize_border, terminals_to_the_right) = self.top_aligned_contiguous_panes_to_the_right(&id, &terminal_borders_above);
-        terminals_above.retain(|t| self.pane_is_between_vertical_borders(t, left_resize_border, right_resize_border));
-        self.increase_pane_height_up(&id, count);
-        for terminal_id in terminals_above {
-            self.reduce_pane_height_up(&terminal_id, count);
-        }
-        for terminal_id in terminals_to_the_left.iter().chain(terminals_to_the_right.iter()) {
-            self.increase_pane_height_up(&terminal_id, count);
-        }
+    pub fn resize_left (&mut self) {
+        self.resize_active_pane(SplitDirection::Vertical, Edge::End, -10);
     }
-    fn increase_pane_and_surroundings_down(&mut self, id: &RawFd, count: u16) {
-        let mut terminals_below = self.terminal_ids_directly_below(&id).expect("can't increase pane size down if there are no terminals below");
-        let terminal_borders_below: HashSet<u16> = terminals_below.iter().map(|t| self.terminals.get(t).unwrap().x_coords).collect();
-        let (left_resize_border, terminals_to_the_left) = self.bottom_aligned_contiguous_panes_to_the_left(&id, &terminal_borders_below);
-        let (right_resize_border, terminals_to_the_right) = self.bottom_aligned_contiguous_panes_to_the_right(&id, &terminal_borders_below);
-        terminals_below.retain(|t| self.pane_is_between_vertical_borders(t, left_resize_border, right_resize_border));
-        self.increase_pane_height_down(&id, count);
-        for terminal_id in terminals_below {
-            self.reduce_pane_height_down(&terminal_id, count);
-        }
-        for terminal_id in terminals_to_the_left.iter().chain(terminals_to_the_right.iter()) {
-            self.increase_pane_height_down(&terminal_id, count);
-        }
+    pub fn resize_down (&mut self) {
+        self.resize_active_pane(SplitDirection::Horizontal, Edge::Start, 2);
     }
-    fn increase_pane_and_surroundings_right(&mut self, id: &RawFd, count: u16) {
-        let mut terminals_to_the_right = self.terminal_ids_directly_right_of(&id).expect("can't increase pane size right if there are no terminals to the right");
-        let terminal_borders_to_the_right: HashSet<u16> = terminals_to_the_right.iter().map(|t| self.terminals.get(t).unwrap().y_coords).collect();
-        let (top_resize_border, terminals_above) = self.right_aligned_contiguous_panes_above(&id, &terminal_borders_to_the_right);
-        let (bottom_resize_border, terminals_below) = self.right_aligned_contiguous_panes_below(&id, &terminal_borders_to_the_right);
-        terminals_to_the_right.retain(|t| self.pane_is_between_horizontal_borders(t, top_resize_border, bottom_resize_border));
-        self.increase_pane_width_right(&id, count);
-        for terminal_id in terminals_to_the_right {
-            self.reduce_pane_width_right(&terminal_id, count);
-        }
-        for terminal_id in terminals_above.iter().chain(terminals_below.iter()) {
-            self.increase_pane_width_right(&terminal_id, count);
-        }
+    pub fn resize_up (&mut self) {
+        self.resize_active_pane(SplitDirection::Horizontal, Edge::Start, -2);
     }
-    fn increase_pane_and_surroundings_left(&mut self, id: &RawFd, count: u16) {
-        let mut terminals_to_the_left = self.terminal_ids_directly_left_of(&id).expect("can't increase pane size right if there are no terminals to the right");
-        let terminal_borders_to_the_left: HashSet<u16> = terminals_to_the_left.iter().map(|t| self.terminals.get(t).unwrap().y_coords).collect();
-        let (top_resize_border, terminals_above) = self.left_aligned_contiguous_panes_above(&id, &terminal_borders_to_the_left);
-        let (bottom_resize_border, terminals_below) = self.left_aligned_contiguous_panes_below(&id, &terminal_borders_to_the_left);
-        terminals_to_the_left.retain(|t| self.pane_is_between_horizontal_borders(t, top_resize_border, bottom_resize_border));
-        self.increase_pane_width_left(&id, count);
-        for terminal_id in terminals_to_the_left {
-            self.reduce_pane_width_left(&terminal_id, count);
-        }
-        for terminal_id in terminals_above.iter().chain(terminals_below.iter()) {
-            self.increase_pane_width_left(&terminal_id, count);
+    pub fn move_focus(&mut self) {
+        if self.active_tab_mut().move_focus() {
+            self.render();
         }
     }
-    fn panes_exist_above(&self, pane_id: &RawFd) -> bool {
-        let pane = self.terminals.get(pane_id).expect("pane does not exist");
-        pane.y_coords > 0
+    pub fn move_focus_left(&mut self) {
+        self.move_focus_towards(Direction::Left);
     }
-    fn panes_exist_below(&self, pane_id: &RawFd) -> bool {
-        let pane = self.terminals.get(pane_id).expect("pane does not exist");
-        pane.y_coords + pane.display_rows < self.full_screen_ws.ws_row
+    pub fn move_focus_right(&mut self) {
+        self.move_focus_towards(Direction::Right);
     }
-    fn panes_exist_to_the_right(&self, pane_id: &RawFd) -> bool {
-        let pane = self.terminals.get(pane_id).expect("pane does not exist");
-        pane.x_coords + pane.display_cols < self.full_screen_ws.ws_col
+    pub fn move_focus_up(&mut self) {
+        self.move_focus_towards(Direction::Up);
     }
-    fn panes_exist_to_the_left(&self, pane_id: &RawFd) -> bool {
-        let pane = self.terminals.get(pane_id).expect("pane does not exist");
-        pane.x_coords > 0
+    pub fn move_focus_down(&mut self) {
+        self.move_focus_towards(Direction::Down);
     }
-    pub fn resize_right (&mut self) {
-        // TODO: find out by how much we actually reduced and only reduce by that much
-        let count = 10;
-        if let Some(active_terminal_id) = self.get_active_terminal_id() {
-            if self.panes_exist_to_the_right(&active_terminal_id) {
-                self.increase_pane_and_surroundings_right(&active_terminal_id, count);
-                self.render();
-            } else if self.panes_exist_to_the_left(&active_terminal_id) {
-                self.reduce_pane_and_surroundings_right(&active_terminal_id, count);
-                self.render();
-            }
+    pub fn move_focus_towards(&mut self, direction: Direction) {
+        if self.active_tab_mut().move_focus_towards(direction) {
+            self.render();
         }
     }
-    pub fn resize_left (&mut self) {
-        // TODO: find out by how much we actually reduced and only reduce by that much
-        let count = 10;
-        if let Some(active_terminal_id) = self.get_active_terminal_id() {
-            if self.panes_exist_to_the_right(&active_terminal_id) {
-                self.reduce_pane_and_surroundings_left(&active_terminal_id, count);
-                self.render();
-            } else if self.panes_exist_to_the_left(&active_terminal_id) {
-                self.increase_pane_and_surroundings_left(&active_terminal_id, count);
-                self.render();
-            }
+    pub fn swap_with_pane_towards(&mut self, direction: Direction) {
+        if self.active_tab_mut().swap_with_pane_towards(direction) {
+            self.render();
         }
     }
-    pub fn resize_down (&mut self) {
-        // TODO: find out by how much we actually reduced and only reduce by that much
-        let count = 2;
-        if let Some(active_terminal_id) = self.get_active_terminal_id() {
-            if self.panes_exist_above(&active_terminal_id) {
-                self.reduce_pane_and_surroundings_down(&active_terminal_id, count);
-                self.render();
-            } else if self.panes_exist_below(&active_terminal_id) {
-                self.increase_pane_and_surroundings_down(&active_terminal_id, count);
-                self.render();
-            }
+    pub fn swap_with_next_pane(&mut self) {
+        if self.active_tab_mut().swap_with_next_pane() {
+            self.render();
         }
     }
-    pub fn resize_up (&mut self) {
-        // TODO: find out by how much we actually reduced and only reduce by that much
-        let count = 2;
-        if let Some(active_terminal_id) = self.get_active_terminal_id() {
-            if self.panes_exist_above(&active_terminal_id) {
-                self.increase_pane_and_surroundings_up(&active_terminal_id, count);
-                self.render();
-            } else if self.panes_exist_below(&active_terminal_id) {
-                self.reduce_pane_and_surroundings_up(&active_terminal_id, count);
-                self.render();
-            }
+    pub fn move_pane_to_position(&mut self, position: usize) {
+        if self.active_tab_mut().move_pane_to_position(position) {
+            self.render();
         }
     }
-    pub fn move_focus(&mut self) {
-        if self.terminals.is_empty() {
-            return;
-        }
-        let active_terminal_id = self.get_active_terminal_id().unwrap();
-        let terminal_ids: Vec<RawFd> = self.terminals.keys().copied().collect(); // TODO: better, no allocations
-        let first_terminal = terminal_ids.get(0).unwrap();
-        let active_terminal_id_position = terminal_ids.iter().position(|id| id == &active_terminal_id).unwrap();
-        if let Some(next_terminal) = terminal_ids.get(active_terminal_id_position + 1) {
-            self.active_terminal = Some(*next_terminal);
-        } else {
-            self.active_terminal = Some(*first_terminal);
+    pub fn focus_previous_pane(&mut self) {
+        if self.active_tab_mut().focus_previous_pane() {
+            self.render();
         }
-        self.render();
     }
-    fn horizontal_borders(&self, terminals: &[RawFd]) -> HashSet<u16> {
-        terminals.iter().fold(HashSet::new(), |mut borders, t| {
-            let terminal = self.terminals.get(t).unwrap();
-            borders.insert(terminal.y_coords);
-            borders.insert(terminal.y_coords + terminal.display_rows + 1); // 1 for the border width
-            borders
-        })
-    }
-    fn vertical_borders(&self, terminals: &[RawFd]) -> HashSet<u16> {
-        terminals.iter().fold(HashSet::new(), |mut borders, t| {
-            let terminal = self.terminals.get(t).unwrap();
-            borders.insert(terminal.x_coords);
-            borders.insert(terminal.x_coords + terminal.display_cols + 1); // 1 for the border width
-            borders
-        })
-    }
-    fn terminals_to_the_left_between_aligning_borders(&self, id: RawFd) -> Option<Vec<RawFd>> {
-        if let Some(terminal) = &self.terminals.get(&id) {
-            let upper_close_border = terminal.y_coords;
-            let lower_close_border = terminal.y_coords + terminal.display_rows + 1;
-
-            if let Some(mut terminals_to_the_left) = self.terminal_ids_directly_left_of(&id) {
-                let terminal_borders_to_the_left = self.horizontal_borders(&terminals_to_the_left);
-                if terminal_borders_to_the_left.contains(&upper_close_border) && terminal_borders_to_the_left.contains(&lower_close_border) {
-                    terminals_to_the_left.retain(|t| self.pane_is_between_horizontal_borders(t, upper_close_border, lower_close_border));
-                    return Some(terminals_to_the_left);
-                }
-            }
+    pub fn toggle_zoom(&mut self) {
+        if self.active_tab_mut().toggle_zoom() {
+            self.render();
         }
-        None
     }
-    fn terminals_to_the_right_between_aligning_borders(&self, id: RawFd) -> Option<Vec<RawFd>> {
-        if let Some(terminal) = &self.terminals.get(&id) {
-            let upper_close_border = terminal.y_coords;
-            let lower_close_border = terminal.y_coords + terminal.display_rows + 1;
-
-            if let Some(mut terminals_to_the_right) = self.terminal_ids_directly_right_of(&id) {
-                let terminal_borders_to_the_right = self.horizontal_borders(&terminals_to_the_right);
-                if terminal_borders_to_the_right.contains(&upper_close_border) && terminal_borders_to_the_right.contains(&lower_close_border) {
-                    terminals_to_the_right.retain(|t| self.pane_is_between_horizontal_borders(t, upper_close_border, lower_close_border));
-                    return Some(terminals_to_the_right);
-                }
-            }
+    pub fn clear_notice(&mut self) {
+        // whatever was under it is drawn again, if there was one
+        if self.active_tab_mut().clear_notice() {
+            self.drawn_layout.clear();
+            self.render();
         }
-        None
     }
-    fn terminals_above_between_aligning_borders(&self, id: RawFd) -> Option<Vec<RawFd>> {
-        if let Some(terminal) = &self.terminals.get(&id) {
-            let left_close_border = terminal.x_coords;
-            let right_close_border = terminal.x_coords + terminal.display_cols + 1;
-
-            if let Some(mut terminals_above) = self.terminal_ids_directly_above(&id) {
-                let terminal_borders_above = self.vertical_borders(&terminals_above);
-                if terminal_borders_above.contains(&left_close_border) && terminal_borders_above.contains(&right_close_border) {
-                    terminals_above.retain(|t| self.pane_is_between_vertical_borders(t, left_close_border, right_close_border));
-                    return Some(terminals_above);
-                }
-            }
+    pub fn toggle_floating_panes(&mut self) {
+        if !self.active_tab().has_floating_panes() {
+            // there is nothing to show yet, so this opens the first one
+            self.send_pty_instructions.send(PtyInstruction::SpawnFloatingTerminal(None)).unwrap();
+        } else if self.active_tab_mut().toggle_floating_panes() {
+            self.render();
         }
-        None
     }
-    fn terminals_below_between_aligning_borders(&self, id: RawFd) -> Option<Vec<RawFd>> {
-        if let Some(terminal) = &self.terminals.get(&id) {
-            let left_close_border = terminal.x_coords;
-            let right_close_border = terminal.x_coords + terminal.display_cols + 1;
-
-            if let Some(mut terminals_below) = self.terminal_ids_directly_below(&id) {
-                let terminal_borders_below = self.vertical_borders(&terminals_below);
-                if terminal_borders_below.contains(&left_close_border) && terminal_borders_below.contains(&right_close_border) {
-                    terminals_below.retain(|t| self.pane_is_between_vertical_borders(t, left_close_border, right_close_border));
-                    return Some(terminals_below);
-                }
-            }
-        }
-        None
-    }
-    fn close_down_to_max_terminals (&mut self) {
-        if let Some(max_panes) = self.max_panes {
-            if self.terminals.len() >= max_panes {
-                for _ in max_panes..=self.terminals.len() {
-                    let first_pid = *self.terminals.iter().next().unwrap().0;
-                    self.send_pty_instructions.send(PtyInstruction::ClosePane(first_pid)).unwrap();
-                    self.close_pane_without_rerender(first_pid); // TODO: do not render yet
-                }
-            }
-        }
+    pub fn new_floating_pane(&mut self, pid: RawFd) {
+        self.active_tab_mut().add_floating_pane(pid);
+        self.render();
     }
     pub fn close_pane(&mut self, id: RawFd) {
-        if self.terminals.get(&id).is_some() {
-            self.close_pane_without_rerender(id);
-            self.render();
-        }
-    }
-    pub fn close_pane_without_rerender(&mut self, id: RawFd) {
-        if let Some(terminal_to_close) = &self.terminals.get(&id) {
-            let terminal_to_close_width = terminal_to_close.display_cols;
-            let terminal_to_close_height = terminal_to_close.display_rows;
-            if let Some(terminals) = self.terminals_to_the_left_between_aligning_borders(id) {
-                for terminal_id in terminals.iter() {
-                    &self.increase_pane_width_right(&terminal_id, terminal_to_close_width + 1); // 1 for the border
-                }
-                if self.active_terminal == Some(id) {
-                    self.active_terminal = Some(*terminals.last().unwrap());
-                }
-            } else if let Some(terminals) = self.terminals_to_the_right_between_aligning_borders(id) {
-                for terminal_id in terminals.iter() {
-                    &self.increase_pane_width_left(&terminal_id, terminal_to_close_width + 1); // 1 for the border
-                }
-                if self.active_terminal == Some(id) {
-                    self.active_terminal = Some(*terminals.last().unwrap());
-                }
-            } else if let Some(terminals) = self.terminals_above_between_aligning_borders(id) {
-                for terminal_id in terminals.iter() {
-                    &self.increase_pane_height_down(&terminal_id, terminal_to_close_height + 1); // 1 for the border
-                }
-                if self.active_terminal == Some(id) {
-                    self.active_terminal = Some(*terminals.last().unwrap());
-                }
-            } else if let Some(terminals) = self.terminals_below_between_aligning_borders(id) {
-                for terminal_id in terminals.iter() {
-                    &self.increase_pane_height_up(&terminal_id, terminal_to_close_height + 1); // 1 for the border
-                }
-                if self.active_terminal == Some(id) {
-                    self.active_terminal = Some(*terminals.last().unwrap());
+        if let Some(tab_index) = self.tab_index_with_pane(id) {
+            if self.tabs[tab_index].is_last_tiled_pane(id) && self.tabs.len() > 1 {
+                // the last tiled pane of a tab takes the tab with it, floating panes and all
+                let tab = &mut self.tabs[tab_index];
+                for other_id in tab.pane_ids().into_iter().filter(|other_id| *other_id != id) {
+                    tab.close_pane_process(other_id);
                 }
+                self.remove_tab(tab_index);
             } else {
-                return; // TODO: exit app? here we're trying to close the last pane on screen
+                self.tabs[tab_index].close_pane(id);
+                self.drop_pending_input(id);
             }
-            self.terminals.remove(&id);
+            self.render();
         }
     }
     pub fn close_focused_pane(&mut self) {
-        if let Some(active_terminal_id) = self.get_active_terminal_id() {
-            self.send_pty_instructions.send(PtyInstruction::ClosePane(active_terminal_id)).unwrap();
+        if let Some(active_terminal_id) = self.active_tab().get_active_terminal_id() {
+            self.active_tab_mut().close_pane_process(active_terminal_id);
             self.close_pane(active_terminal_id);
         }
     }
+    pub fn rerun_focused_pane(&mut self) {
+        if let Some(active_terminal_id) = self.active_tab().get_active_terminal_id() {
+            if self.get_terminal(active_terminal_id).unwrap().exit_code.is_some() {
+                self.send_pty_instructions.send(PtyInstruction::RerunCommand(active_terminal_id)).unwrap();
+            }
+        }
+    }
+    pub fn replace_pane(&mut self, old_id: RawFd, new_id: RawFd) {
+        if let Some(tab_index) = self.tab_index_with_pane(old_id) {
+            self.tabs[tab_index].replace_pane(old_id, new_id);
+            self.drop_pending_input(old_id);
+            self.render();
+        } else {
+            // the pane was closed while its command was being re-run
+            self.send_pty_instructions.send(PtyInstruction::ClosePane(new_id)).unwrap();
+        }
+    }
     pub fn scroll_active_terminal_up(&mut self) {
-        if let Some(active_terminal_id) = self.get_active_terminal_id() {
-            let active_terminal = self.terminals.get_mut(&active_terminal_id).unwrap();
-            active_terminal.scroll_up(1);
+        if self.active_tab_mut().scroll_active_terminal_up() {
             self.render();
         }
     }
     pub fn scroll_active_terminal_down(&mut self) {
-        if let Some(active_terminal_id) = self.get_active_terminal_id() {
-            let active_terminal = self.terminals.get_mut(&active_terminal_id).unwrap();
-            active_terminal.scroll_down(1);
+        if self.active_tab_mut().scroll_active_terminal_down() {
             self.render();
         }
     }
     pub fn clear_active_terminal_scroll(&mut self) {
-        if let Some(active_terminal_id) = self.get_active_terminal_id() {
-            let active_terminal = self.terminals.get_mut(&active_terminal_id).unwrap();
-            active_terminal.clear_scroll();
+        self.active_tab_mut().clear_active_terminal_scroll();
+    }
+}
+
+// writes to the pane until its pty is full, returning how much of the bytes were written
+fn write_while_tty_takes_it(os_api: &mut Box<dyn ServerOsApi>, id: RawFd, bytes: &mut [u8]) -> Result<usize, nix::Error> {
+    let mut written = 0;
+    while written < bytes.len() {
+        match os_api.write_to_tty_stdin(id, &mut bytes[written..]) {
+            Ok(count) => written += count,
+            Err(nix::Error::Sys(nix::errno::Errno::EINTR)) => {}
+            Err(nix::Error::Sys(nix::errno::Errno::EAGAIN)) => break,
+            Err(error) => return Err(error),
+        }
+    }
+    Ok(written)
+}
+
+fn write_pending_input(id: RawFd, mut os_api: Box<dyn ServerOsApi>, pending_input: Arc<Mutex<PendingInput>>) {
+    loop {
+        let mut bytes = {
+            let mut pending_input = pending_input.lock().unwrap();
+            if pending_input.bytes.is_empty() || pending_input.pane_closed {
+                pending_input.writing = false;
+                return;
+            }
+            ::std::mem::take(&mut pending_input.bytes)
+        };
+        let mut written = 0;
+        while written < bytes.len() {
+            match write_while_tty_takes_it(&mut os_api, id, &mut bytes[written..]) {
+                Ok(count) => written += count,
+                Err(_) => {
+                    // the pane's process is gone, and so is what it would have read
+                    let mut pending_input = pending_input.lock().unwrap();
+                    pending_input.bytes.clear();
+                    pending_input.writing = false;
+                    return;
+                }
+            }
+            if written < bytes.len() {
+                if pending_input.lock().unwrap().pane_closed {
+                    return;
+                }
+                // it doesn't wait for longer, so that it notices when the pane is closed
+                os_api.wait_until_tty_writable(id, Duration::from_millis(100));
+            }
         }
+        os_api.tcdrain(id).ok();
+    }
+}
+
+fn tab_area (screen_ws: &Winsize, tab_count: usize) -> PaneGeometry {
+    // where the panes of each tab are laid out, below the tab bar if there is one
+    let tab_bar_rows = if tab_count > 1 { 1 } else { 0 };
+    PaneGeometry {
+        x: 0,
+        y: tab_bar_rows,
+        rows: screen_ws.ws_row.saturating_sub(tab_bar_rows),
+        cols: screen_ws.ws_col,
+    }
+}
+
+fn draw_tab_bar (output_grid: &mut OutputGrid, tabs: &[Tab], active_tab_index: usize) {
+    // eg. " 1  2:logs  3 " with the tab that is shown in reverse video, over the whole first row
+    let active_styles = CharacterStyles::new().reverse(Some(AnsiCode::Code((Some(7), None))));
+    let mut column = 0;
+    for (index, tab) in tabs.iter().enumerate() {
+        let label = match &tab.name {
+            Some(name) => format!(" {}:{} ", index + 1, name),
+            None => format!(" {} ", index + 1),
+        };
+        let styles = if index == active_tab_index { active_styles } else { EMPTY_TERMINAL_CHARACTER.styles };
+        for character in label.chars() {
+            output_grid.set_cell(column, 0, TerminalCharacter { character, styles });
+            column += 1;
+        }
+    }
+    for column in column..output_grid.columns {
+        output_grid.set_cell(column, 0, EMPTY_TERMINAL_CHARACTER);
     }
 }
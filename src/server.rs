@@ -0,0 +1,719 @@
+use ::std::collections::BTreeMap;
+use ::std::os::unix::net::UnixStream;
+use ::std::net::Shutdown;
+use ::std::sync::mpsc::{channel, sync_channel, Sender, SyncSender, Receiver};
+use ::std::thread;
+use ::std::time::{Duration, SystemTime, UNIX_EPOCH};
+use ::nix::pty::Winsize;
+use ::serde::Serialize;
+
+use crate::exit_with_error;
+use crate::input::{handle_client_input, CommandBinding};
+use crate::ipc::{ApiCommand, AttachOptions, ClientConnections, Direction, Event, ExitReason, ServerToClientMsg, SessionInfo, SessionState, SplitDirection, TerminalSize};
+use crate::os_input_output::ServerOsApi;
+use crate::pty_bus::{OutputRate, PtyBus, PtyInstruction, TerminalAction};
+use crate::screen::{Screen, ScreenInstruction};
+use crate::layout::MinPaneSize;
+use crate::layout_file::LayoutFile;
+use crate::Opt;
+
+/*
+ * Server
+ *
+ * the server runs in the background and owns the session: its panes, their ptys and the screen
+ * clients attach to it through the session's socket. they forward their user's input and draw
+ * whatever the server renders, so that closing a client's terminal doesn't end the session
+ *
+ * any number of clients can be attached at the same time, all of them see the same screen. it is
+ * sized to fit the smallest of them, other than those that asked to be ignored when sizing it
+ *
+ */
+
+pub type ClientId = usize;
+
+pub enum ServerInstruction {
+    Render(String),
+    AttachClient(UnixStream, TerminalSize, AttachOptions),
+    DetachClient(ClientId),
+    ResizeClient(ClientId, TerminalSize),
+    GetClientCount(Sender<usize>),
+    Subscribe(UnixStream),
+    Event(Event),
+    Quit,
+}
+
+// how many messages can be waiting to be written to a connection. a client or subscriber that falls
+// this far behind (eg. a suspended ssh session, or a subscriber that stopped reading) is
+// disconnected, rather than holding up the session for everyone else
+const MAX_QUEUED_MESSAGES: usize = 256;
+// and how long writing a single message can take, so that closing a connection never hangs
+const WRITE_TIMEOUT: Duration = Duration::from_secs(5);
+
+// writes the messages sent to a connection on a thread of its own
+struct Connection<T> {
+    stream: UnixStream,
+    send_messages: SyncSender<T>,
+    writer_thread: thread::JoinHandle<()>,
+}
+
+impl<T: Serialize + Send + 'static> Connection<T> {
+    pub fn new(stream: UnixStream, thread_name: String) -> Self {
+        let (send_messages, receive_messages) = sync_channel::<T>(MAX_QUEUED_MESSAGES);
+        let mut writer_stream = stream.try_clone().expect("failed to clone stream");
+        writer_stream.set_write_timeout(Some(WRITE_TIMEOUT)).ok();
+        let writer_thread = thread::Builder::new()
+            .name(thread_name)
+            .spawn(move || {
+                for message in receive_messages {
+                    if bincode::serialize_into(&mut writer_stream, &message).is_err() {
+                        break;
+                    }
+                }
+                // this also ends the thread reading a client's input
+                writer_stream.shutdown(Shutdown::Both).ok();
+            }).unwrap();
+        Connection { stream, send_messages, writer_thread }
+    }
+    // false once the connection went away or fell too far behind, and was closed
+    pub fn send(&mut self, message: T) -> bool {
+        if self.send_messages.try_send(message).is_ok() {
+            true
+        } else {
+            self.stream.shutdown(Shutdown::Both).ok();
+            false
+        }
+    }
+    // the connection is closed once everything sent to it was written
+    pub fn close(self) -> thread::JoinHandle<()> {
+        self.writer_thread
+    }
+}
+
+struct Client {
+    id: ClientId,
+    connection: Connection<ServerToClientMsg>,
+    size: TerminalSize,
+    attach_options: AttachOptions,
+}
+
+impl Client {
+    pub fn new(id: ClientId, stream: UnixStream, size: TerminalSize, attach_options: AttachOptions) -> Self {
+        let connection = Connection::new(stream, format!("client_output_{}", id));
+        Client { id, connection, size, attach_options }
+    }
+    pub fn send(&mut self, message: ServerToClientMsg) -> bool {
+        self.connection.send(message)
+    }
+    pub fn exit(mut self, reason: ExitReason) -> thread::JoinHandle<()> {
+        self.send(ServerToClientMsg::Exit(reason));
+        self.connection.close()
+    }
+}
+
+// sent when the screen thread ends, including when it panics: the session can't go on without it
+// and its clients would otherwise be left waiting for it
+struct QuitOnDrop(Sender<ServerInstruction>);
+
+impl Drop for QuitOnDrop {
+    fn drop(&mut self) {
+        self.0.send(ServerInstruction::Quit).ok();
+    }
+}
+
+fn screen_size_for_clients(clients: &BTreeMap<ClientId, Client>) -> Option<Winsize> {
+    let sizes = clients.values()
+        .filter(|client| !client.attach_options.ignore_size)
+        .map(|client| client.size);
+    let rows = sizes.clone().map(|size| size.rows).min()?;
+    let cols = sizes.map(|size| size.cols).min()?;
+    Some(Winsize {
+        ws_row: rows,
+        ws_col: cols,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    })
+}
+
+fn wait_for_first_client(client_connections: &mut ClientConnections) -> Option<(UnixStream, TerminalSize, AttachOptions)> {
+    // the screen can only be created once we know the size of the terminal it will be drawn on,
+    // until then we ignore everything other than the client attaching (this also skips the
+    // connections made by the process that started us to check whether we're listening yet)
+    for mut stream in client_connections {
+        if let Ok(ApiCommand::AttachClient(terminal_size, attach_options)) = bincode::deserialize_from(&mut stream) {
+            return Some((stream, terminal_size, attach_options));
+        }
+    }
+    None
+}
+
+fn start_client_input_thread(
+    client: &Client,
+    command_bindings: &[CommandBinding],
+    send_screen_instructions: &Sender<ScreenInstruction>,
+    send_pty_instructions: &Sender<PtyInstruction>,
+    send_server_instructions: &Sender<ServerInstruction>,
+) {
+    let client_id = client.id;
+    let read_only = client.attach_options.read_only;
+    let command_bindings = command_bindings.to_vec();
+    let stream = client.connection.stream.try_clone().expect("failed to clone client stream");
+    let send_screen_instructions = send_screen_instructions.clone();
+    let send_pty_instructions = send_pty_instructions.clone();
+    let send_server_instructions = send_server_instructions.clone();
+    thread::Builder::new()
+        .name(format!("client_input_{}", client_id))
+        .spawn(move || {
+            handle_client_input(stream, client_id, read_only, command_bindings, send_screen_instructions, send_pty_instructions, send_server_instructions);
+        }).unwrap();
+}
+
+fn handle_connection(
+    mut stream: UnixStream,
+    session_name: &str,
+    created_at: u64,
+    send_screen_instructions: &Sender<ScreenInstruction>,
+    send_pty_instructions: &Sender<PtyInstruction>,
+    send_server_instructions: &Sender<ServerInstruction>,
+) {
+    let decoded: ApiCommand = match bincode::deserialize_from(&mut stream) {
+        Ok(decoded) => decoded,
+        // this happens when other mosaic processes connect only to check
+        // whether this session is still alive
+        Err(_) => return,
+    };
+    match decoded {
+        ApiCommand::OpenFile(file_name) => {
+            let (send_result, receive_result) = channel();
+            send_pty_instructions.send(PtyInstruction::OpenFile(file_name, send_result)).unwrap();
+            bincode::serialize_into(&mut stream, &receive_result.recv().unwrap()).ok();
+        }
+        ApiCommand::RunCommand(run_command, split_direction) => {
+            let terminal_action = Some(TerminalAction::RunCommand(run_command));
+            let pty_instruction = match split_direction {
+                None => PtyInstruction::SpawnTerminal(terminal_action),
+                Some(SplitDirection::Horizontal) => PtyInstruction::SpawnTerminalHorizontally(terminal_action),
+                Some(SplitDirection::Vertical) => PtyInstruction::SpawnTerminalVertically(terminal_action),
+            };
+            send_pty_instructions.send(pty_instruction).unwrap();
+        }
+        ApiCommand::SplitHorizontally => {
+            send_pty_instructions.send(PtyInstruction::SpawnTerminalHorizontally(None)).unwrap();
+        }
+        ApiCommand::SplitVertically => {
+            send_pty_instructions.send(PtyInstruction::SpawnTerminalVertically(None)).unwrap();
+        }
+        ApiCommand::MoveFocus => {
+            send_screen_instructions.send(ScreenInstruction::MoveFocus).unwrap();
+        }
+        ApiCommand::MoveFocusTowards(direction) => {
+            let screen_instruction = match direction {
+                Direction::Left => ScreenInstruction::MoveFocusLeft,
+                Direction::Right => ScreenInstruction::MoveFocusRight,
+                Direction::Up => ScreenInstruction::MoveFocusUp,
+                Direction::Down => ScreenInstruction::MoveFocusDown,
+            };
+            send_screen_instructions.send(screen_instruction).unwrap();
+        }
+        ApiCommand::FocusPreviousPane => {
+            send_screen_instructions.send(ScreenInstruction::FocusPreviousPane).unwrap();
+        }
+        ApiCommand::SwapPane(direction) => {
+            let screen_instruction = match direction {
+                Direction::Left => ScreenInstruction::SwapPaneLeft,
+                Direction::Right => ScreenInstruction::SwapPaneRight,
+                Direction::Up => ScreenInstruction::SwapPaneUp,
+                Direction::Down => ScreenInstruction::SwapPaneDown,
+            };
+            send_screen_instructions.send(screen_instruction).unwrap();
+        }
+        ApiCommand::SwapWithNextPane => {
+            send_screen_instructions.send(ScreenInstruction::SwapWithNextPane).unwrap();
+        }
+        ApiCommand::MovePaneToPosition(position) => {
+            send_screen_instructions.send(ScreenInstruction::MovePaneToPosition(position)).unwrap();
+        }
+        ApiCommand::ToggleZoom => {
+            send_screen_instructions.send(ScreenInstruction::ToggleZoom).unwrap();
+        }
+        ApiCommand::ToggleFloatingPanes => {
+            send_screen_instructions.send(ScreenInstruction::ToggleFloatingPanes).unwrap();
+        }
+        ApiCommand::NewFloatingPane(run_command) => {
+            let terminal_action = run_command.map(TerminalAction::RunCommand);
+            send_pty_instructions.send(PtyInstruction::SpawnFloatingTerminal(terminal_action)).unwrap();
+        }
+        ApiCommand::NewTab => {
+            send_pty_instructions.send(PtyInstruction::SpawnTerminalInNewTab(None)).unwrap();
+        }
+        ApiCommand::CloseTab => {
+            send_screen_instructions.send(ScreenInstruction::CloseTab).unwrap();
+        }
+        ApiCommand::RenameTab(name) => {
+            send_screen_instructions.send(ScreenInstruction::RenameTab(name)).unwrap();
+        }
+        ApiCommand::NextTab => {
+            send_screen_instructions.send(ScreenInstruction::NextTab).unwrap();
+        }
+        ApiCommand::PreviousTab => {
+            send_screen_instructions.send(ScreenInstruction::PreviousTab).unwrap();
+        }
+        ApiCommand::GetSessionInfo => {
+            let (send_pane_count, receive_pane_count) = channel();
+            send_screen_instructions.send(ScreenInstruction::GetPaneCount(send_pane_count)).unwrap();
+            let (send_client_count, receive_client_count) = channel();
+            send_server_instructions.send(ServerInstruction::GetClientCount(send_client_count)).unwrap();
+            let session_info = SessionInfo {
+                name: session_name.to_string(),
+                pane_count: receive_pane_count.recv().unwrap(),
+                created_at,
+                attached_clients: receive_client_count.recv().unwrap(),
+            };
+            bincode::serialize_into(&mut stream, &session_info).ok();
+        }
+        ApiCommand::KillSession => {
+            send_screen_instructions.send(ScreenInstruction::Quit).unwrap();
+            send_pty_instructions.send(PtyInstruction::Quit).unwrap();
+        }
+        ApiCommand::AttachClient(terminal_size, attach_options) => {
+            send_server_instructions.send(ServerInstruction::AttachClient(stream, terminal_size, attach_options)).unwrap();
+        }
+        ApiCommand::WriteChars(pane_selector, chars) => {
+            let (send_result, receive_result) = channel();
+            send_screen_instructions.send(ScreenInstruction::WriteToPane(pane_selector, chars.into_bytes(), send_result)).unwrap();
+            bincode::serialize_into(&mut stream, &receive_result.recv().unwrap()).ok();
+        }
+        ApiCommand::SendKeys(pane_selector, keys) => {
+            let bytes = keys.iter().flat_map(|key| key.as_bytes()).collect();
+            let (send_result, receive_result) = channel();
+            send_screen_instructions.send(ScreenInstruction::WriteToPane(pane_selector, bytes, send_result)).unwrap();
+            bincode::serialize_into(&mut stream, &receive_result.recv().unwrap()).ok();
+        }
+        ApiCommand::RenamePane(pane_selector, name) => {
+            let (send_result, receive_result) = channel();
+            send_screen_instructions.send(ScreenInstruction::RenamePane(pane_selector, name, send_result)).unwrap();
+            bincode::serialize_into(&mut stream, &receive_result.recv().unwrap()).ok();
+        }
+        ApiCommand::GoToTab(index) => {
+            let (send_result, receive_result) = channel();
+            send_screen_instructions.send(ScreenInstruction::GoToTab(index, send_result)).unwrap();
+            bincode::serialize_into(&mut stream, &receive_result.recv().unwrap()).ok();
+        }
+        ApiCommand::CapturePane(pane_selector, capture_options) => {
+            let (send_result, receive_result) = channel();
+            send_screen_instructions.send(ScreenInstruction::CapturePane(pane_selector, capture_options, send_result)).unwrap();
+            bincode::serialize_into(&mut stream, &receive_result.recv().unwrap()).ok();
+        }
+        ApiCommand::Subscribe => {
+            send_server_instructions.send(ServerInstruction::Subscribe(stream)).unwrap();
+        }
+        ApiCommand::GetState => {
+            let (send_state, receive_state) = channel();
+            send_screen_instructions.send(ScreenInstruction::GetState(send_state)).unwrap();
+            let (size, tabs, panes) = receive_state.recv().unwrap();
+            let session_state = SessionState {
+                name: session_name.to_string(),
+                size,
+                tabs,
+                panes,
+            };
+            bincode::serialize_into(&mut stream, &session_state).ok();
+        }
+        ApiCommand::GetLayout => {
+            let (send_run_commands, receive_run_commands) = channel();
+            send_pty_instructions.send(PtyInstruction::GetRunCommands(send_run_commands)).unwrap();
+            let (send_layout, receive_layout) = channel();
+            send_screen_instructions.send(ScreenInstruction::GetLayout(receive_run_commands.recv().unwrap(), send_layout)).unwrap();
+            bincode::serialize_into(&mut stream, &receive_layout.recv().unwrap()).ok();
+        }
+    }
+}
+
+pub fn start_server(mut os_input: Box<dyn ServerOsApi>, opts: Opt) {
+    let session_name = opts.session.clone().expect("server was started without a session name");
+    let mut client_connections = os_input.bind_session_socket(&session_name).unwrap_or_else(|e| exit_with_error(e));
+    let created_at = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+
+    let (first_client, terminal_size, attach_options) = match wait_for_first_client(&mut client_connections) {
+        Some(first_client) => first_client,
+        None => {
+            os_input.remove_session_socket(&session_name);
+            return;
+        }
+    };
+    let full_screen_ws = Winsize {
+        ws_row: terminal_size.rows,
+        ws_col: terminal_size.cols,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+
+    let mut active_threads = vec![];
+    let (send_screen_instructions, receive_screen_instructions): (Sender<ScreenInstruction>, Receiver<ScreenInstruction>) = channel();
+    let (send_pty_instructions, receive_pty_instructions): (Sender<PtyInstruction>, Receiver<PtyInstruction>) = channel();
+    let (send_server_instructions, receive_server_instructions): (Sender<ServerInstruction>, Receiver<ServerInstruction>) = channel();
+    let mut screen = Screen::new(
+        receive_screen_instructions,
+        send_pty_instructions.clone(),
+        send_server_instructions.clone(),
+        &full_screen_ws,
+        os_input.clone(),
+        opts.max_panes,
+        MinPaneSize {
+            rows: opts.min_pane_rows.unwrap_or(1).max(1),
+            cols: opts.min_pane_cols.unwrap_or(1).max(1),
+        },
+    );
+    // the client that started us already made sure the file can be loaded
+    let layout_file = opts.layout.as_ref().map(|path| LayoutFile::load(path).unwrap_or_else(|e| exit_with_error(e)));
+    let output_rate = opts.max_output_rate.map(|max_bytes_per_second| OutputRate { max_bytes_per_second });
+    let mut pty_bus = PtyBus::new(receive_pty_instructions, send_screen_instructions.clone(), os_input.clone(), output_rate);
+
+    active_threads.push(
+        thread::Builder::new()
+            .name("pty".to_string())
+            .spawn({
+                move || {
+                    let first_panes = match layout_file {
+                        Some(layout_file) => pty_bus.spawn_layout(layout_file),
+                        None => pty_bus.spawn_terminal_vertically(None),
+                    };
+                    first_panes.unwrap_or_else(|e| exit_with_error(e));
+                    loop {
+                        let event = pty_bus.receive_pty_instructions
+                            .recv()
+                            .expect("failed to receive event on channel");
+                        // like one there is no room for, a pane that can't be spawned (eg. when
+                        // out of ptys) just isn't opened
+                        match event {
+                            PtyInstruction::SpawnTerminal(terminal_action) => {
+                                pty_bus.spawn_terminal(terminal_action).ok();
+                            }
+                            PtyInstruction::SpawnTerminalVertically(terminal_action) => {
+                                pty_bus.spawn_terminal_vertically(terminal_action).ok();
+                            }
+                            PtyInstruction::SpawnTerminalHorizontally(terminal_action) => {
+                                pty_bus.spawn_terminal_horizontally(terminal_action).ok();
+                            }
+                            PtyInstruction::SpawnTerminalInNewTab(terminal_action) => {
+                                pty_bus.spawn_terminal_in_new_tab(terminal_action).ok();
+                            }
+                            PtyInstruction::SpawnFloatingTerminal(terminal_action) => {
+                                pty_bus.spawn_floating_terminal(terminal_action).ok();
+                            }
+                            PtyInstruction::OpenFile(file_to_open, send_result) => {
+                                let result = pty_bus.spawn_terminal(Some(TerminalAction::OpenFile(file_to_open)));
+                                send_result.send(result.map_err(|e| e.to_string())).unwrap();
+                            }
+                            PtyInstruction::RerunCommand(id) => {
+                                pty_bus.rerun_command(id).ok();
+                            }
+                            PtyInstruction::ClosePane(id) => {
+                                pty_bus.close_pane(id);
+                            }
+                            PtyInstruction::ProcessExited(id) => {
+                                pty_bus.process_exited(id);
+                            }
+                            PtyInstruction::GetRunCommands(send_run_commands) => {
+                                send_run_commands.send(pty_bus.run_commands()).unwrap();
+                            }
+                            PtyInstruction::Quit => {
+                                break;
+                            }
+                        }
+                    }
+                }
+            }).unwrap()
+    );
+
+    active_threads.push(
+        thread::Builder::new()
+            .name("screen".to_string())
+            .spawn({
+                let send_server_instructions = send_server_instructions.clone();
+                move || {
+                    // everything the screen rendered is already queued when this is sent, so the
+                    // attached clients will draw it all before being told the session ended
+                    let _quit_on_drop = QuitOnDrop(send_server_instructions);
+                    loop {
+                        let event = screen.receiver
+                            .recv()
+                            .expect("failed to receive event on channel");
+                        if event.is_user_action() {
+                            // eg. that the last split was refused, shown until the user does something else
+                            screen.clear_notice();
+                        }
+                        match event {
+                            ScreenInstruction::PtyBytes(pid, bytes, batch_applied) => {
+                                screen.handle_pty_bytes(pid, &bytes);
+                                batch_applied.send(()).ok();
+                            },
+                            ScreenInstruction::Render => {
+                                screen.render();
+                            },
+                            ScreenInstruction::ForceRender => {
+                                screen.force_render();
+                            },
+                            ScreenInstruction::TerminalResize(new_screen_size) => {
+                                screen.resize_to_screen(new_screen_size);
+                            },
+                            ScreenInstruction::NewPane(pid) => {
+                                screen.new_pane(pid);
+                            }
+                            ScreenInstruction::HorizontalSplit(pid) => {
+                                screen.horizontal_split(pid);
+                            }
+                            ScreenInstruction::VerticalSplit(pid) => {
+                                screen.vertical_split(pid);
+                            }
+                            ScreenInstruction::WriteCharacter(byte) => {
+                                screen.write_to_active_terminal(byte);
+                            }
+                            ScreenInstruction::ResizeLeft => {
+                                screen.resize_left();
+                            }
+                            ScreenInstruction::ResizeRight => {
+                                screen.resize_right();
+                            }
+                            ScreenInstruction::ResizeDown => {
+                                screen.resize_down();
+                            }
+                            ScreenInstruction::ResizeUp => {
+                                screen.resize_up();
+                            }
+                            ScreenInstruction::MoveFocus => {
+                                screen.move_focus();
+                            }
+                            ScreenInstruction::MoveFocusLeft => {
+                                screen.move_focus_left();
+                            }
+                            ScreenInstruction::MoveFocusRight => {
+                                screen.move_focus_right();
+                            }
+                            ScreenInstruction::MoveFocusUp => {
+                                screen.move_focus_up();
+                            }
+                            ScreenInstruction::MoveFocusDown => {
+                                screen.move_focus_down();
+                            }
+                            ScreenInstruction::FocusPreviousPane => {
+                                screen.focus_previous_pane();
+                            }
+                            ScreenInstruction::SwapPaneLeft => {
+                                screen.swap_with_pane_towards(Direction::Left);
+                            }
+                            ScreenInstruction::SwapPaneRight => {
+                                screen.swap_with_pane_towards(Direction::Right);
+                            }
+                            ScreenInstruction::SwapPaneUp => {
+                                screen.swap_with_pane_towards(Direction::Up);
+                            }
+                            ScreenInstruction::SwapPaneDown => {
+                                screen.swap_with_pane_towards(Direction::Down);
+                            }
+                            ScreenInstruction::SwapWithNextPane => {
+                                screen.swap_with_next_pane();
+                            }
+                            ScreenInstruction::MovePaneToPosition(position) => {
+                                screen.move_pane_to_position(position);
+                            }
+                            ScreenInstruction::ToggleZoom => {
+                                screen.toggle_zoom();
+                            }
+                            ScreenInstruction::ToggleFloatingPanes => {
+                                screen.toggle_floating_panes();
+                            }
+                            ScreenInstruction::NewFloatingPane(pid) => {
+                                screen.new_floating_pane(pid);
+                            }
+                            ScreenInstruction::NewTab(pid) => {
+                                screen.new_tab(pid);
+                            }
+                            ScreenInstruction::CloseTab => {
+                                screen.close_tab();
+                            }
+                            ScreenInstruction::RenameTab(name) => {
+                                screen.rename_tab(name);
+                            }
+                            ScreenInstruction::NextTab => {
+                                screen.next_tab();
+                            }
+                            ScreenInstruction::PreviousTab => {
+                                screen.previous_tab();
+                            }
+                            ScreenInstruction::GoToTab(index, send_result) => {
+                                send_result.send(screen.go_to_tab(index)).ok(); // not when it was a key
+                            }
+                            ScreenInstruction::ScrollUp => {
+                                screen.scroll_active_terminal_up();
+                            }
+                            ScreenInstruction::ScrollDown => {
+                                screen.scroll_active_terminal_down();
+                            }
+                            ScreenInstruction::ClearScroll => {
+                                screen.clear_active_terminal_scroll();
+                            }
+                            ScreenInstruction::CloseFocusedPane => {
+                                screen.close_focused_pane();
+                            }
+                            ScreenInstruction::ProcessExited(id, exit_code, hold_pane) => {
+                                screen.handle_process_exited(id, exit_code, hold_pane);
+                            }
+                            ScreenInstruction::RerunFocusedPane => {
+                                screen.rerun_focused_pane();
+                            }
+                            ScreenInstruction::ReplacePane(old_id, new_id) => {
+                                screen.replace_pane(old_id, new_id);
+                            }
+                            ScreenInstruction::GetPaneCount(send_pane_count) => {
+                                send_pane_count.send(screen.pane_count()).unwrap();
+                            }
+                            ScreenInstruction::WriteToPane(pane_selector, bytes, send_result) => {
+                                send_result.send(screen.write_to_pane(&pane_selector, bytes)).unwrap();
+                            }
+                            ScreenInstruction::RenamePane(pane_selector, name, send_result) => {
+                                send_result.send(screen.rename_pane(&pane_selector, name)).unwrap();
+                            }
+                            ScreenInstruction::CapturePane(pane_selector, capture_options, send_result) => {
+                                send_result.send(screen.capture_pane(&pane_selector, capture_options)).unwrap();
+                            }
+                            ScreenInstruction::GetState(send_state) => {
+                                send_state.send(screen.get_state()).unwrap();
+                            }
+                            ScreenInstruction::OpenLayout(layout_file, pane_ids) => {
+                                screen.open_layout(layout_file, pane_ids);
+                            }
+                            ScreenInstruction::GetLayout(run_commands, send_layout) => {
+                                send_layout.send(screen.get_layout(&run_commands)).unwrap();
+                            }
+                            ScreenInstruction::Quit => {
+                                break;
+                            }
+                        }
+                    }
+                }
+            }).unwrap()
+    );
+
+    // TODO: currently we don't push this into active_threads
+    // because otherwise the app will hang. Need to fix this so it both
+    // listens to the ipc-bus and is able to quit cleanly
+    let _ipc_thread = thread::Builder::new()
+        .name("ipc_server".to_string())
+        .spawn({
+            let send_pty_instructions = send_pty_instructions.clone();
+            let send_screen_instructions = send_screen_instructions.clone();
+            let send_server_instructions = send_server_instructions.clone();
+            let session_name = session_name.clone();
+            move || {
+                for stream in client_connections {
+                    // each connection is read from on its own thread, so that one that never
+                    // sends its command doesn't keep the ones after it waiting
+                    let send_pty_instructions = send_pty_instructions.clone();
+                    let send_screen_instructions = send_screen_instructions.clone();
+                    let send_server_instructions = send_server_instructions.clone();
+                    let session_name = session_name.clone();
+                    thread::Builder::new()
+                        .name("ipc_connection".to_string())
+                        .spawn(move || {
+                            handle_connection(stream, &session_name, created_at, &send_screen_instructions, &send_pty_instructions, &send_server_instructions);
+                        }).unwrap();
+                }
+            }
+        }).unwrap();
+
+    let mut next_client_id: ClientId = 0;
+    let mut screen_size = full_screen_ws;
+    let mut clients = BTreeMap::new();
+    let mut subscribers: Vec<Connection<Event>> = vec![];
+    let mut closing_connections = vec![];
+    let first_client = Client::new(next_client_id, first_client, terminal_size, attach_options);
+    start_client_input_thread(&first_client, &opts.bind_command, &send_screen_instructions, &send_pty_instructions, &send_server_instructions);
+    clients.insert(first_client.id, first_client);
+    loop {
+        let event = receive_server_instructions
+            .recv()
+            .expect("failed to receive event on channel");
+        match event {
+            ServerInstruction::Render(output) => {
+                let render = ServerToClientMsg::Render(output);
+                // clients we can't reach anymore went away or fell behind, their input threads
+                // will let us know soon and we'll resize the screen then
+                clients.retain(|_client_id, client| client.send(render.clone()));
+            }
+            ServerInstruction::AttachClient(stream, size, attach_options) => {
+                next_client_id += 1;
+                let client = Client::new(next_client_id, stream, size, attach_options);
+                start_client_input_thread(&client, &opts.bind_command, &send_screen_instructions, &send_pty_instructions, &send_server_instructions);
+                clients.insert(client.id, client);
+                match screen_size_for_clients(&clients) {
+                    Some(new_screen_size) if new_screen_size != screen_size => {
+                        screen_size = new_screen_size;
+                        send_screen_instructions.send(ScreenInstruction::TerminalResize(screen_size)).unwrap();
+                    }
+                    _ => {
+                        send_screen_instructions.send(ScreenInstruction::ForceRender).unwrap();
+                    }
+                }
+            }
+            ServerInstruction::DetachClient(client_id) => {
+                if let Some(client) = clients.remove(&client_id) {
+                    client.exit(ExitReason::Detached);
+                }
+                if let Some(new_screen_size) = screen_size_for_clients(&clients) {
+                    if new_screen_size != screen_size {
+                        screen_size = new_screen_size;
+                        send_screen_instructions.send(ScreenInstruction::TerminalResize(screen_size)).unwrap();
+                    }
+                }
+            }
+            ServerInstruction::ResizeClient(client_id, size) => {
+                if let Some(client) = clients.get_mut(&client_id) {
+                    client.size = size;
+                }
+                match screen_size_for_clients(&clients) {
+                    Some(new_screen_size) if new_screen_size != screen_size => {
+                        screen_size = new_screen_size;
+                        send_screen_instructions.send(ScreenInstruction::TerminalResize(screen_size)).unwrap();
+                    }
+                    _ => {
+                        // the screen stays the same size, but whatever was on the resized
+                        // terminal was probably moved around by it
+                        send_screen_instructions.send(ScreenInstruction::ForceRender).unwrap();
+                    }
+                }
+            }
+            ServerInstruction::GetClientCount(send_client_count) => {
+                send_client_count.send(clients.len()).unwrap();
+            }
+            ServerInstruction::Subscribe(stream) => {
+                subscribers.push(Connection::new(stream, String::from("subscriber")));
+            }
+            ServerInstruction::Event(event) => {
+                // subscribers go away by closing their connection, or are dropped if they don't
+                // keep up with the events
+                subscribers.retain_mut(|subscriber| subscriber.send(event.clone()));
+            }
+            ServerInstruction::Quit => {
+                for (_client_id, client) in clients {
+                    closing_connections.push(client.exit(ExitReason::Quit));
+                }
+                for subscriber in subscribers {
+                    closing_connections.push(subscriber.close());
+                }
+                // the pty thread might not have been told yet, if the screen ended on its own
+                send_pty_instructions.send(PtyInstruction::Quit).ok();
+                break;
+            }
+        }
+    }
+
+    // the session is over for its clients even if one of the threads panicked
+    os_input.remove_session_socket(&session_name);
+    for thread_handler in active_threads {
+        thread_handler.join().unwrap();
+    }
+    // so that the clients get everything that was sent to them before the session ended
+    for writer_thread in closing_connections {
+        writer_thread.join().ok();
+    }
+}
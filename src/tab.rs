@@ -0,0 +1,832 @@
+use std::collections::{BTreeMap, HashMap};
+use nix::pty::Winsize;
+use std::os::unix::io::RawFd;
+use std::sync::mpsc::Sender;
+
+use crate::os_input_output::ServerOsApi;
+use crate::terminal_pane::TerminalPane;
+use crate::pty_bus::{PtyInstruction, RunCommand};
+use crate::boundaries::{boundary_type, Boundaries};
+use crate::output_grid::OutputGrid;
+use crate::layout::{Edge, Layout, MinPaneSize, PaneGeometry};
+use crate::layout_file::LayoutFile;
+use crate::terminal_pane::{AnsiCode, CharacterStyles, NamedColor, TerminalCharacter, EMPTY_TERMINAL_CHARACTER};
+use crate::ipc::{Direction, PaneState, SplitDirection};
+
+/*
+ * Tab
+ *
+ * a set of panes laid out together, with its own focus (and zoom). the screen shows one tab at a
+ * time, the panes of the others keep running and reading what their processes write, they are
+ * only drawn once their tab is shown again
+ *
+ * the panes of a tab are laid out in its area of the screen, which is all of it unless the tab
+ * bar takes its first row
+ *
+ * floating panes are not part of the layout, each has its own position and size and they are
+ * drawn over the tiled panes, framed on every side. they are shown while one of them is focused,
+ * and hidden (but kept running) when focus goes back to the tiled panes
+ *
+ */
+
+const CURSOR_HEIGHT_WIDGH_RATIO: u16 = 4; // this is not accurate and kind of a magic number, TODO: look into this
+const ZOOM_INDICATOR: &str = " ZOOMED ";
+const NO_ROOM_TO_SPLIT: &str = " NO ROOM TO SPLIT ";
+const NO_ROOM_TO_RESIZE: &str = " NO ROOM TO RESIZE ";
+const FLOATING_PANE_STEP: (u16, u16) = (10, 2); // cols, rows a floating pane is moved or resized by
+
+pub struct Tab {
+    pub name: Option<String>, // given with mosaic action rename-tab
+    area: PaneGeometry, // where on the screen the panes are laid out
+    max_panes: Option<usize>,
+    min_pane_size: MinPaneSize,
+    send_pty_instructions: Sender<PtyInstruction>,
+    terminals: BTreeMap<RawFd, TerminalPane>, // BTreeMap because we need a predictable order when changing focus
+    layout: Layout,
+    active_terminal: Option<RawFd>,
+    focus_history: Vec<RawFd>, // the panes in the order they were last focused, the active one last
+    zoomed: bool, // the active pane takes the whole tab, the others are hidden but keep running
+    floating_panes: Vec<RawFd>, // in the order they are drawn, the one on top last
+    floating_panes_shown: bool, // while one of them is focused
+    notice: Option<&'static str>, // why the last split or resize didn't happen, until the next key
+    os_api: Box<dyn ServerOsApi>,
+}
+
+impl Tab {
+    pub fn new (
+        area: PaneGeometry,
+        send_pty_instructions: Sender<PtyInstruction>,
+        os_api: Box<dyn ServerOsApi>,
+        max_panes: Option<usize>,
+        min_pane_size: MinPaneSize,
+    ) -> Self {
+        Tab {
+            name: None,
+            area,
+            max_panes,
+            min_pane_size,
+            send_pty_instructions,
+            terminals: BTreeMap::new(),
+            layout: Layout::new(min_pane_size),
+            active_terminal: None,
+            focus_history: vec![],
+            zoomed: false,
+            floating_panes: vec![],
+            floating_panes_shown: false,
+            notice: None,
+            os_api,
+        }
+    }
+    // these return whether anything changed on screen, ie. whether it should be rendered
+    pub fn new_pane(&mut self, pid: RawFd) -> bool {
+        self.close_down_to_max_terminals();
+        if self.floating_panes_shown {
+            self.add_floating_pane(pid);
+            true
+        } else if self.terminals.is_empty() {
+            self.add_only_pane(pid);
+            false
+        } else {
+            let (_longest_edge, terminal_id_to_split) = self.terminals.iter().fold((0, 0), |(current_longest_edge, current_terminal_id_to_split), id_and_terminal_to_check| {
+                let (id_of_terminal_to_check, terminal_to_check) = id_and_terminal_to_check;
+                let terminal_size = (terminal_to_check.display_rows * CURSOR_HEIGHT_WIDGH_RATIO) * terminal_to_check.display_cols;
+                if terminal_size > current_longest_edge {
+                    (terminal_size, *id_of_terminal_to_check)
+                } else {
+                    (current_longest_edge, current_terminal_id_to_split)
+                }
+            });
+            let terminal_to_split = self.terminals.get(&terminal_id_to_split).unwrap();
+            let direction = if terminal_to_split.display_rows * CURSOR_HEIGHT_WIDGH_RATIO > terminal_to_split.display_cols {
+                SplitDirection::Horizontal
+            } else {
+                SplitDirection::Vertical
+            };
+            self.split_pane(terminal_id_to_split, pid, direction)
+        }
+    }
+    pub fn horizontal_split(&mut self, pid: RawFd) -> bool {
+        self.close_down_to_max_terminals();
+        if self.floating_panes_shown {
+            self.add_floating_pane(pid);
+            true
+        } else if self.terminals.is_empty() {
+            self.add_only_pane(pid);
+            false
+        } else {
+            let active_terminal_id = self.get_active_terminal_id().unwrap();
+            self.split_pane(active_terminal_id, pid, SplitDirection::Horizontal)
+        }
+    }
+    pub fn vertical_split(&mut self, pid: RawFd) -> bool {
+        self.close_down_to_max_terminals();
+        if self.floating_panes_shown {
+            self.add_floating_pane(pid);
+            true
+        } else if self.terminals.is_empty() {
+            self.add_only_pane(pid);
+            false
+        } else {
+            let active_terminal_id = self.get_active_terminal_id().unwrap();
+            self.split_pane(active_terminal_id, pid, SplitDirection::Vertical)
+        }
+    }
+    pub fn open_layout(&mut self, layout_file: LayoutFile, pane_ids: Vec<RawFd>) {
+        let (cols, rows) = (self.area.cols, self.area.rows);
+        let layout = Layout::with_root(layout_file.layout_node(&mut pane_ids.iter().copied(), cols, rows), self.min_pane_size);
+        match layout.pane_geometries(cols, rows) {
+            Some(geometries) => {
+                for (id, geometry) in geometries {
+                    let ws = Winsize {
+                        ws_row: geometry.rows,
+                        ws_col: geometry.cols,
+                        ws_xpixel: 0,
+                        ws_ypixel: 0,
+                    };
+                    let new_terminal = TerminalPane::new(id, ws, self.area.x + geometry.x, self.area.y + geometry.y);
+                    self.os_api.set_terminal_size_using_fd(id, geometry.cols, geometry.rows);
+                    self.terminals.insert(id, new_terminal);
+                }
+                self.layout = layout;
+                self.focus_pane(pane_ids[0]);
+            }
+            None => {
+                // the screen is too small for this layout, so we start with only its first pane
+                self.add_only_pane(pane_ids[0]);
+                for id in &pane_ids[1..] {
+                    self.send_pty_instructions.send(PtyInstruction::ClosePane(*id)).unwrap();
+                }
+            }
+        }
+        for (pane, id) in layout_file.panes().into_iter().zip(pane_ids) {
+            if let Some(terminal) = self.terminals.get_mut(&id) {
+                terminal.name = pane.name.clone();
+            }
+        }
+    }
+    pub fn get_layout(&self, run_commands: &HashMap<RawFd, RunCommand>) -> String {
+        let pane = |id: RawFd| {
+            let name = self.terminals.get(&id).and_then(|terminal| terminal.name.clone());
+            match run_commands.get(&id) {
+                Some(run_command) => LayoutFile {
+                    name,
+                    command: Some(run_command.command.clone()),
+                    args: run_command.args.clone(),
+                    cwd: run_command.cwd.clone(),
+                    ..LayoutFile::default()
+                },
+                None => LayoutFile {
+                    name,
+                    cwd: self.os_api.get_foreground_process_cwd(id),
+                    ..LayoutFile::default()
+                },
+            }
+        };
+        let layout_file = match self.layout.root() {
+            Some(root) => LayoutFile::from_layout_node(root, &pane),
+            None => LayoutFile::default(),
+        };
+        layout_file.to_yaml()
+    }
+    pub fn add_only_pane(&mut self, pid: RawFd) {
+        let new_terminal = TerminalPane::new(pid, self.area_winsize(), self.area.x, self.area.y);
+        self.os_api.set_terminal_size_using_fd(new_terminal.pid, new_terminal.display_cols, new_terminal.display_rows);
+        self.terminals.insert(pid, new_terminal);
+        self.layout.add_only_pane(pid);
+        self.focus_pane(pid);
+    }
+    pub fn add_floating_pane(&mut self, pid: RawFd) {
+        // in the middle of the tab, each one a little lower and to the right of the one before it
+        let (cols, rows) = (self.area.cols / 2, self.area.rows / 2);
+        let offset = self.floating_panes.len() as u16;
+        let geometry = self.fit_floating_pane(PaneGeometry {
+            x: self.area.x + (self.area.cols - cols) / 2 + offset * 2,
+            y: self.area.y + (self.area.rows - rows) / 2 + offset,
+            rows,
+            cols,
+        });
+        let ws = Winsize {
+            ws_row: geometry.rows,
+            ws_col: geometry.cols,
+            ws_xpixel: 0,
+            ws_ypixel: 0,
+        };
+        let new_terminal = TerminalPane::new(pid, ws, geometry.x, geometry.y);
+        self.os_api.set_terminal_size_using_fd(new_terminal.pid, new_terminal.display_cols, new_terminal.display_rows);
+        self.terminals.insert(pid, new_terminal);
+        self.floating_panes.push(pid);
+        self.focus_pane(pid);
+    }
+    fn fit_floating_pane(&self, geometry: PaneGeometry) -> PaneGeometry {
+        // floating panes are framed on every side, so they stay a cell away from the edges of the tab
+        let area = self.area;
+        let cols = geometry.cols.max(self.min_pane_size.cols).min(area.cols.saturating_sub(2)).max(1);
+        let rows = geometry.rows.max(self.min_pane_size.rows).min(area.rows.saturating_sub(2)).max(1);
+        PaneGeometry {
+            x: geometry.x.min(area.x + area.cols.saturating_sub(cols + 1)).max(area.x + 1),
+            y: geometry.y.min(area.y + area.rows.saturating_sub(rows + 1)).max(area.y + 1),
+            rows,
+            cols,
+        }
+    }
+    fn set_floating_pane_geometry(&mut self, id: RawFd, geometry: PaneGeometry) -> bool {
+        // returns false if the pane was already there, at that size (eg. it can't grow any more)
+        let geometry = self.fit_floating_pane(geometry);
+        let terminal = self.terminals.get_mut(&id).unwrap();
+        let current_geometry = (terminal.x_coords, terminal.y_coords, terminal.display_rows, terminal.display_cols);
+        if current_geometry == (geometry.x, geometry.y, geometry.rows, geometry.cols) {
+            return false;
+        }
+        terminal.x_coords = geometry.x;
+        terminal.y_coords = geometry.y;
+        if terminal.display_rows != geometry.rows || terminal.display_cols != geometry.cols {
+            let ws = Winsize {
+                ws_row: geometry.rows,
+                ws_col: geometry.cols,
+                ws_xpixel: 0,
+                ws_ypixel: 0,
+            };
+            terminal.change_size(&ws);
+            self.os_api.set_terminal_size_using_fd(id, geometry.cols, geometry.rows);
+        }
+        true
+    }
+    fn floating_pane_geometry(&self, id: RawFd) -> PaneGeometry {
+        let terminal = self.terminals.get(&id).unwrap();
+        PaneGeometry {
+            x: terminal.x_coords,
+            y: terminal.y_coords,
+            rows: terminal.display_rows,
+            cols: terminal.display_cols,
+        }
+    }
+    fn is_floating(&self, id: RawFd) -> bool {
+        self.floating_panes.contains(&id)
+    }
+    fn active_pane_is_floating(&self) -> bool {
+        self.active_terminal.map(|id| self.is_floating(id)).unwrap_or(false)
+    }
+    pub fn has_floating_panes(&self) -> bool {
+        !self.floating_panes.is_empty()
+    }
+    pub fn toggle_floating_panes(&mut self) -> bool {
+        // back to the tiled pane that was focused last, or to the floating pane on top
+        let pane_to_focus = if self.floating_panes_shown {
+            self.last_focused_tiled_pane()
+        } else {
+            self.floating_panes.last().copied()
+        };
+        match pane_to_focus {
+            Some(id) => {
+                self.focus_pane(id);
+                true
+            }
+            None => false,
+        }
+    }
+    fn last_focused_tiled_pane(&self) -> Option<RawFd> {
+        self.focus_history.iter().rev()
+            .find(|id| !self.is_floating(**id))
+            .copied()
+            .or_else(|| self.layout.pane_ids().first().copied())
+    }
+    fn move_floating_pane(&mut self, id: RawFd, direction: Direction) -> bool {
+        let mut geometry = self.floating_pane_geometry(id);
+        let (cols, rows) = FLOATING_PANE_STEP;
+        match direction {
+            Direction::Left => geometry.x = geometry.x.saturating_sub(cols),
+            Direction::Right => geometry.x += cols,
+            Direction::Up => geometry.y = geometry.y.saturating_sub(rows),
+            Direction::Down => geometry.y += rows,
+        }
+        self.set_floating_pane_geometry(id, geometry)
+    }
+    fn resize_floating_pane(&mut self, id: RawFd, direction: SplitDirection, grow: bool) -> bool {
+        // from its bottom right corner, the top left one stays where it is
+        let mut geometry = self.floating_pane_geometry(id);
+        let (cols, rows) = FLOATING_PANE_STEP;
+        match (direction, grow) {
+            (SplitDirection::Vertical, true) => geometry.cols += cols,
+            (SplitDirection::Vertical, false) => geometry.cols = geometry.cols.saturating_sub(cols),
+            (SplitDirection::Horizontal, true) => geometry.rows += rows,
+            (SplitDirection::Horizontal, false) => geometry.rows = geometry.rows.saturating_sub(rows),
+        }
+        self.set_floating_pane_geometry(id, geometry)
+    }
+    fn split_pane(&mut self, id_to_split: RawFd, pid: RawFd, direction: SplitDirection) -> bool {
+        if self.layout.split_pane(id_to_split, pid, direction, self.area.cols, self.area.rows) {
+            let geometry = self.layout.pane_geometry(pid, self.area.cols, self.area.rows).unwrap();
+            let ws = Winsize {
+                ws_row: geometry.rows,
+                ws_col: geometry.cols,
+                ws_xpixel: 0,
+                ws_ypixel: 0,
+            };
+            let new_terminal = TerminalPane::new(pid, ws, self.area.x + geometry.x, self.area.y + geometry.y);
+            self.os_api.set_terminal_size_using_fd(new_terminal.pid, new_terminal.display_cols, new_terminal.display_rows);
+            self.terminals.insert(pid, new_terminal);
+            self.apply_layout();
+            self.focus_pane(pid);
+            true
+        } else {
+            // the pane is too small to be split, so there is nowhere to show the new one
+            self.send_pty_instructions.send(PtyInstruction::ClosePane(pid)).unwrap();
+            self.notice = Some(NO_ROOM_TO_SPLIT);
+            true
+        }
+    }
+    fn apply_layout(&mut self) {
+        let (cols, rows) = (self.area.cols, self.area.rows);
+        let geometries = self.layout.pane_geometries(cols, rows)
+            .expect("panes do not fit on screen");
+        for (id, mut geometry) in geometries {
+            if self.zoomed && self.active_terminal == Some(id) {
+                geometry = PaneGeometry { x: 0, y: 0, rows, cols };
+            }
+            let terminal = self.terminals.get_mut(&id).unwrap();
+            terminal.x_coords = self.area.x + geometry.x;
+            terminal.y_coords = self.area.y + geometry.y;
+            if terminal.display_rows != geometry.rows || terminal.display_cols != geometry.cols {
+                let ws = Winsize {
+                    ws_row: geometry.rows,
+                    ws_col: geometry.cols,
+                    ws_xpixel: 0,
+                    ws_ypixel: 0,
+                };
+                terminal.change_size(&ws);
+                self.os_api.set_terminal_size_using_fd(id, geometry.cols, geometry.rows);
+            }
+        }
+    }
+    fn area_winsize(&self) -> Winsize {
+        Winsize {
+            ws_row: self.area.rows,
+            ws_col: self.area.cols,
+            ws_xpixel: 0,
+            ws_ypixel: 0,
+        }
+    }
+    pub fn fits(&self, cols: u16, rows: u16) -> bool {
+        self.layout.pane_geometries(cols, rows).is_some()
+    }
+    pub fn resize(&mut self, area: PaneGeometry) {
+        // the layout keeps the ratios between the panes rather than their sizes, so they are
+        // resized in proportion to the tab's area, which should fit them (see fits)
+        self.area = area;
+        self.apply_layout();
+        for id in self.floating_panes.clone() {
+            self.set_floating_pane_geometry(id, self.floating_pane_geometry(id));
+        }
+    }
+    pub fn pane_count(&self) -> usize {
+        self.terminals.len()
+    }
+    pub fn pane_ids(&self) -> Vec<RawFd> {
+        self.terminals.keys().copied().collect()
+    }
+    pub fn is_last_tiled_pane(&self, id: RawFd) -> bool {
+        !self.is_floating(id) && self.layout.pane_ids() == vec![id]
+    }
+    pub fn pane_states(&self, tab_index: usize, is_active_tab: bool) -> Vec<PaneState> {
+        self.terminals.values().map(|terminal| {
+            PaneState {
+                id: terminal.pid,
+                name: terminal.name.clone(),
+                title: terminal.title.clone(),
+                tab: tab_index,
+                x: terminal.x_coords,
+                y: terminal.y_coords,
+                rows: terminal.display_rows,
+                cols: terminal.display_cols,
+                cwd: self.os_api.get_foreground_process_cwd(terminal.pid),
+                process: self.os_api.get_foreground_process_name(terminal.pid),
+                exit_code: terminal.exit_code,
+                floating: self.is_floating(terminal.pid),
+                focused: is_active_tab && self.active_terminal == Some(terminal.pid),
+            }
+        }).collect()
+    }
+    pub fn get_terminal(&self, id: RawFd) -> Option<&TerminalPane> {
+        self.terminals.get(&id)
+    }
+    pub fn get_terminal_mut(&mut self, id: RawFd) -> Option<&mut TerminalPane> {
+        self.terminals.get_mut(&id)
+    }
+    pub fn terminals(&self) -> impl Iterator<Item = &TerminalPane> {
+        self.terminals.values()
+    }
+    pub fn terminals_mut(&mut self) -> impl Iterator<Item = &mut TerminalPane> {
+        self.terminals.values_mut()
+    }
+    fn get_active_terminal (&self) -> Option<&TerminalPane> {
+        match self.active_terminal {
+            Some(active_terminal) => self.terminals.get(&active_terminal),
+            None => None
+        }
+    }
+    pub fn get_active_terminal_id (&self) -> Option<RawFd> {
+        self.active_terminal.map(|active_terminal| self.terminals.get(&active_terminal).unwrap().pid)
+    }
+    pub fn get_active_terminal_cursor_position(&self) -> (usize, usize) { // (x, y)
+        let active_terminal = &self.get_active_terminal().unwrap();
+        let (x_in_terminal, y_in_terminal) = active_terminal.cursor_coordinates();
+
+        let x = active_terminal.x_coords as usize + x_in_terminal;
+        let y = active_terminal.y_coords as usize + y_in_terminal;
+        (x, y)
+    }
+    pub fn visible_layout(&self) -> Vec<(RawFd, u16, u16, u16, u16)> {
+        // id, x, y, rows, cols of the panes that are drawn, the floating ones last
+        let floating_panes: &[RawFd] = if self.floating_panes_shown { &self.floating_panes } else { &[] };
+        self.visible_tiled_pane_ids().iter().chain(floating_panes)
+            .map(|id| self.terminals.get(id).unwrap())
+            .map(|terminal| (terminal.pid, terminal.x_coords, terminal.y_coords, terminal.display_rows, terminal.display_cols))
+            .collect()
+    }
+    fn visible_tiled_pane_ids(&self) -> Vec<RawFd> {
+        let zoomed_terminal = if self.zoomed { self.active_terminal } else { None };
+        self.terminals.keys()
+            .filter(|id| !self.is_floating(**id))
+            .filter(|id| zoomed_terminal.map(|zoomed_id| zoomed_id == **id).unwrap_or(true))
+            .copied()
+            .collect()
+    }
+    pub fn draw_on(&mut self, output_grid: &mut OutputGrid, redraw_all: bool) {
+        // only the tiled panes that changed since they were last drawn, unless redraw_all
+        let mut boundaries = Boundaries::new(self.area.x + self.area.cols, self.area.y + self.area.rows);
+        let visible_tiled_pane_ids = self.visible_tiled_pane_ids();
+        for id in &visible_tiled_pane_ids {
+            let terminal = self.terminals.get_mut(id).unwrap();
+            boundaries.add_rect(&terminal);
+            if redraw_all || terminal.should_render {
+                terminal.draw_on(output_grid);
+            }
+        }
+        let floating_panes = &self.floating_panes;
+        let tiled_terminals = self.terminals.iter_mut().filter(|(id, _)| !floating_panes.contains(id));
+        for (_pid, terminal) in tiled_terminals {
+            match terminal.exit_code {
+                Some(0) => boundaries.color_frame(&terminal, NamedColor::Green),
+                Some(_) => boundaries.color_frame(&terminal, NamedColor::Red),
+                None => {}
+            }
+        }
+        boundaries.draw_on(output_grid);
+        for id in &visible_tiled_pane_ids {
+            draw_exit_status(output_grid, &self.terminals[id]);
+        }
+        if self.floating_panes_shown {
+            // over the tiled panes and over each other, so whatever changed under them they are
+            // all drawn again, from the bottom one to the top one
+            for id in &self.floating_panes {
+                draw_floating_pane(self.terminals.get_mut(id).unwrap(), output_grid);
+            }
+        }
+        if let Some(notice) = self.notice {
+            draw_indicator(output_grid, self.area.y as usize, notice);
+        } else if self.zoomed {
+            draw_indicator(output_grid, self.area.y as usize, ZOOM_INDICATOR);
+        }
+    }
+    pub fn resize_active_pane (&mut self, direction: SplitDirection, preferred_edge: Edge, count: i32) -> bool {
+        if let Some(active_terminal_id) = self.get_active_terminal_id() {
+            let resized = if self.is_floating(active_terminal_id) {
+                self.resize_floating_pane(active_terminal_id, direction, count > 0)
+            } else {
+                let (cols, rows) = (self.area.cols, self.area.rows);
+                self.layout.resize_pane(active_terminal_id, direction, preferred_edge, count, cols, rows)
+            };
+            if resized {
+                self.apply_layout();
+            } else {
+                // the pane or the one next to it is as small as it can get, or fills the tab
+                self.notice = Some(NO_ROOM_TO_RESIZE);
+            }
+            return true;
+        }
+        false
+    }
+    pub fn show_no_room_to_split(&mut self) {
+        // for a pane that was refused somewhere else (eg. in a new tab), this tab being the one shown
+        self.notice = Some(NO_ROOM_TO_SPLIT);
+    }
+    pub fn clear_notice(&mut self) -> bool {
+        self.notice.take().is_some()
+    }
+    pub fn move_focus(&mut self) -> bool {
+        if self.terminals.is_empty() {
+            return false;
+        }
+        let active_terminal_id = self.get_active_terminal_id().unwrap();
+        // between the floating panes while they're shown, otherwise between the tiled ones
+        let floating_panes_shown = self.floating_panes_shown;
+        let terminal_ids: Vec<RawFd> = self.terminals.keys()
+            .filter(|id| self.is_floating(**id) == floating_panes_shown)
+            .copied()
+            .collect(); // TODO: better, no allocations
+        let first_terminal = terminal_ids.first().unwrap();
+        let active_terminal_id_position = terminal_ids.iter().position(|id| id == &active_terminal_id).unwrap();
+        if let Some(next_terminal) = terminal_ids.get(active_terminal_id_position + 1) {
+            self.focus_pane(*next_terminal);
+        } else {
+            self.focus_pane(*first_terminal);
+        }
+        true
+    }
+    pub fn move_focus_towards(&mut self, direction: Direction) -> bool {
+        if let Some(next_terminal) = self.pane_towards(direction) {
+            self.focus_pane(next_terminal);
+            true
+        } else {
+            false
+        }
+    }
+    fn pane_towards(&self, direction: Direction) -> Option<RawFd> {
+        // the pane on that side of the focused one that shares the most of its border with it,
+        // or the one that was focused last if several share as much
+        let active_terminal = self.get_active_terminal()?;
+        if self.floating_panes_shown {
+            return self.floating_pane_towards(active_terminal, direction);
+        }
+        self.terminals.values()
+            .filter(|terminal| !self.is_floating(terminal.pid))
+            .filter_map(|terminal| {
+                let shared_border = shared_border(active_terminal, terminal, direction)?;
+                let last_focused = self.focus_history.iter().position(|id| *id == terminal.pid);
+                Some((shared_border, last_focused, terminal.pid))
+            })
+            .max_by_key(|(shared_border, last_focused, pid)| (*shared_border, *last_focused, ::std::cmp::Reverse(*pid)))
+            .map(|(_, _, pid)| pid)
+    }
+    fn floating_pane_towards(&self, active_terminal: &TerminalPane, direction: Direction) -> Option<RawFd> {
+        // floating panes can be anywhere, so this is the one whose top left corner is closest on
+        // that side of the focused one's
+        let (x, y) = (active_terminal.x_coords as i32, active_terminal.y_coords as i32);
+        self.floating_panes.iter()
+            .map(|id| self.terminals.get(id).unwrap())
+            .filter_map(|terminal| {
+                let (other_x, other_y) = (terminal.x_coords as i32, terminal.y_coords as i32);
+                let (distance, offset) = match direction {
+                    Direction::Left => (x - other_x, other_y - y),
+                    Direction::Right => (other_x - x, other_y - y),
+                    Direction::Up => (y - other_y, other_x - x),
+                    Direction::Down => (other_y - y, other_x - x),
+                };
+                if distance > 0 { Some(((distance, offset.abs()), terminal.pid)) } else { None }
+            })
+            .min()
+            .map(|(_, pid)| pid)
+    }
+    pub fn swap_with_pane_towards(&mut self, direction: Direction) -> bool {
+        // floating panes are not in the layout, so rather than swapping places they move
+        if let Some(active_terminal_id) = self.get_active_terminal_id().filter(|id| self.is_floating(*id)) {
+            return self.move_floating_pane(active_terminal_id, direction);
+        }
+        if let (Some(active_terminal_id), Some(other_terminal_id)) = (self.get_active_terminal_id(), self.pane_towards(direction)) {
+            let mut pane_ids = self.layout.pane_ids();
+            let active_position = pane_ids.iter().position(|id| *id == active_terminal_id).unwrap();
+            let other_position = pane_ids.iter().position(|id| *id == other_terminal_id).unwrap();
+            pane_ids.swap(active_position, other_position);
+            self.rearrange_panes(&pane_ids);
+            true
+        } else {
+            false
+        }
+    }
+    pub fn swap_with_next_pane(&mut self) -> bool {
+        // in the order of the layout, the last pane swaps with the first
+        if let Some(active_terminal_id) = self.get_active_terminal_id().filter(|id| !self.is_floating(*id)) {
+            let mut pane_ids = self.layout.pane_ids();
+            let active_position = pane_ids.iter().position(|id| *id == active_terminal_id).unwrap();
+            let next_position = (active_position + 1) % pane_ids.len();
+            pane_ids.swap(active_position, next_position);
+            self.rearrange_panes(&pane_ids);
+            true
+        } else {
+            false
+        }
+    }
+    pub fn move_pane_to_position(&mut self, position: usize) -> bool {
+        // the panes between the two positions each move one place to make room
+        if let Some(active_terminal_id) = self.get_active_terminal_id().filter(|id| !self.is_floating(*id)) {
+            let mut pane_ids = self.layout.pane_ids();
+            let position = position.min(pane_ids.len() - 1);
+            pane_ids.retain(|id| *id != active_terminal_id);
+            pane_ids.insert(position, active_terminal_id);
+            self.rearrange_panes(&pane_ids);
+            true
+        } else {
+            false
+        }
+    }
+    fn rearrange_panes(&mut self, pane_ids: &[RawFd]) {
+        // the places in the layout stay where they are, the panes (and focus with them) move
+        // between them
+        self.layout.set_pane_ids(pane_ids);
+        self.apply_layout();
+    }
+    pub fn focus_previous_pane(&mut self) -> bool {
+        // the pane that was focused before the focused one, pressing it again goes back
+        let previous_pane = match self.focus_history.len() {
+            0 | 1 => return false,
+            len => self.focus_history[len - 2],
+        };
+        self.focus_pane(previous_pane);
+        true
+    }
+    pub fn toggle_zoom(&mut self) -> bool {
+        // the other panes keep their places in the layout, so unzooming puts everything back
+        if self.active_terminal.is_some() && !self.active_pane_is_floating() && (self.zoomed || self.layout.pane_ids().len() > 1) {
+            self.zoomed = !self.zoomed;
+            self.apply_layout();
+            true
+        } else {
+            false
+        }
+    }
+    fn focus_pane(&mut self, id: RawFd) {
+        if self.zoomed && self.active_terminal != Some(id) {
+            // the pane that was zoomed goes back to its place before another one is shown
+            self.zoomed = false;
+            self.apply_layout();
+        }
+        // floating panes are shown while one of them is focused, the focused one on top
+        self.floating_panes_shown = self.is_floating(id);
+        if self.floating_panes_shown {
+            self.floating_panes.retain(|floating_id| *floating_id != id);
+            self.floating_panes.push(id);
+        }
+        self.active_terminal = Some(id);
+        self.focus_history.retain(|focused_id| *focused_id != id);
+        self.focus_history.push(id);
+    }
+    fn close_down_to_max_terminals (&mut self) {
+        if let Some(max_panes) = self.max_panes {
+            if self.terminals.len() >= max_panes {
+                for _ in max_panes..=self.terminals.len() {
+                    let first_pid = *self.terminals.iter().next().unwrap().0;
+                    self.close_pane_process(first_pid);
+                    self.close_pane(first_pid); // TODO: do not render yet
+                }
+            }
+        }
+    }
+    pub fn close_pane(&mut self, id: RawFd) {
+        if self.is_floating(id) {
+            self.floating_panes.retain(|floating_id| *floating_id != id);
+            self.focus_history.retain(|focused_id| *focused_id != id);
+            self.terminals.remove(&id);
+            if self.active_terminal == Some(id) {
+                // the floating pane under it, or back to the tiled panes if it was the last one
+                let next_pane = self.floating_panes.last().copied().or_else(|| self.last_focused_tiled_pane());
+                self.focus_pane(next_pane.unwrap());
+            }
+        } else if self.terminals.contains_key(&id) {
+            let (cols, rows) = (self.area.cols, self.area.rows);
+            match self.layout.remove_pane(id, cols, rows) {
+                Some(pane_next_to_closed_pane) => {
+                    self.focus_history.retain(|focused_id| *focused_id != id);
+                    if self.active_terminal == Some(id) {
+                        // back to the pane that was focused before it, if there was one
+                        let previous_pane = self.focus_history.iter().rev().find(|id| !self.is_floating(**id)).copied();
+                        self.focus_pane(previous_pane.unwrap_or(pane_next_to_closed_pane));
+                    }
+                }
+                None => return, // TODO: exit app? here we're trying to close the last pane on screen
+            }
+            self.terminals.remove(&id);
+            self.apply_layout();
+        }
+    }
+    pub fn close_pane_process(&mut self, id: RawFd) {
+        if self.terminals.contains_key(&id) {
+            self.send_pty_instructions.send(PtyInstruction::ClosePane(id)).unwrap();
+        }
+    }
+    pub fn replace_pane(&mut self, old_id: RawFd, new_id: RawFd) {
+        if let Some(old_terminal) = self.terminals.remove(&old_id) {
+            let ws = Winsize {
+                ws_row: old_terminal.display_rows,
+                ws_col: old_terminal.display_cols,
+                ws_xpixel: 0,
+                ws_ypixel: 0,
+            };
+            let mut new_terminal = TerminalPane::new(new_id, ws, old_terminal.x_coords, old_terminal.y_coords);
+            new_terminal.name = old_terminal.name;
+            self.layout.replace_pane(old_id, new_id);
+            self.os_api.set_terminal_size_using_fd(new_id, new_terminal.display_cols, new_terminal.display_rows);
+            self.terminals.insert(new_id, new_terminal);
+            for focused_id in self.focus_history.iter_mut().chain(self.floating_panes.iter_mut()).filter(|focused_id| **focused_id == old_id) {
+                *focused_id = new_id;
+            }
+            if self.active_terminal == Some(old_id) {
+                self.active_terminal = Some(new_id);
+            }
+        }
+    }
+    pub fn scroll_active_terminal_up(&mut self) -> bool {
+        if let Some(active_terminal_id) = self.get_active_terminal_id() {
+            let active_terminal = self.terminals.get_mut(&active_terminal_id).unwrap();
+            active_terminal.scroll_up(1);
+            true
+        } else {
+            false
+        }
+    }
+    pub fn scroll_active_terminal_down(&mut self) -> bool {
+        if let Some(active_terminal_id) = self.get_active_terminal_id() {
+            let active_terminal = self.terminals.get_mut(&active_terminal_id).unwrap();
+            active_terminal.scroll_down(1);
+            true
+        } else {
+            false
+        }
+    }
+    pub fn clear_active_terminal_scroll(&mut self) {
+        if let Some(active_terminal_id) = self.get_active_terminal_id() {
+            let active_terminal = self.terminals.get_mut(&active_terminal_id).unwrap();
+            active_terminal.clear_scroll();
+        }
+    }
+}
+
+fn shared_border (pane: &TerminalPane, other_pane: &TerminalPane, direction: Direction) -> Option<u16> {
+    // how many cells of the border on that side of the pane other_pane is on the other side of
+    // (panes are one cell apart, the border is between them)
+    let (pane_start, pane_end, other_start, other_end) = match direction {
+        Direction::Left | Direction::Right => (pane.y_coords, pane.y_coords + pane.display_rows, other_pane.y_coords, other_pane.y_coords + other_pane.display_rows),
+        Direction::Up | Direction::Down => (pane.x_coords, pane.x_coords + pane.display_cols, other_pane.x_coords, other_pane.x_coords + other_pane.display_cols),
+    };
+    let adjacent = match direction {
+        Direction::Left => other_pane.x_coords + other_pane.display_cols + 1 == pane.x_coords,
+        Direction::Right => pane.x_coords + pane.display_cols + 1 == other_pane.x_coords,
+        Direction::Up => other_pane.y_coords + other_pane.display_rows + 1 == pane.y_coords,
+        Direction::Down => pane.y_coords + pane.display_rows + 1 == other_pane.y_coords,
+    };
+    let shared = pane_end.min(other_end).saturating_sub(pane_start.max(other_start));
+    if adjacent && shared > 0 {
+        Some(shared)
+    } else {
+        None
+    }
+}
+
+fn draw_indicator (output_grid: &mut OutputGrid, row: usize, indicator: &str) {
+    // in the top right corner of the tab, over whichever pane is there
+    let styles = CharacterStyles::new().reverse(Some(AnsiCode::Code((Some(7), None))));
+    let first_column = output_grid.columns.saturating_sub(indicator.chars().count());
+    for (index, character) in indicator.chars().enumerate() {
+        output_grid.set_cell(first_column + index, row, TerminalCharacter { character, styles });
+    }
+}
+
+fn draw_exit_status (output_grid: &mut OutputGrid, terminal: &TerminalPane) {
+    // in the top right corner of a pane whose command exited, since the pane might have no frame
+    // to color (eg. when it's the only one in its tab)
+    if let Some(exit_code) = terminal.exit_code {
+        let color = if exit_code == 0 { NamedColor::Green } else { NamedColor::Red };
+        let styles = CharacterStyles::new()
+            .reverse(Some(AnsiCode::Code((Some(7), None))))
+            .foreground(Some(AnsiCode::NamedColor(color)));
+        let status = format!(" EXIT CODE {} - CTRL-G R TO RE-RUN ", exit_code);
+        let cols = terminal.display_cols as usize;
+        let first_column = terminal.x_coords as usize + cols.saturating_sub(status.chars().count());
+        for (index, character) in status.chars().take(cols).enumerate() {
+            output_grid.set_cell(first_column + index, terminal.y_coords as usize, TerminalCharacter { character, styles });
+        }
+    }
+}
+
+fn draw_floating_pane (terminal: &mut TerminalPane, output_grid: &mut OutputGrid) {
+    // over whatever is under it, including the cells the pane has nothing in
+    let (x, y) = (terminal.x_coords as usize, terminal.y_coords as usize);
+    let (cols, rows) = (terminal.display_cols as usize, terminal.display_rows as usize);
+    for row in y..y + rows {
+        for col in x..x + cols {
+            output_grid.set_cell(col, row, EMPTY_TERMINAL_CHARACTER);
+        }
+    }
+    terminal.draw_on(output_grid);
+    draw_exit_status(output_grid, terminal);
+    let styles = match terminal.exit_code {
+        Some(0) => CharacterStyles::new().foreground(Some(AnsiCode::NamedColor(NamedColor::Green))),
+        Some(_) => CharacterStyles::new().foreground(Some(AnsiCode::NamedColor(NamedColor::Red))),
+        None => EMPTY_TERMINAL_CHARACTER.styles,
+    };
+    let mut draw_frame_cell = |col: usize, row: usize, boundary: &str| {
+        let character = boundary.chars().next().unwrap();
+        output_grid.set_cell(col, row, TerminalCharacter { character, styles });
+    };
+    for col in x..x + cols {
+        draw_frame_cell(col, y - 1, boundary_type::HORIZONTAL);
+        draw_frame_cell(col, y + rows, boundary_type::HORIZONTAL);
+    }
+    for row in y..y + rows {
+        draw_frame_cell(x - 1, row, boundary_type::VERTICAL);
+        draw_frame_cell(x + cols, row, boundary_type::VERTICAL);
+    }
+    draw_frame_cell(x - 1, y - 1, boundary_type::TOP_LEFT);
+    draw_frame_cell(x + cols, y - 1, boundary_type::TOP_RIGHT);
+    draw_frame_cell(x - 1, y + rows, boundary_type::BOTTOM_LEFT);
+    draw_frame_cell(x + cols, y + rows, boundary_type::BOTTOM_RIGHT);
+}
@@ -56,6 +56,10 @@ impl CanonicalLine {
                 wrapped_fragments.push(WrappedFragment::from_vec(characters.drain(..).collect()));
             }
         }
+        if wrapped_fragments.is_empty() {
+            // an empty line still has a fragment for the cursor to be on
+            wrapped_fragments.push(WrappedFragment::new());
+        }
         self.wrapped_fragments = wrapped_fragments;
     }
     pub fn clear_after(&mut self, fragment_index: usize, column_index: usize) {
@@ -228,6 +232,14 @@ impl Scroll {
         }
         Vec::from(lines)
     }
+    pub fn history_as_character_lines(&self) -> Vec<Vec<TerminalCharacter>> {
+        // all the lines from the top of the scrollback, without padding and regardless of the viewport
+        self.canonical_lines
+            .iter()
+            .flat_map(|canonical_line| canonical_line.wrapped_fragments.iter())
+            .map(|wrapped_fragment| wrapped_fragment.characters.clone())
+            .collect()
+    }
     pub fn add_character(&mut self, terminal_character: TerminalCharacter) {
         let (canonical_line_position, wrapped_fragment_index_in_line) = self.cursor_position.line_index;
         let cursor_position_in_line = self.cursor_position.column_index;
@@ -292,10 +304,12 @@ impl Scroll {
             }
         }
         let total_lines = self.canonical_lines.iter().fold(0, |total_lines, current_line| total_lines + current_line.wrapped_fragments.len()); // TODO: is this performant enough? should it be cached or kept track of?
+        // lines rewrapped to a narrower width can push the cursor above the view, until the
+        // application in the pane redraws
         let y = if total_lines < self.lines_in_view {
-            total_lines - y
+            total_lines.saturating_sub(y)
         } else {
-            self.lines_in_view - y
+            self.lines_in_view.saturating_sub(y)
         };
         (x, y)
     }
@@ -338,8 +352,9 @@ impl Scroll {
                 canonical_line.change_width(columns);
             }
             let cursor_line = self.canonical_lines.get(self.cursor_position.line_index.0).expect("cursor out of bounds");
-            if cursor_line.wrapped_fragments.len() < self.cursor_position.line_index.1 {
-                self.cursor_position.line_index.1 = cursor_line.wrapped_fragments.len();
+            // the line might have fewer wrapped fragments once it's wider
+            if cursor_line.wrapped_fragments.len() <= self.cursor_position.line_index.1 {
+                self.cursor_position.line_index.1 = cursor_line.wrapped_fragments.len() - 1;
             }
         }
         self.lines_in_view = lines;
@@ -1,9 +1,9 @@
+use ::std::fmt::Write;
 use ::std::os::unix::io::RawFd;
 use ::nix::pty::Winsize;
-use ::vte::Perform;
 
-use crate::VteEvent;
 use crate::boundaries::Rect;
+use crate::output_grid::OutputGrid;
 use crate::terminal_pane::Scroll;
 use crate::terminal_pane::terminal_character::{
     TerminalCharacter,
@@ -20,7 +20,12 @@ pub struct TerminalPane {
     pub should_render: bool,
     pub x_coords: u16,
     pub y_coords: u16,
+    pub name: Option<String>,
+    pub title: Option<String>,
+    pub rang_bell: bool, // since the screen last reported it
+    pub exit_code: Option<i32>, // once the process exited, if the pane was kept open
     pending_styles: CharacterStyles,
+    vte_parser: vte::Parser, // of what the pane's process writes to it
 }
 
 impl Rect for &mut TerminalPane {
@@ -51,81 +56,26 @@ impl TerminalPane {
             pending_styles,
             x_coords,
             y_coords,
+            name: None,
+            title: None,
+            rang_bell: false,
+            exit_code: None,
+            vte_parser: vte::Parser::new(),
         }
     }
-    pub fn handle_event(&mut self, event: VteEvent) {
-        match event {
-            VteEvent::Print(c) => {
-                self.print(c);
-                self.should_render = true;
-            },
-            VteEvent::Execute(byte) => {
-                self.execute(byte);
-            },
-            VteEvent::Hook(params, intermediates, ignore, c) => {
-                self.hook(&params, &intermediates, ignore, c);
-            },
-            VteEvent::Put(byte) => {
-                self.put(byte);
-            },
-            VteEvent::Unhook => {
-                self.unhook();
-            },
-            VteEvent::OscDispatch(params, bell_terminated) => {
-                let params: Vec<&[u8]> = params.iter().map(|p| &p[..]).collect();
-                self.osc_dispatch(&params[..], bell_terminated);
-            },
-            VteEvent::CsiDispatch(params, intermediates, ignore, c) => {
-                self.csi_dispatch(&params, &intermediates, ignore, c);
-            },
-            VteEvent::EscDispatch(intermediates, ignore, byte) => {
-                self.esc_dispatch(&intermediates, ignore, byte);
-            }
-        }
-    }
-    pub fn reduce_width_right(&mut self, count: u16) {
-        self.x_coords += count;
-        self.display_cols -= count;
-        self.reflow_lines();
-        self.should_render = true;
-    }
-    pub fn reduce_width_left(&mut self, count: u16) {
-        self.display_cols -= count;
-        self.reflow_lines();
-        self.should_render = true;
-    }
-    pub fn increase_width_left(&mut self, count: u16) {
-        self.x_coords -= count;
-        self.display_cols += count;
-        self.reflow_lines();
-        self.should_render = true;
-    }
-    pub fn increase_width_right(&mut self, count: u16) {
-        self.display_cols += count;
-        self.reflow_lines();
-        self.should_render = true;
-    }
-    pub fn reduce_height_down(&mut self, count: u16) {
-        self.y_coords += count;
-        self.display_rows -= count;
-        self.reflow_lines();
-        self.should_render = true;
-    }
-    pub fn increase_height_down(&mut self, count: u16) {
-        self.display_rows += count;
-        self.reflow_lines();
-        self.should_render = true;
-    }
-    pub fn increase_height_up(&mut self, count: u16) {
-        self.y_coords -= count;
-        self.display_rows += count;
-        self.reflow_lines();
+    pub fn hold_with_exit_code(&mut self, exit_code: i32) {
+        // the exit code is drawn over the pane rather than written to it (see Tab::draw_on), so
+        // that it stays out of what the pane's process wrote
+        self.exit_code = Some(exit_code);
         self.should_render = true;
     }
-    pub fn reduce_height_up(&mut self, count: u16) {
-        self.display_rows -= count;
-        self.reflow_lines();
-        self.should_render = true;
+    pub fn handle_pty_bytes(&mut self, bytes: &[u8]) {
+        // the parser calls back into this pane (see vte::Perform below) for everything it parsed
+        let mut vte_parser = ::std::mem::take(&mut self.vte_parser);
+        for byte in bytes {
+            vte_parser.advance(self, *byte);
+        }
+        self.vte_parser = vte_parser;
     }
     pub fn change_size(&mut self, ws: &Winsize) {
         self.display_cols = ws.ws_col;
@@ -136,35 +86,49 @@ impl TerminalPane {
     fn reflow_lines (&mut self) {
         self.scroll.change_size(self.display_cols as usize, self.display_rows as usize);
     }
-    pub fn buffer_as_vte_output(&mut self) -> Option<String> {
-        if self.should_render {
-            let mut vte_output = String::new();
-            let buffer_lines = &self.read_buffer_as_lines();
-            let display_cols = &self.display_cols;
+    pub fn draw_on(&mut self, output_grid: &mut OutputGrid) {
+        let buffer_lines = self.read_buffer_as_lines();
+        for (row, line) in buffer_lines.iter().enumerate().take(self.display_rows as usize) {
+            // in some cases (eg. while resizing) some characters will spill over before they are
+            // corrected by the shell (for the prompt) or by reflowing lines
+            for (col, t_character) in line.iter().enumerate().take(self.display_cols as usize) {
+                output_grid.set_cell(self.x_coords as usize + col, self.y_coords as usize + row, *t_character);
+            }
+        }
+        self.should_render = false;
+    }
+    pub fn capture(&self, ansi: bool, history: bool) -> String {
+        let lines = if history {
+            self.scroll.history_as_character_lines()
+        } else {
+            self.read_buffer_as_lines()
+        };
+        let mut captured_lines = Vec::with_capacity(lines.len());
+        for line in lines {
+            let mut captured_line = String::new();
             let mut character_styles = CharacterStyles::new();
-            for (row, line) in buffer_lines.iter().enumerate() {
-                vte_output = format!("{}\u{1b}[{};{}H\u{1b}[m", vte_output, self.y_coords as usize + row + 1, self.x_coords + 1); // goto row/col and reset styles
-                for (col, t_character) in line.iter().enumerate() {
-                    if (col as u16) < *display_cols {
-                        // in some cases (eg. while resizing) some characters will spill over
-                        // before they are corrected by the shell (for the prompt) or by reflowing
-                        // lines
-                        if let Some(new_styles) = character_styles.update_and_return_diff(&t_character.styles) {
-                            // the terminal keeps the previous styles as long as we're in the same
-                            // line, so we only want to update the new styles here (this also
-                            // includes resetting previous styles as needed)
-                            vte_output = format!("{}{}", vte_output, new_styles);
-                        }
-                        vte_output.push(t_character.character);
+            for t_character in line.iter().take(self.display_cols as usize) {
+                if ansi {
+                    if let Some(new_styles) = character_styles.update_and_return_diff(&t_character.styles) {
+                        write!(captured_line, "{}", new_styles).unwrap();
                     }
                 }
-                character_styles.clear();
+                captured_line.push(t_character.character);
+            }
+            if ansi {
+                captured_line.push_str("\u{1b}[m");
+            } else {
+                // padding is not part of what was printed
+                captured_line.truncate(captured_line.trim_end().len());
+            }
+            captured_lines.push(captured_line);
+        }
+        if !ansi {
+            while captured_lines.last().map(|line| line.is_empty()).unwrap_or(false) {
+                captured_lines.pop();
             }
-            self.should_render = false;
-            Some(vte_output)
-        } else {
-            None
         }
+        captured_lines.join("\n")
     }
     pub fn read_buffer_as_lines (&self) -> Vec<Vec<TerminalCharacter>> {
         self.scroll.as_character_lines()
@@ -219,6 +183,7 @@ impl vte::Perform for TerminalPane {
             styles: self.pending_styles,
         };
         self.scroll.add_character(terminal_character);
+        self.should_render = true;
     }
 
     fn execute(&mut self, byte: u8) {
@@ -228,6 +193,8 @@ impl vte::Perform for TerminalPane {
             self.move_cursor_backwards(1);
         } else if byte == 10 { // 0a, newline
             self.add_newline();
+        } else if byte == 7 { // bell
+            self.rang_bell = true;
         }
     }
 
@@ -243,8 +210,17 @@ impl vte::Perform for TerminalPane {
         // TBD
     }
 
-    fn osc_dispatch(&mut self, _params: &[&[u8]], _bell_terminated: bool) {
-        // TBD
+    fn osc_dispatch(&mut self, params: &[&[u8]], _bell_terminated: bool) {
+        match params {
+            // set the window title (2), or both it and the icon name (0). the parser splits the
+            // title on any ';' in it, so it's put back together
+            [b"0", title @ ..] | [b"2", title @ ..] if !title.is_empty() => {
+                self.title = Some(String::from_utf8_lossy(&title.join(&b';')).to_string());
+            }
+            _ => {
+                // TBD
+            }
+        }
     }
 
     fn csi_dispatch(&mut self, params: &[i64], _intermediates: &[u8], _ignore: bool, c: char) {
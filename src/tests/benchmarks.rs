@@ -0,0 +1,131 @@
+use ::std::os::unix::io::RawFd;
+use ::std::path::PathBuf;
+use ::std::sync::mpsc::{channel, Receiver};
+use ::std::thread;
+use ::std::time::{Duration, Instant};
+use ::nix::pty::Winsize;
+
+use crate::os_input_output::{get_os_input, ServerOsApi};
+use crate::pty_bus::{stream_terminal_bytes, RunCommand, TerminalAction};
+use crate::screen::ScreenInstruction;
+use crate::terminal_pane::TerminalPane;
+
+/*
+ * Benchmarks
+ *
+ * these run real processes in real ptys, and so are ignored along with the rest of the tests.
+ * run them with: cargo test benchmarks -- --ignored --nocapture --test-threads 1
+ *
+ */
+
+fn spawn_streamed_command(os_input: &mut Box<dyn ServerOsApi>, command: &str, args: &[&str]) -> (RawFd, RawFd, Receiver<ScreenInstruction>) {
+    let run_command = RunCommand {
+        command: PathBuf::from(command),
+        args: args.iter().map(|arg| arg.to_string()).collect(),
+        ..RunCommand::default()
+    };
+    let (pid_primary, pid_secondary) = os_input.spawn_terminal(Some(TerminalAction::RunCommand(run_command))).unwrap();
+    let (send_screen_instructions, receive_screen_instructions) = channel();
+    stream_terminal_bytes(pid_primary, pid_secondary, false, send_screen_instructions, os_input.clone(), None);
+    (pid_primary, pid_secondary, receive_screen_instructions)
+}
+
+fn process_cpu_time() -> Duration {
+    let mut usage: libc::rusage = unsafe { ::std::mem::zeroed() };
+    unsafe { libc::getrusage(libc::RUSAGE_SELF, &mut usage) };
+    let to_duration = |time: libc::timeval| Duration::from_secs(time.tv_sec as u64) + Duration::from_micros(time.tv_usec as u64);
+    to_duration(usage.ru_utime) + to_duration(usage.ru_stime)
+}
+
+#[test]
+#[ignore]
+pub fn pane_output_latency() {
+    // how long it takes from something being written to a pane's pty until the screen is told to render it
+    let mut os_input: Box<dyn ServerOsApi> = Box::new(get_os_input());
+    let (pid_primary, pid_secondary, receive_screen_instructions) = spawn_streamed_command(&mut os_input, "cat", &[]);
+    thread::sleep(Duration::from_millis(200));
+    while receive_screen_instructions.try_recv().is_ok() {}
+
+    let mut latencies = vec![];
+    for _ in 0..100 {
+        let sent_at = Instant::now();
+        os_input.write_to_tty_stdin(pid_primary, &mut [b'x']).unwrap();
+        loop {
+            if let ScreenInstruction::Render = receive_screen_instructions.recv().unwrap() {
+                break;
+            }
+        }
+        latencies.push(sent_at.elapsed());
+        thread::sleep(Duration::from_millis(20));
+        while receive_screen_instructions.try_recv().is_ok() {}
+    }
+    latencies.sort();
+    let mean = latencies.iter().sum::<Duration>() / latencies.len() as u32;
+    println!("pane output latency: mean {:?}, median {:?}, max {:?}", mean, latencies[latencies.len() / 2], latencies[latencies.len() - 1]);
+    os_input.kill(pid_secondary).unwrap();
+    thread::sleep(Duration::from_millis(500));
+}
+
+#[test]
+#[ignore]
+pub fn idle_panes_cpu_usage() {
+    let pane_count = 20;
+    let measured_for = Duration::from_secs(3);
+    let mut os_input: Box<dyn ServerOsApi> = Box::new(get_os_input());
+    let panes: Vec<_> = (0..pane_count).map(|_| spawn_streamed_command(&mut os_input, "sleep", &["100"])).collect();
+    thread::sleep(Duration::from_millis(500));
+
+    let cpu_time_before = process_cpu_time();
+    thread::sleep(measured_for);
+    let cpu_time = process_cpu_time() - cpu_time_before;
+    println!(
+        "cpu usage of {} idle panes: {:?} over {:?} ({:.2}%)",
+        pane_count,
+        cpu_time,
+        measured_for,
+        cpu_time.as_secs_f64() / measured_for.as_secs_f64() * 100.0
+    );
+    for (_pid_primary, pid_secondary, _receive_screen_instructions) in panes.iter() {
+        os_input.kill(*pid_secondary).unwrap();
+    }
+    // the panes' streams end along with their processes, with one last render
+    thread::sleep(Duration::from_millis(500));
+}
+
+#[test]
+#[ignore]
+pub fn pane_output_throughput() {
+    // how fast a pane can go through a large output (eg. cat-ing a big file), parsing and
+    // applying it to the pane, but not rendering it. the output clears the screen every 1000 lines,
+    // since the pane keeps all of its scrollback and growing it would be measured instead
+    let output_size = 100 * 1024 * 1024;
+    let mut os_input: Box<dyn ServerOsApi> = Box::new(get_os_input());
+    let ws = Winsize { ws_row: 24, ws_col: 80, ws_xpixel: 0, ws_ypixel: 0 };
+    let started_at = Instant::now();
+    let output_command = format!(
+        "yes 'the quick brown fox jumps over the lazy dog' | awk 'NR % 1000 == 0 {{ printf \"\\033[2J\" }} 1' | head -c {}",
+        output_size
+    );
+    let (pid_primary, _pid_secondary, receive_screen_instructions) = spawn_streamed_command(&mut os_input, "sh", &["-c", &output_command]);
+    let mut terminal_pane = TerminalPane::new(pid_primary, ws, 0, 0);
+    os_input.set_terminal_size_using_fd(pid_primary, ws.ws_col, ws.ws_row);
+    let mut bytes_applied = 0;
+    loop {
+        match receive_screen_instructions.recv().unwrap() {
+            ScreenInstruction::PtyBytes(_pid, bytes, batch_applied) => {
+                terminal_pane.handle_pty_bytes(&bytes);
+                bytes_applied += bytes.len();
+                batch_applied.send(()).ok();
+            }
+            ScreenInstruction::ProcessExited(..) => break,
+            _ => {}
+        }
+    }
+    let elapsed = started_at.elapsed();
+    println!(
+        "pane output throughput: {} bytes in {:?} ({:.1} MB/s)",
+        bytes_applied,
+        elapsed,
+        bytes_applied as f64 / 1024.0 / 1024.0 / elapsed.as_secs_f64()
+    );
+}
@@ -1,31 +1,53 @@
 use ::std::time::Duration;
 use ::nix::pty::Winsize;
 use ::std::os::unix::io::RawFd;
-use ::std::io::{Read, Write};
-use ::std::collections::HashMap;
+use ::std::io::{self, Read, Write};
+use ::std::collections::{HashMap, HashSet};
+use ::std::os::unix::net::UnixStream;
 use ::std::sync::{Arc, Mutex};
+use ::std::sync::mpsc::{channel, Sender, Receiver};
 use ::std::path::PathBuf;
+use ::std::future::Future;
+use ::std::pin::Pin;
+use ::futures::channel::oneshot;
 
-use crate::os_input_output::OsApi;
+use crate::Opt;
+use crate::ipc::ClientConnections;
+use crate::os_input_output::{ClientOsApi, PtyReadiness, ServerOsApi};
+use crate::pty_bus::TerminalAction;
+use crate::server::start_server;
 use crate::tests::possible_tty_inputs::{Bytes, get_possible_tty_inputs};
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum IoEvent {
     Kill(RawFd),
     SetTerminalSizeUsingFd(RawFd, u16, u16),
     IntoRawMode(RawFd),
+    UnsetRawMode(RawFd),
     TcDrain(RawFd),
+    CloseTty(RawFd),
+}
+
+// the fake ptys never run out of things to read, they end instead
+struct AlwaysReadable;
+
+impl PtyReadiness for AlwaysReadable {
+    fn readable(&self) -> Pin<Box<dyn Future<Output = ()> + Send + '_>> {
+        Box::pin(async {})
+    }
 }
 
 pub struct FakeStdinReader {
     pub input_chars: Bytes,
+    more_input: Option<Receiver<Vec<u8>>>, // see keep_terminal_open
 }
 
 impl FakeStdinReader {
-    pub fn new(input_chars: Vec<u8>) -> Self {
+    pub fn new(input_chars: Vec<u8>, more_input: Option<Receiver<Vec<u8>>>) -> Self {
         let bytes = Bytes::new().content(input_chars);
         FakeStdinReader {
             input_chars: bytes,
+            more_input,
         }
     }
 }
@@ -37,6 +59,17 @@ impl Read for FakeStdinReader {
         ::std::thread::sleep(Duration::from_millis(50));
         // ::std::thread::sleep(Duration::from_millis(100));
         let read_position = self.input_chars.read_position;
+        if read_position >= self.input_chars.content.len() {
+            match self.more_input.as_ref().map(|more_input| more_input.recv()) {
+                Some(Ok(more_input_chars)) => self.input_chars.content.extend(more_input_chars),
+                Some(Err(_)) => {
+                    self.more_input = None;
+                    self.input_chars.content.push(17); // ctrl-q (quit)
+                }
+                None => return Ok(0),
+            }
+            return self.read(buf);
+        }
         buf[0] = self.input_chars.content[read_position];
         self.input_chars.set_read_position(read_position + 1);
         Ok(1)
@@ -68,66 +101,218 @@ impl Write for FakeStdoutWriter {
     }
 }
 
+// each terminal that was spawned, with what it was spawned to run
+type TerminalActions = Arc<Mutex<Vec<(RawFd, Option<TerminalAction>)>>>;
+
 #[derive(Clone)]
 pub struct FakeInputOutput {
     read_buffers: Arc<Mutex<HashMap<RawFd, Bytes>>>,
+    all_read: Arc<Mutex<HashSet<RawFd>>>, // the ptys that ended, once everything they had was read
     input_to_add: Arc<Mutex<Option<Vec<u8>>>>,
+    more_input: Arc<Mutex<Option<Receiver<Vec<u8>>>>>,
     stdin_writes: Arc<Mutex<HashMap<RawFd, Vec<u8>>>>,
+    stdin_room: Arc<Mutex<HashMap<RawFd, usize>>>, // of the panes whose process isn't reading its input
+    has_editor: bool, // like EDITOR or VISUAL being set, files can't be opened without them
+    terminal_actions: TerminalActions,
+    exit_codes: Arc<Mutex<HashMap<RawFd, i32>>>, // of the processes that should exit once their output was read
+    send_exit_codes: Arc<Mutex<HashMap<RawFd, oneshot::Sender<i32>>>>, // kept until then, or forever
+    background_jobs: Arc<Mutex<HashSet<RawFd>>>, // that keep the pty of their process open after it exits
     pub stdout_writer: FakeStdoutWriter, // stdout_writer.output is already an arc/mutex
     io_events: Arc<Mutex<Vec<IoEvent>>>,
     win_sizes: Arc<Mutex<HashMap<RawFd, Winsize>>>,
     possible_tty_inputs: HashMap<u16, Bytes>,
+    // connecting a client hands one end of a socket pair to the server, as if it were accepted
+    // on the session's socket
+    send_client_connections: Arc<Mutex<Sender<UnixStream>>>,
+    receive_client_connections: Arc<Mutex<Option<Receiver<UnixStream>>>>,
+    // stands in for SIGWINCH, the client waits on it once its terminal's size was changed
+    send_sigwinch: Arc<Mutex<Sender<()>>>,
+    receive_sigwinch: Arc<Mutex<Option<Receiver<()>>>>,
 }
 
 impl FakeInputOutput {
     pub fn new(winsize: Winsize) -> Self {
         let mut win_sizes = HashMap::new();
         win_sizes.insert(0 , winsize); // 0 is the current terminal
+        let (send_client_connections, receive_client_connections) = channel();
+        let (send_sigwinch, receive_sigwinch) = channel();
         FakeInputOutput {
             read_buffers: Arc::new(Mutex::new(HashMap::new())),
+            all_read: Arc::new(Mutex::new(HashSet::new())),
             stdin_writes: Arc::new(Mutex::new(HashMap::new())),
+            stdin_room: Arc::new(Mutex::new(HashMap::new())),
+            has_editor: true,
+            terminal_actions: Arc::new(Mutex::new(vec![])),
+            exit_codes: Arc::new(Mutex::new(HashMap::new())),
+            send_exit_codes: Arc::new(Mutex::new(HashMap::new())),
+            background_jobs: Arc::new(Mutex::new(HashSet::new())),
             input_to_add: Arc::new(Mutex::new(None)),
+            more_input: Arc::new(Mutex::new(None)),
             stdout_writer: FakeStdoutWriter::default(),
             io_events: Arc::new(Mutex::new(vec![])),
             win_sizes: Arc::new(Mutex::new(win_sizes)),
             possible_tty_inputs: get_possible_tty_inputs(),
+            send_client_connections: Arc::new(Mutex::new(send_client_connections)),
+            receive_client_connections: Arc::new(Mutex::new(Some(receive_client_connections))),
+            send_sigwinch: Arc::new(Mutex::new(send_sigwinch)),
+            receive_sigwinch: Arc::new(Mutex::new(Some(receive_sigwinch))),
         }
     }
     pub fn with_tty_inputs(mut self, tty_inputs: HashMap<u16, Bytes>) -> Self {
         self.possible_tty_inputs = tty_inputs;
         self
     }
+    pub fn without_editor(mut self) -> Self {
+        self.has_editor = false;
+        self
+    }
     pub fn add_terminal_input(&mut self, input: &[u8]) {
         self.input_to_add = Arc::new(Mutex::new(Some(input.to_vec())));
     }
+    pub fn keep_terminal_open(&mut self) -> Sender<Vec<u8>> {
+        // rather than quitting once it typed the input that was added, the terminal waits for
+        // more of it to be sent, and quits once the sender is dropped. this lets tests look at the
+        // session (and type into it) when it's in the state they expect, instead of guessing how
+        // long it takes to get there
+        let (send_more_input, receive_more_input) = channel();
+        self.more_input = Arc::new(Mutex::new(Some(receive_more_input)));
+        send_more_input
+    }
+    pub fn new_client(&self, winsize: Winsize) -> Self {
+        // another terminal attaching to the session served by this fake: it has its own size,
+        // input and output, but reaches the same server
+        let mut win_sizes = HashMap::new();
+        win_sizes.insert(0, winsize);
+        let mut client = self.clone();
+        client.win_sizes = Arc::new(Mutex::new(win_sizes));
+        client.input_to_add = Arc::new(Mutex::new(None));
+        client.more_input = Arc::new(Mutex::new(None));
+        client.stdout_writer = FakeStdoutWriter::default();
+        let (send_sigwinch, receive_sigwinch) = channel();
+        client.send_sigwinch = Arc::new(Mutex::new(send_sigwinch));
+        client.receive_sigwinch = Arc::new(Mutex::new(Some(receive_sigwinch)));
+        client
+    }
+    pub fn resize_terminal(&self, winsize: Winsize) {
+        // as if the user resized the window of the terminal this client runs in
+        self.win_sizes.lock().unwrap().insert(0, winsize);
+        self.send_sigwinch.lock().unwrap().send(()).unwrap();
+    }
     pub fn add_terminal(&mut self, fd: RawFd) {
         self.stdin_writes.lock().unwrap().insert(fd, vec![]);
     }
+    pub fn set_exit_code(&mut self, terminal_id: RawFd, exit_code: i32) {
+        // processes that don't have an exit code keep running after their output was read
+        self.exit_codes.lock().unwrap().insert(terminal_id, exit_code);
+    }
+    pub fn leave_background_job(&mut self, terminal_id: RawFd) {
+        // once its output was read the process exits, but its pty never ends: it just has nothing
+        // more to read, like when a job the process left in the background still has it open
+        self.background_jobs.lock().unwrap().insert(terminal_id);
+    }
+    pub fn terminal_actions(&self) -> Vec<(RawFd, Option<TerminalAction>)> {
+        self.terminal_actions.lock().unwrap().clone()
+    }
+    pub fn all_output_read(&self) -> bool {
+        // every pane's output was sent to the screen, since its pty is only read to its end after that
+        // (locked in the same order as when reading them, so that the two can't wait on each other)
+        let read_buffers = self.read_buffers.lock().unwrap();
+        let all_read = self.all_read.lock().unwrap();
+        read_buffers.keys().all(|pid| all_read.contains(pid))
+    }
+    pub fn io_events(&self) -> Vec<IoEvent> {
+        self.io_events.lock().unwrap().clone()
+    }
+    pub fn stop_reading_stdin(&self, fd: RawFd, room: usize) {
+        // the pane's pty takes this many more bytes, and then writing to it fails with EAGAIN
+        self.stdin_room.lock().unwrap().insert(fd, room);
+    }
+    pub fn resume_reading_stdin(&self, fd: RawFd) {
+        self.stdin_room.lock().unwrap().remove(&fd);
+    }
+    pub fn stdin_writes_to(&self, fd: RawFd) -> Vec<u8> {
+        self.stdin_writes.lock().unwrap().get(&fd).cloned().unwrap_or_default()
+    }
 }
 
-impl OsApi for FakeInputOutput {
+impl ClientOsApi for FakeInputOutput {
     fn get_terminal_size_using_fd(&self, pid: RawFd) -> Winsize {
         let win_sizes = self.win_sizes.lock().unwrap();
         let winsize = win_sizes.get(&pid).unwrap();
         *winsize
     }
+    fn into_raw_mode(&mut self, pid: RawFd) {
+        self.io_events.lock().unwrap().push(IoEvent::IntoRawMode(pid));
+    }
+    fn unset_raw_mode(&mut self, pid: RawFd) {
+        self.io_events.lock().unwrap().push(IoEvent::UnsetRawMode(pid));
+    }
+    fn get_stdin_reader(&self) -> Box<dyn Read> {
+        let mut input_chars = vec![0];
+        if let Some(input_to_add) = self.input_to_add.lock().unwrap().as_ref() {
+            for byte in input_to_add {
+                input_chars.push(*byte);
+            }
+        }
+        let more_input = self.more_input.lock().unwrap().take();
+        if more_input.is_none() {
+            input_chars.push(17); // ctrl-q (quit)
+        }
+        let reader = FakeStdinReader::new(input_chars, more_input);
+        Box::new(reader)
+    }
+    fn get_stdout_writer(&self) -> Box<dyn Write> {
+        Box::new(self.stdout_writer.clone())
+    }
+    fn spawn_server(&mut self, opts: Opt) -> io::Result<()> {
+        let server_os_input = Box::new(self.clone());
+        ::std::thread::Builder::new()
+            .name("fake_server".to_string())
+            .spawn(move || start_server(server_os_input, opts))?;
+        Ok(())
+    }
+    fn connect_to_server(&mut self, _session_name: &str) -> io::Result<UnixStream> {
+        let (client_end, server_end) = UnixStream::pair()?;
+        self.send_client_connections.lock().unwrap().send(server_end).unwrap();
+        Ok(client_end)
+    }
+    fn receive_sigwinch(&self, on_resize: Box<dyn Fn() + Send>) {
+        // a client that attaches again with the same fake won't be resized, the first one was
+        if let Some(receive_sigwinch) = self.receive_sigwinch.lock().unwrap().take() {
+            for () in receive_sigwinch {
+                on_resize();
+            }
+        }
+    }
+    fn box_clone(&self) -> Box<dyn ClientOsApi> {
+        Box::new((*self).clone())
+    }
+}
+
+impl ServerOsApi for FakeInputOutput {
     fn set_terminal_size_using_fd(&mut self, pid: RawFd, cols: u16, rows: u16) {
         let terminal_input = self.possible_tty_inputs.get(&cols).expect(&format!("could not find input for size {:?}", cols));
         self.read_buffers.lock().unwrap().insert(pid, terminal_input.clone());
         self.io_events.lock().unwrap().push(IoEvent::SetTerminalSizeUsingFd(pid, cols, rows));
     }
-    fn into_raw_mode(&mut self, pid: RawFd) {
-        self.io_events.lock().unwrap().push(IoEvent::IntoRawMode(pid));
-    }
-    fn spawn_terminal(&mut self, file_to_open: Option<PathBuf>) -> (RawFd, RawFd) {
-        let next_terminal_id = { self.read_buffers.lock().unwrap().keys().len() as RawFd + 1 };
+    fn spawn_terminal(&mut self, terminal_action: Option<TerminalAction>) -> io::Result<(RawFd, RawFd)> {
+        if let (Some(TerminalAction::OpenFile(_)), false) = (&terminal_action, self.has_editor) {
+            return Err(io::Error::new(io::ErrorKind::NotFound, "can't edit files if an editor is not defined"));
+        }
+        // terminals can be spawned before the ones spawned earlier were given a size (eg. those of a
+        // layout file), so we count them as they're spawned
+        let next_terminal_id = { self.terminal_actions.lock().unwrap().len() as RawFd + 1 };
         self.add_terminal(next_terminal_id);
-        (next_terminal_id as i32, next_terminal_id + 1000) // secondary number is arbitrary here
+        self.terminal_actions.lock().unwrap().push((next_terminal_id, terminal_action));
+        Ok((next_terminal_id as i32, next_terminal_id + 1000)) // secondary number is arbitrary here
     }
     fn read_from_tty_stdout(&mut self, pid: RawFd, buf: &mut [u8]) -> Result<usize, nix::Error> {
         let mut attempts_left = 3;
         loop {
-            if attempts_left < 3 {
+            if attempts_left == 0 {
+                // eg. a pane that was refused (and so never sized) before it was ever shown
+                return Ok(0);
+            } else if attempts_left < 3 {
                 // this sometimes happens because in the context of the tests,
                 // the read_buffers are set in set_terminal_size_using_fd
                 // which sometimes happens after the first read and then the tests get messed up
@@ -135,8 +320,6 @@ impl OsApi for FakeInputOutput {
                 // in the tests are asserted against exact copies, and so a slight variation makes
                 // them fail
                 ::std::thread::sleep(::std::time::Duration::from_millis(25));
-            } else if attempts_left == 0 {
-                return Ok(0);
             }
             let mut read_buffers = self.read_buffers.lock().unwrap();
             let mut bytes_read = 0;
@@ -149,6 +332,17 @@ impl OsApi for FakeInputOutput {
                     if bytes_read > bytes.read_position {
                         bytes.set_read_position(bytes_read);
                     }
+                    if bytes_read == 0 {
+                        self.all_read.lock().unwrap().insert(pid);
+                        if let Some(exit_code) = self.exit_codes.lock().unwrap().get(&pid) {
+                            if let Some(send_exit_code) = self.send_exit_codes.lock().unwrap().remove(&pid) {
+                                send_exit_code.send(*exit_code).ok();
+                            }
+                        }
+                        if self.background_jobs.lock().unwrap().contains(&pid) {
+                            return Err(nix::Error::Sys(nix::errno::Errno::EAGAIN));
+                        }
+                    }
                     return Ok(bytes_read);
                 },
                 None => {
@@ -157,9 +351,21 @@ impl OsApi for FakeInputOutput {
             }
         }
     }
+    fn tty_readiness(&self, _pid: RawFd) -> Box<dyn PtyReadiness> {
+        Box::new(AlwaysReadable)
+    }
     fn write_to_tty_stdin(&mut self, pid: RawFd, buf: &mut [u8]) -> Result<usize, nix::Error> {
         let mut stdin_writes = self.stdin_writes.lock().unwrap();
         let write_buffer = stdin_writes.get_mut(&pid).unwrap();
+        let mut buf = buf;
+        if let Some(room) = self.stdin_room.lock().unwrap().get_mut(&pid) {
+            if *room == 0 {
+                return Err(nix::Error::Sys(nix::errno::Errno::EAGAIN));
+            }
+            let taken = buf.len().min(*room);
+            *room -= taken;
+            buf = &mut buf[..taken];
+        }
         let mut bytes_written = 0;
         for byte in buf {
             bytes_written += 1;
@@ -167,29 +373,40 @@ impl OsApi for FakeInputOutput {
         }
         Ok(bytes_written)
     }
+    fn wait_until_tty_writable(&mut self, _pid: RawFd, _timeout: Duration) {
+        ::std::thread::sleep(Duration::from_millis(10));
+    }
     fn tcdrain(&mut self, pid: RawFd) -> Result<(), nix::Error> {
         self.io_events.lock().unwrap().push(IoEvent::TcDrain(pid));
         Ok(())
     }
-    fn box_clone(&self) -> Box<dyn OsApi> {
-        Box::new((*self).clone())
-    }
-    fn get_stdin_reader(&self) -> Box<dyn Read> {
-        let mut input_chars = vec![0];
-        if let Some(input_to_add) = self.input_to_add.lock().unwrap().as_ref() {
-            for byte in input_to_add {
-                input_chars.push(*byte);
-            }
-        }
-        input_chars.push(17); // ctrl-q (quit)
-        let reader = FakeStdinReader::new(input_chars);
-        Box::new(reader)
-    }
-    fn get_stdout_writer(&self) -> Box<dyn Write> {
-        Box::new(self.stdout_writer.clone())
-    }
     fn kill(&mut self, fd: RawFd) -> Result<(), nix::Error> {
         self.io_events.lock().unwrap().push(IoEvent::Kill(fd));
         Ok(())
     }
+    fn exit_code(&mut self, pid: RawFd) -> oneshot::Receiver<i32> {
+        // asked for as the pane starts being read, and sent once all of it was (see read_from_tty_stdout)
+        let terminal_id = pid - 1000; // see spawn_terminal
+        let (send_exit_code, receive_exit_code) = oneshot::channel();
+        self.send_exit_codes.lock().unwrap().insert(terminal_id, send_exit_code);
+        receive_exit_code
+    }
+    fn close_tty(&mut self, pid: RawFd) {
+        self.io_events.lock().unwrap().push(IoEvent::CloseTty(pid));
+    }
+    fn get_foreground_process_name(&self, _pid: RawFd) -> Option<String> {
+        Some(String::from("fake-shell"))
+    }
+    fn get_foreground_process_cwd(&self, _pid: RawFd) -> Option<PathBuf> {
+        Some(PathBuf::from("/tmp"))
+    }
+    fn bind_session_socket(&mut self, _session_name: &str) -> io::Result<ClientConnections> {
+        let receive_client_connections = self.receive_client_connections.lock().unwrap().take()
+            .expect("only one server can be started for each FakeInputOutput");
+        Ok(Box::new(receive_client_connections.into_iter()))
+    }
+    fn remove_session_socket(&mut self, _session_name: &str) {}
+    fn box_clone(&self) -> Box<dyn ServerOsApi> {
+        Box::new((*self).clone())
+    }
 }
@@ -84,8 +84,8 @@ pub fn split_largest_terminal () {
 
 #[test]
 pub fn resize_right_and_up_on_the_same_axis() {
-    // this is a specific test to explicitly ensure that a tmux-like pane-container algorithm is not
-    // implemented (this test can never pass with such an algorithm)
+    // the panes are laid out in rows here, so resizing right only moves the border in the row of
+    // the focused pane, while resizing up moves the border between the rows for all of their panes
     //
     // ┌─────┬─────┐                   ┌─────┬─────┐
     // │     │     │                   │     │     │
@@ -94,8 +94,8 @@ pub fn resize_right_and_up_on_the_same_axis() {
     // └─────┴─────┘                   └───────┴───┘
     //
     // ┌─────┬─────┐                   ┌─────┬─────┐
-    // │     │     │                   ├─────┤     │
-    // ├─────┼─────┤ ==resize=up==>    │█████├─────┤
+    // │     │     │                   ├─────┼─────┤
+    // ├─────┼─────┤ ==resize=up==>    │█████│     │
     // │█████│     │                   │█████│     │
     // └─────┴─────┘                   └─────┴─────┘
     // █ == focused pane
@@ -157,3 +157,25 @@ pub fn max_panes () {
         assert_snapshot!(snapshot);
     }
 }
+
+#[test]
+pub fn only_changes_are_rendered () {
+    // after the first frame, only the cells that changed since the previous one are sent to the
+    // client, so moving focus between panes just moves the cursor
+    let fake_win_size = Winsize {
+        ws_col: 121,
+        ws_row: 20,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+    let mut fake_input_output = get_fake_os_input(&fake_win_size);
+    fake_input_output.add_terminal_input(&[14, 16, 17]); // split-vertically, change focus and quit (ctrl-n + ctrl-p + ctrl-q)
+    start(Box::new(fake_input_output.clone()), Opt::default());
+    let output_frames = fake_input_output.stdout_writer.output_frames.lock().unwrap();
+    let change_focus_frame = String::from_utf8_lossy(&output_frames[output_frames.len() - 2]); // the last one says goodbye
+    assert_eq!(change_focus_frame, "\u{1b}[m\u{1b}[20;10H\u{1b}[m", "only the cursor moved");
+    let snapshots = get_output_frame_snapshots(&output_frames, &fake_win_size);
+    for snapshot in snapshots {
+        assert_snapshot!(snapshot);
+    }
+}
@@ -1,9 +1,10 @@
 use ::nix::pty::Winsize;
 use ::insta::assert_snapshot;
+use ::std::thread;
 
 use crate::{start, Opt};
-use crate::tests::fakes::{FakeInputOutput};
-use crate::tests::utils::get_output_frame_snapshots;
+use crate::tests::fakes::{FakeInputOutput, IoEvent};
+use crate::tests::utils::{get_output_frame_snapshots, wait_for_state, wait_until};
 
 fn get_fake_os_input (fake_win_size: &Winsize) -> FakeInputOutput {
     FakeInputOutput::new(fake_win_size.clone())
@@ -233,8 +234,8 @@ pub fn close_pane_with_multiple_panes_to_the_right() {
 pub fn close_pane_with_multiple_panes_above_it_away_from_screen_edges() {
     // ┌───┬───┬───┬───┐            ┌───┬───┬───┬───┐
     // │xxx│xxx│xxx│xxx│            │xxx│xxx│xxx│xxx│
-    // ├───┤xxx│xxx├───┤            ├───┤xxx│xxx├───┤
-    // │xxx├───┴───┤xxx│ ==close==> │xxx│xxx│xxx│xxx│
+    // │xxx├───┴───┤xxx│            │xxx│xxx│xxx│xxx│
+    // ├───┤███████├───┤ ==close==> ├───┤xxx│xxx├───┤
     // │xxx│███████│xxx│            │xxx│xxx│xxx│xxx│
     // │xxx│███████│xxx│            │xxx│xxx│xxx│xxx│
     // └───┴───────┴───┘            └───┴───┴───┴───┘
@@ -246,12 +247,12 @@ pub fn close_pane_with_multiple_panes_above_it_away_from_screen_edges() {
         ws_ypixel: 0,
     };
     let mut fake_input_output = get_fake_os_input(&fake_win_size);
-    // split-horizontally, split-vertically * 2, change-focus, split-vertically * 2, resize-up,
-    // change-focus, resize-up, close focuse * 4, split-vertically, change-focus * 3, close-focused-pane and quit
+    // split-vertically * 2, split-horizontally, change-focus, split-horizontally, change-focus * 2,
+    // split-horizontally, change-focus * 2, split-vertically, change-focus * 6, resize-up, close-focused-pane and quit
     //
-    // (ctrl-b + ctrl-n + ctrl-n + ctrl-p + ctrl-n + ctrl-n + ctrl-k + ctrl-p + ctrl-k + ctrl-p * 4
-    // + ctrl-n + ctrl-p * 3 + ctrl-x + ctrl-q)
-    fake_input_output.add_terminal_input(&[2, 14, 14, 16, 14, 14, 11, 16, 11, 16, 16, 16, 16, 14, 16, 16, 16, 24, 17]);
+    // (ctrl-n * 2 + ctrl-b + ctrl-p + ctrl-b + ctrl-p * 2 + ctrl-b + ctrl-p * 2 + ctrl-n + ctrl-p * 6
+    // + ctrl-k + ctrl-x + ctrl-q)
+    fake_input_output.add_terminal_input(&[14, 14, 2, 16, 2, 16, 16, 2, 16, 16, 14, 16, 16, 16, 16, 16, 16, 11, 24, 17]);
     start(Box::new(fake_input_output.clone()), Opt::default());
 
     let output_frames = fake_input_output.stdout_writer.output_frames.lock().unwrap();
@@ -266,8 +267,8 @@ pub fn close_pane_with_multiple_panes_below_it_away_from_screen_edges() {
     // ┌───┬───────┬───┐            ┌───┬───┬───┬───┐
     // │xxx│███████│xxx│            │xxx│xxx│xxx│xxx│
     // │xxx│███████│xxx│            │xxx│xxx│xxx│xxx│
-    // │xxx├───┬───┤xxx│ ==close==> │xxx│xxx│xxx│xxx│
-    // ├───┤xxx│xxx├───┤            ├───┤xxx│xxx├───┤
+    // ├───┤███████├───┤ ==close==> ├───┤xxx│xxx├───┤
+    // │xxx├───┬───┤xxx│            │xxx│xxx│xxx│xxx│
     // │xxx│xxx│xxx│xxx│            │xxx│xxx│xxx│xxx│
     // └───┴───┴───┴───┘            └───┴───┴───┴───┘
     // █ == pane being closed
@@ -278,12 +279,12 @@ pub fn close_pane_with_multiple_panes_below_it_away_from_screen_edges() {
         ws_ypixel: 0,
     };
     let mut fake_input_output = get_fake_os_input(&fake_win_size);
-    // split-horizontally, split-vertically * 2, change-focus, split-vertically * 2,
-    // resize-down, change-focus, resize-down, change-focus * 2, split-vertically, change-focus * 5, close-focused-pane and quit
+    // split-vertically * 2, split-horizontally, change-focus, split-horizontally, change-focus * 2,
+    // split-horizontally, split-vertically, change-focus * 2, resize-down, close-focused-pane and quit
     //
-    // (ctrl-b + ctrl-n + ctrl-n + ctrl-p + ctrl-n + ctrl-n + ctrl-j + ctrl-p + ctrl-j + ctrl-p * 2
-    // + ctrl-n + ctrl-p * 5 + ctrl-x + ctrl-q)
-    fake_input_output.add_terminal_input(&[2, 14, 14, 16, 14, 14, 10, 16, 10, 16, 16, 14, 16, 16, 16, 16, 16, 24, 17]);
+    // (ctrl-n * 2 + ctrl-b + ctrl-p + ctrl-b + ctrl-p * 2 + ctrl-b + ctrl-n + ctrl-p * 2 + ctrl-j
+    // + ctrl-x + ctrl-q)
+    fake_input_output.add_terminal_input(&[14, 14, 2, 16, 2, 16, 16, 2, 14, 16, 16, 10, 24, 17]);
     start(Box::new(fake_input_output.clone()), Opt::default());
 
     let output_frames = fake_input_output.stdout_writer.output_frames.lock().unwrap();
@@ -295,15 +296,15 @@ pub fn close_pane_with_multiple_panes_below_it_away_from_screen_edges() {
 
 #[test]
 pub fn close_pane_with_multiple_panes_to_the_left_away_from_screen_edges() {
-    // ┌────┬──────┐            ┌────┬──────┐
-    // │xxxx│xxxxxx│            │xxxx│xxxxxx│
-    // ├────┴┬─────┤            ├────┴──────┤
-    // │xxxxx│█████│            │xxxxxxxxxxx│
-    // ├─────┤█████│ ==close==> ├───────────┤
-    // │xxxxx│█████│            │xxxxxxxxxxx│
-    // ├────┬┴─────┤            ├────┬──────┤
-    // │xxxx│xxxxxx│            │xxxx│xxxxxx│
-    // └────┴──────┘            └────┴──────┘
+    // ┌─────┬─────┐            ┌─────┬─────┐
+    // │xxxxx│xxxxx│            │xxxxx│xxxxx│
+    // ├────┬┴─────┤            ├─────┴─────┤
+    // │xxxx│██████│            │xxxxxxxxxxx│
+    // ├────┤██████│ ==close==> ├───────────┤
+    // │xxxx│██████│            │xxxxxxxxxxx│
+    // ├────┴┬─────┤            ├─────┬─────┤
+    // │xxxxx│xxxxx│            │xxxxx│xxxxx│
+    // └─────┴─────┘            └─────┴─────┘
     // █ == pane being closed
     let fake_win_size = Winsize {
         ws_col: 121,
@@ -312,13 +313,13 @@ pub fn close_pane_with_multiple_panes_to_the_left_away_from_screen_edges() {
         ws_ypixel: 0,
     };
     let mut fake_input_output = get_fake_os_input(&fake_win_size);
-    // split-vertically, split-horizontally * 2, change-focus, split-horizontally * 2, resize-left,
-    // change-focus, resize-left, change-focus * 4, split-horizontally, change-focus * 3,
+    // split-horizontally * 2, split-vertically, change-focus, split-vertically, change-focus * 2,
+    // split-vertically, change-focus * 2, split-horizontally, change-focus * 6, resize-left,
     // close-focused pane and quit
     //
-    // (ctrl-n + ctrl-b + ctrl-b + ctrl-p + ctrl-b + ctrl-b + ctrl-h + ctrl-p + ctrl-h + ctrl-p * 4
-    // + ctrl-b + ctrl-p * 3 + ctrl-x + ctrl-q)
-    fake_input_output.add_terminal_input(&[14, 2, 2, 16, 2, 2, 8, 16, 8, 16, 16, 16, 16, 2, 16, 16, 16, 24, 17]);
+    // (ctrl-b * 2 + ctrl-n + ctrl-p + ctrl-n + ctrl-p * 2 + ctrl-n + ctrl-p * 2 + ctrl-b + ctrl-p * 6
+    // + ctrl-h + ctrl-x + ctrl-q)
+    fake_input_output.add_terminal_input(&[2, 2, 14, 16, 14, 16, 16, 14, 16, 16, 2, 16, 16, 16, 16, 16, 16, 8, 24, 17]);
     start(Box::new(fake_input_output.clone()), Opt::default());
 
     let output_frames = fake_input_output.stdout_writer.output_frames.lock().unwrap();
@@ -330,15 +331,15 @@ pub fn close_pane_with_multiple_panes_to_the_left_away_from_screen_edges() {
 
 #[test]
 pub fn close_pane_with_multiple_panes_to_the_right_away_from_screen_edges() {
-    // ┌────┬──────┐            ┌────┬──────┐
-    // │xxxx│xxxxxx│            │xxxx│xxxxxx│
-    // ├────┴┬─────┤            ├────┴──────┤
-    // │█████│xxxxx│            │xxxxxxxxxxx│
-    // │█████├─────┤ ==close==> ├───────────┤
-    // │█████│xxxxx│            │xxxxxxxxxxx│
-    // ├────┬┴─────┤            ├────┬──────┤
-    // │xxxx│xxxxxx│            │xxxx│xxxxxx│
-    // └────┴──────┘            └────┴──────┘
+    // ┌─────┬─────┐            ┌─────┬─────┐
+    // │xxxxx│xxxxx│            │xxxxx│xxxxx│
+    // ├─────┴┬────┤            ├─────┴─────┤
+    // │██████│xxxx│            │xxxxxxxxxxx│
+    // │██████├────┤ ==close==> ├───────────┤
+    // │██████│xxxx│            │xxxxxxxxxxx│
+    // ├─────┬┴────┤            ├─────┬─────┤
+    // │xxxxx│xxxxx│            │xxxxx│xxxxx│
+    // └─────┴─────┘            └─────┴─────┘
     // █ == pane being closed
     let fake_win_size = Winsize {
         ws_col: 121,
@@ -347,12 +348,12 @@ pub fn close_pane_with_multiple_panes_to_the_right_away_from_screen_edges() {
         ws_ypixel: 0,
     };
     let mut fake_input_output = get_fake_os_input(&fake_win_size);
-    // split-vertically, split-horizontally * 2, change-focus, split-horizontally * 2, resize-left,
-    // change-focus, resize-left, change-focus * 2, split-horizontally, change-focus * 5, close-focused-pane and quit
+    // split-horizontally * 2, split-vertically, change-focus, split-vertically, change-focus * 2,
+    // split-vertically, split-horizontally, change-focus * 2, resize-right, close-focused-pane and quit
     //
-    // (ctrl-n + ctrl-b + ctrl-b + ctrl-p + ctrl-b + ctrl-b + ctrl-h + ctrl-p + ctrl-h + ctrl-p * 2
-    // + ctrl-b + ctrl-p * 5 + ctrl-x + ctrl-q)
-    fake_input_output.add_terminal_input(&[14, 2, 2, 16, 2, 2, 8, 16, 8, 16, 16, 2, 16, 16, 16, 16, 16, 24, 17]);
+    // (ctrl-b * 2 + ctrl-n + ctrl-p + ctrl-n + ctrl-p * 2 + ctrl-n + ctrl-b + ctrl-p * 2 + ctrl-l
+    // + ctrl-x + ctrl-q)
+    fake_input_output.add_terminal_input(&[2, 2, 14, 16, 14, 16, 16, 14, 2, 16, 16, 12, 24, 17]);
     start(Box::new(fake_input_output.clone()), Opt::default());
 
     let output_frames = fake_input_output.stdout_writer.output_frames.lock().unwrap();
@@ -361,3 +362,40 @@ pub fn close_pane_with_multiple_panes_to_the_right_away_from_screen_edges() {
         assert_snapshot!(snapshot);
     }
 }
+
+#[test]
+pub fn close_pane_when_its_process_exits_with_a_background_job() {
+    let fake_win_size = Winsize {
+        ws_col: 121,
+        ws_row: 20,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+    let mut fake_input_output = get_fake_os_input(&fake_win_size);
+    // split-vertically (ctrl-n), the shell of the new pane exits but its pty stays open
+    fake_input_output.add_terminal_input(&[14]);
+    fake_input_output.set_exit_code(2, 0);
+    fake_input_output.leave_background_job(2);
+    let more_input = fake_input_output.keep_terminal_open();
+    let query_thread = thread::spawn({
+        let mut fake_input_output = fake_input_output.clone();
+        move || {
+            // the new pane is opened and closed again
+            let io_events = wait_until(|| Some(fake_input_output.io_events()), |io_events| io_events.contains(&IoEvent::CloseTty(2)));
+            let session_state = wait_for_state(&mut fake_input_output, |state| state.panes.len() == 1);
+            drop(more_input);
+            (session_state, io_events)
+        }
+    });
+    let opts = Opt {
+        session: Some(String::from("close_pane_when_its_process_exits_with_a_background_job")),
+        ..Opt::default()
+    };
+    start(Box::new(fake_input_output.clone()), opts);
+    let (session_state, io_events) = query_thread.join().unwrap();
+
+    let pane_ids: Vec<i32> = session_state.panes.iter().map(|pane| pane.id).collect();
+    assert_eq!(pane_ids, vec![1], "the pane was closed when its process exited, not when its pty ended");
+    assert!(io_events.contains(&IoEvent::CloseTty(2)), "the pty of the closed pane was closed");
+    assert!(!io_events.contains(&IoEvent::CloseTty(1)), "the pty of the open pane was not");
+}
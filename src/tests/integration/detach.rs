@@ -0,0 +1,39 @@
+use ::nix::pty::Winsize;
+use ::insta::assert_snapshot;
+
+use crate::{start, Opt};
+use crate::client::start_client;
+use crate::ipc::AttachOptions;
+use crate::tests::fakes::{FakeInputOutput};
+use crate::tests::utils::get_output_frame_snapshots;
+
+fn get_fake_os_input (fake_win_size: &Winsize) -> FakeInputOutput {
+    FakeInputOutput::new(fake_win_size.clone())
+}
+
+#[test]
+pub fn detach_and_reattach() {
+    let fake_win_size = Winsize {
+        ws_col: 121,
+        ws_row: 20,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+    let mut fake_input_output = get_fake_os_input(&fake_win_size);
+    fake_input_output.add_terminal_input(&[14, 7, 100]); // split-vertically and detach (ctrl-n + ctrl-g + d)
+    let opts = Opt {
+        session: Some(String::from("detach_and_reattach")),
+        ..Opt::default()
+    };
+    start(Box::new(fake_input_output.clone()), opts);
+
+    // the session kept running in the background, both panes should be there when we come back
+    fake_input_output.add_terminal_input(&[17]); // quit (ctrl-q)
+    start_client(Box::new(fake_input_output.clone()), "detach_and_reattach", AttachOptions::default());
+
+    let output_frames = fake_input_output.stdout_writer.output_frames.lock().unwrap();
+    let snapshots = get_output_frame_snapshots(&output_frames, &fake_win_size);
+    for snapshot in snapshots {
+        assert_snapshot!(snapshot);
+    }
+}
@@ -0,0 +1,112 @@
+use ::nix::pty::Winsize;
+use ::std::os::unix::net::UnixStream;
+use ::std::sync::mpsc::Sender;
+use ::std::thread;
+use ::std::time::Duration;
+
+use crate::{start, Opt};
+use crate::ipc::{ApiCommand, Event, InputMode};
+use crate::os_input_output::ClientOsApi;
+use crate::tests::fakes::{FakeInputOutput};
+use crate::tests::utils::{wait_for_state, wait_until};
+
+fn get_fake_os_input (fake_win_size: &Winsize) -> FakeInputOutput {
+    FakeInputOutput::new(fake_win_size.clone())
+}
+
+fn subscribe (fake_input_output: &mut FakeInputOutput, more_input: &Sender<Vec<u8>>) -> UnixStream {
+    wait_for_state(fake_input_output, |_state| true);
+    let mut server = fake_input_output.connect_to_server("").unwrap();
+    bincode::serialize_into(&mut server, &ApiCommand::Subscribe).unwrap();
+    // there's no telling when the subscription starts, so command mode is toggled (ctrl-g +
+    // ctrl-g) until its events arrive. the ones for the toggles that were still on their way are
+    // left out by the tests
+    server.set_read_timeout(Some(Duration::from_millis(100))).unwrap();
+    wait_until(|| {
+        more_input.send(vec![7, 7]).unwrap();
+        bincode::deserialize_from::<_, Event>(&mut server).ok()
+    }, |_event| true);
+    // a test fails rather than hangs if the events it waits for never come
+    server.set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+    server
+}
+
+#[test]
+pub fn subscribe_to_events() {
+    let fake_win_size = Winsize {
+        ws_col: 121,
+        ws_row: 20,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+    let mut fake_input_output = get_fake_os_input(&fake_win_size);
+    let more_input = fake_input_output.keep_terminal_open();
+    let subscriber_thread = thread::spawn({
+        let mut fake_input_output = fake_input_output.clone();
+        move || {
+            let mut server = subscribe(&mut fake_input_output, &more_input);
+            // split-vertically (ctrl-n) and send ctrl-g to the pane (ctrl-g + ctrl-g)
+            more_input.send(vec![14, 7, 7]).unwrap();
+            let mut events = vec![];
+            while !events.contains(&Event::PaneFocused { id: 2 }) || events.last() != Some(&Event::ModeChanged { mode: InputMode::Normal }) {
+                events.push(bincode::deserialize_from::<_, Event>(&mut server).unwrap());
+            }
+            drop(more_input);
+            events
+        }
+    });
+    let opts = Opt {
+        session: Some(String::from("subscribe_to_events")),
+        ..Opt::default()
+    };
+    start(Box::new(fake_input_output.clone()), opts);
+    let events = subscriber_thread.join().unwrap();
+
+    let events: Vec<Event> = events.into_iter().skip_while(|event| !matches!(event, Event::PaneResized { .. })).collect();
+    assert_eq!(events, vec![
+        Event::PaneResized { id: 1, x: 0, y: 0, rows: 20, cols: 60 },
+        Event::PaneOpened { id: 2, x: 61, y: 0, rows: 20, cols: 60 },
+        Event::PaneFocused { id: 2 },
+        Event::ModeChanged { mode: InputMode::Command },
+        Event::ModeChanged { mode: InputMode::Normal },
+    ]);
+}
+
+#[test]
+pub fn resizing_the_terminal_resizes_the_panes() {
+    let fake_win_size = Winsize {
+        ws_col: 121,
+        ws_row: 20,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+    let smaller_win_size = Winsize {
+        ws_col: 60,
+        ws_row: 20,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+    let mut fake_input_output = get_fake_os_input(&fake_win_size);
+    let more_input = fake_input_output.keep_terminal_open();
+    let subscriber_thread = thread::spawn({
+        let mut fake_input_output = fake_input_output.clone();
+        move || {
+            let mut server = subscribe(&mut fake_input_output, &more_input);
+            fake_input_output.resize_terminal(smaller_win_size);
+            let mut events = vec![];
+            while !matches!(events.last(), Some(Event::PaneResized { .. })) {
+                events.push(bincode::deserialize_from::<_, Event>(&mut server).unwrap());
+            }
+            drop(more_input);
+            events
+        }
+    });
+    let opts = Opt {
+        session: Some(String::from("resizing_the_terminal_resizes_the_panes")),
+        ..Opt::default()
+    };
+    start(Box::new(fake_input_output.clone()), opts);
+    let events = subscriber_thread.join().unwrap();
+
+    assert_eq!(events.last(), Some(&Event::PaneResized { id: 1, x: 0, y: 0, rows: 20, cols: 60 }));
+}
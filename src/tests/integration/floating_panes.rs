@@ -0,0 +1,104 @@
+use ::nix::pty::Winsize;
+use ::insta::assert_snapshot;
+
+use crate::{start, Opt};
+use crate::tests::fakes::FakeInputOutput;
+use crate::tests::utils::{get_output_frame_snapshots, get_state_when, pane_sizes};
+
+fn get_fake_os_input (fake_win_size: &Winsize) -> FakeInputOutput {
+    FakeInputOutput::new(*fake_win_size)
+}
+
+#[test]
+pub fn toggle_floating_panes() {
+    // ┌─────┬─────┐      ┌─────┬─────┐      ┌─────┬─────┐      ┌─────┬─────┐
+    // │     │     │      │  ┌─────┐  │      │     │     │      │  ┌─────┐  │
+    // │  1  │  2  │ =f=> │  │  3  │  │ =f=> │  1  │  2  │ =f=> │  │  3  │  │
+    // │     │     │      │  └─────┘  │      │     │     │      │  └─────┘  │
+    // └─────┴─────┘      └─────┴─────┘      └─────┴─────┘      └─────┴─────┘
+    let fake_win_size = Winsize {
+        ws_col: 121,
+        ws_row: 20,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+    let mut fake_input_output = get_fake_os_input(&fake_win_size);
+    // split-vertically (ctrl-n), toggle floating panes three times (ctrl-g + f), opening one the
+    // first time, and quit (ctrl-q)
+    fake_input_output.add_terminal_input(&[14, 7, b'f', 7, b'f', 7, b'f', 17]);
+    start(Box::new(fake_input_output.clone()), Opt::default());
+
+    assert_eq!(pane_sizes(&fake_input_output, 3), vec![(60, 10)], "the floating pane took half of the tab");
+    assert_eq!(pane_sizes(&fake_input_output, 1), vec![(121, 20), (60, 20)], "the tiled panes were not resized");
+    let output_frames = fake_input_output.stdout_writer.output_frames.lock().unwrap();
+    let snapshots = get_output_frame_snapshots(&output_frames, &fake_win_size);
+    for snapshot in snapshots {
+        assert_snapshot!(snapshot);
+    }
+}
+
+#[test]
+pub fn move_and_resize_floating_pane() {
+    let fake_win_size = Winsize {
+        ws_col: 121,
+        ws_row: 20,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+    let mut fake_input_output = get_fake_os_input(&fake_win_size);
+    // toggle floating panes (ctrl-g + f), resize right (ctrl-l), resize down (ctrl-j), move right
+    // (ctrl-g + L), move up (ctrl-g + K) and wait for it to have moved up
+    fake_input_output.add_terminal_input(&[7, b'f', 12, 10, 7, b'L', 7, b'K']);
+    let state_thread = get_state_when(&mut fake_input_output, |state| state.panes.iter().any(|pane| pane.floating && pane.y == 3));
+    start(Box::new(fake_input_output.clone()), Opt::default());
+    let session_state = state_thread.join().unwrap();
+
+    assert_eq!(pane_sizes(&fake_input_output, 2), vec![(60, 10), (70, 10), (70, 12)], "the floating pane grew from its bottom right corner");
+    let panes: Vec<_> = session_state.panes.iter().map(|pane| (pane.id, pane.x, pane.y, pane.cols, pane.rows, pane.floating, pane.focused)).collect();
+    assert_eq!(panes, vec![(1, 0, 0, 121, 20, false, false), (2, 40, 3, 70, 12, true, true)], "the floating pane moved up and to the right");
+}
+
+#[test]
+pub fn floating_pane_stays_inside_the_tab() {
+    let fake_win_size = Winsize {
+        ws_col: 121,
+        ws_row: 20,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+    let mut fake_input_output = get_fake_os_input(&fake_win_size);
+    // toggle floating panes (ctrl-g + f), move left four times (ctrl-g + H) and down six times
+    // (ctrl-g + J) and wait for it to reach the bottom left corner
+    fake_input_output.add_terminal_input(&[
+        7, b'f', 7, b'H', 7, b'H', 7, b'H', 7, b'H',
+        7, b'J', 7, b'J', 7, b'J', 7, b'J', 7, b'J', 7, b'J',
+    ]);
+    let state_thread = get_state_when(&mut fake_input_output, |state| state.panes.iter().any(|pane| pane.floating && pane.x == 1 && pane.y == 9));
+    start(Box::new(fake_input_output.clone()), Opt::default());
+    let session_state = state_thread.join().unwrap();
+
+    let floating_pane = session_state.panes.iter().find(|pane| pane.floating).unwrap();
+    assert_eq!((floating_pane.x, floating_pane.y), (1, 9), "the frame of the floating pane is still on screen");
+}
+
+#[test]
+pub fn close_floating_pane() {
+    let fake_win_size = Winsize {
+        ws_col: 121,
+        ws_row: 20,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+    let mut fake_input_output = get_fake_os_input(&fake_win_size);
+    // toggle floating panes (ctrl-g + f), open another floating pane (ctrl-z), close it and the
+    // first one (ctrl-x) and wait for both to be closed
+    fake_input_output.add_terminal_input(&[7, b'f', 26, 24, 24]);
+    let io_events = fake_input_output.clone();
+    let state_thread = get_state_when(&mut fake_input_output, move |state| state.panes.len() == 1 && !pane_sizes(&io_events, 3).is_empty());
+    start(Box::new(fake_input_output.clone()), Opt::default());
+    let session_state = state_thread.join().unwrap();
+
+    assert_eq!(pane_sizes(&fake_input_output, 3), vec![(60, 10)], "the second pane floated too");
+    let panes: Vec<_> = session_state.panes.iter().map(|pane| (pane.id, pane.floating, pane.focused)).collect();
+    assert_eq!(panes, vec![(1, false, true)], "focus went back to the tiled pane");
+}
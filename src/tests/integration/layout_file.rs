@@ -0,0 +1,147 @@
+use ::nix::pty::Winsize;
+use ::insta::assert_snapshot;
+use ::std::fs;
+use ::std::path::PathBuf;
+use ::std::thread;
+
+use crate::{start, Opt};
+use crate::ipc::ApiCommand;
+use crate::layout_file::LayoutFile;
+use crate::pty_bus::TerminalAction;
+use crate::os_input_output::ClientOsApi;
+use crate::tests::fakes::{FakeInputOutput};
+use crate::tests::utils::wait_for_state;
+
+fn get_fake_os_input (fake_win_size: &Winsize) -> FakeInputOutput {
+    FakeInputOutput::new(*fake_win_size)
+}
+
+fn write_layout_file (file_name: &str, contents: &str) -> PathBuf {
+    let directory = ::std::env::temp_dir().join(format!("mosaic-tests-{}", ::std::process::id()));
+    fs::create_dir_all(&directory).unwrap();
+    let path = directory.join(file_name);
+    fs::write(&path, contents).unwrap();
+    path
+}
+
+#[test]
+pub fn start_with_layout_file_and_save_it() {
+    let fake_win_size = Winsize {
+        ws_col: 121,
+        ws_row: 20,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+    let layout_path = write_layout_file("start_with_layout_file_and_save_it.yaml", "
+direction: vertical
+parts:
+  - size: 75%
+    name: editor
+    command: vim
+    args: [src/main.rs]
+  - direction: horizontal
+    parts:
+      - cwd: src
+      - size: 5
+        name: tests
+        command: cargo
+        args: [watch, -x, test]
+");
+    let mut fake_input_output = get_fake_os_input(&fake_win_size);
+    let more_input = fake_input_output.keep_terminal_open();
+    let query_thread = thread::spawn({
+        let mut fake_input_output = fake_input_output.clone();
+        move || {
+            let session_state = wait_for_state(&mut fake_input_output, |_state| true);
+            let mut server = fake_input_output.connect_to_server("").unwrap();
+            bincode::serialize_into(&mut server, &ApiCommand::GetLayout).unwrap();
+            let saved_layout: String = bincode::deserialize_from(&mut server).unwrap();
+            drop(more_input);
+            (session_state, saved_layout)
+        }
+    });
+    let opts = Opt {
+        session: Some(String::from("start_with_layout_file_and_save_it")),
+        layout: Some(layout_path.clone()),
+        ..Opt::default()
+    };
+    start(Box::new(fake_input_output.clone()), opts);
+    let (session_state, saved_layout) = query_thread.join().unwrap();
+
+    let panes: Vec<_> = session_state.panes.iter()
+        .map(|pane| (pane.id, pane.x, pane.y, pane.cols, pane.rows, pane.name.clone(), pane.focused))
+        .collect();
+    assert_eq!(panes, vec![
+        (1, 0, 0, 90, 20, Some(String::from("editor")), true),
+        (2, 91, 0, 30, 14, None, false),
+        (3, 91, 15, 30, 5, Some(String::from("tests")), false),
+    ], "the panes were laid out as in the file");
+    let terminal_actions = fake_input_output.terminal_actions();
+    match &terminal_actions[0].1 {
+        Some(TerminalAction::RunCommand(run_command)) => {
+            assert_eq!(run_command.command, PathBuf::from("vim"));
+            assert_eq!(run_command.args, vec![String::from("src/main.rs")]);
+        }
+        other => panic!("first pane does not run its command: {:?}", other),
+    }
+    match &terminal_actions[1].1 {
+        Some(TerminalAction::Shell(cwd)) => assert_eq!(cwd, &layout_path.parent().unwrap().join("src")),
+        other => panic!("second pane does not run the shell in its cwd: {:?}", other),
+    }
+    assert_snapshot!(saved_layout);
+}
+
+#[test]
+pub fn load_invalid_layout_files() {
+    let errors: Vec<String> = vec![
+        ("split_without_direction.yaml", "parts:\n  - name: left\n  - name: right\n"),
+        ("invalid_size.yaml", "direction: vertical\nparts:\n  - size: 120%\n  - name: right\n"),
+        ("unknown_field.yaml", "direction: vertical\nparts:\n  - commnd: vim\n  - name: right\n"),
+        ("named_split.yaml", "direction: vertical\nname: split\nparts:\n  - name: left\n  - name: right\n"),
+    ].into_iter().map(|(file_name, contents)| {
+        let path = write_layout_file(file_name, contents);
+        LayoutFile::load(&path).unwrap_err()
+    }).collect();
+
+    assert!(errors[0].ends_with("a part with parts needs a direction (horizontal or vertical)"), "{}", errors[0]);
+    assert!(errors[1].contains("invalid size \"120%\""), "{}", errors[1]);
+    assert!(errors[2].contains("unknown field `commnd`"), "{}", errors[2]);
+    assert!(errors[3].ends_with("only panes can have a name, command, args or cwd, not parts with parts"), "{}", errors[3]);
+}
+
+#[test]
+pub fn exit_status_of_a_command_pane_without_a_frame() {
+    let fake_win_size = Winsize {
+        ws_col: 121,
+        ws_row: 20,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+    // the only pane has no frame around it to color, so its status is shown in its top right corner
+    let layout_path = write_layout_file("exit_status_of_a_command_pane_without_a_frame.yaml", "
+command: make
+");
+    let mut fake_input_output = get_fake_os_input(&fake_win_size);
+    fake_input_output.set_exit_code(1, 0);
+    let more_input = fake_input_output.keep_terminal_open();
+    let query_thread = thread::spawn({
+        let mut fake_input_output = fake_input_output.clone();
+        move || {
+            let session_state = wait_for_state(&mut fake_input_output, |state| state.panes[0].exit_code.is_some());
+            drop(more_input);
+            session_state
+        }
+    });
+    let opts = Opt {
+        session: Some(String::from("exit_status_of_a_command_pane_without_a_frame")),
+        layout: Some(layout_path),
+        ..Opt::default()
+    };
+    start(Box::new(fake_input_output.clone()), opts);
+    let session_state = query_thread.join().unwrap();
+
+    assert_eq!(session_state.panes[0].exit_code, Some(0));
+    let output_frames = fake_input_output.stdout_writer.output_frames.lock().unwrap();
+    let green_status = output_frames.iter().any(|frame| String::from_utf8_lossy(frame).contains("\u{1b}[32m\u{1b}[7;7m EXIT CODE 0 "));
+    assert!(green_status, "the exit status is shown in green");
+}
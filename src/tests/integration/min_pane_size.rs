@@ -0,0 +1,63 @@
+use ::nix::pty::Winsize;
+use ::insta::assert_snapshot;
+
+use crate::{start, Opt};
+use crate::tests::fakes::FakeInputOutput;
+use crate::tests::utils::{get_output_frame_snapshots, pane_sizes};
+
+fn get_fake_os_input (fake_win_size: &Winsize) -> FakeInputOutput {
+    FakeInputOutput::new(*fake_win_size)
+}
+
+#[test]
+pub fn split_narrower_than_the_minimum_pane_size_is_refused() {
+    // ┌───────────┐                       ┌─────┬─────┐                       ┌─────┬─────┐
+    // │           │                       │     │     │                       │     │     │
+    // │           │ ==split=vertically==> │     │     │ ==split=vertically==> │     │     │
+    // │           │                       │     │     │   (refused)           │     │     │
+    // └───────────┘                       └─────┴─────┘                       └─────┴─────┘
+    let fake_win_size = Winsize {
+        ws_col: 121,
+        ws_row: 20,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+    let mut fake_input_output = get_fake_os_input(&fake_win_size);
+    // split-vertically twice (ctrl-n + ctrl-n), type in the pane and quit (ctrl-q)
+    fake_input_output.add_terminal_input(&[14, 14, b'a', 17]);
+    let opts = Opt {
+        min_pane_cols: Some(60),
+        ..Opt::default()
+    };
+    start(Box::new(fake_input_output.clone()), opts);
+
+    assert_eq!(pane_sizes(&fake_input_output, 2), vec![(60, 20)], "the first split left both panes exactly wide enough");
+    assert_eq!(pane_sizes(&fake_input_output, 3), vec![], "the second split was refused");
+    let output_frames = fake_input_output.stdout_writer.output_frames.lock().unwrap();
+    let snapshots = get_output_frame_snapshots(&output_frames, &fake_win_size);
+    let refused_split_snapshot = snapshots.iter().find(|snapshot| snapshot.contains("NO ROOM TO SPLIT")).unwrap();
+    assert_snapshot!(refused_split_snapshot);
+    let last_snapshot = snapshots.iter().rev().nth(1).unwrap(); // the last one says goodbye
+    assert!(!last_snapshot.contains("NO ROOM TO SPLIT"), "the notice went away with the next key");
+}
+
+#[test]
+pub fn resize_stops_at_the_minimum_pane_size() {
+    let fake_win_size = Winsize {
+        ws_col: 121,
+        ws_row: 20,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+    let mut fake_input_output = get_fake_os_input(&fake_win_size);
+    // split-horizontally (ctrl-b), resize-down * 2 (ctrl-j + ctrl-j) and quit (ctrl-q)
+    fake_input_output.add_terminal_input(&[2, 10, 10, 17]);
+    let opts = Opt {
+        min_pane_rows: Some(8),
+        ..Opt::default()
+    };
+    start(Box::new(fake_input_output.clone()), opts);
+
+    assert_eq!(pane_sizes(&fake_input_output, 2), vec![(121, 9), (121, 8)], "the focused pane only gave up one of the two rows, then none");
+    assert_eq!(pane_sizes(&fake_input_output, 1), vec![(121, 20), (121, 10), (121, 11)], "the other pane took that row");
+}
@@ -5,3 +5,15 @@ pub mod resize_left;
 pub mod resize_up;
 pub mod resize_down;
 pub mod close_pane;
+pub mod detach;
+pub mod multiple_clients;
+pub mod pane_actions;
+pub mod events;
+pub mod terminal_resize;
+pub mod layout_file;
+pub mod move_focus;
+pub mod swap_panes;
+pub mod zoom;
+pub mod tabs;
+pub mod floating_panes;
+pub mod min_pane_size;
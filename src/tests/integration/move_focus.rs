@@ -0,0 +1,93 @@
+use ::nix::pty::Winsize;
+
+use crate::{start, Opt};
+use crate::tests::fakes::{FakeInputOutput};
+
+fn get_fake_os_input (fake_win_size: &Winsize) -> FakeInputOutput {
+    FakeInputOutput::new(*fake_win_size)
+}
+
+#[test]
+pub fn move_focus_in_every_direction() {
+    // ┌─────┬─────┐
+    // │     │  2  │
+    // │  1  ├─────┤
+    // │     │  3  │
+    // └─────┴─────┘
+    // 2 and 3 share as much of the border with 1, so moving right from 1 goes to the one that
+    // was focused last
+    let fake_win_size = Winsize {
+        ws_col: 121,
+        ws_row: 21,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+    let mut fake_input_output = get_fake_os_input(&fake_win_size);
+    // split-vertically (ctrl-n), split-horizontally (ctrl-b), then move focus (ctrl-g + h/j/k/l)
+    // and type a character in the focused pane after each move
+    fake_input_output.add_terminal_input(&[
+        14, 2,
+        7, b'h', b'a',
+        7, b'l', b'b',
+        7, b'k', b'c',
+        7, b'h', b'd',
+        7, b'h', b'e', // there is no pane left of the leftmost one
+        7, b'l', b'f',
+        7, b'j', b'g',
+        17,
+    ]);
+    start(Box::new(fake_input_output.clone()), Opt::default());
+
+    // the 0 is the first input byte of the fake terminal, typed before the splits
+    assert_eq!(fake_input_output.stdin_writes_to(1), b"\0ade".to_vec(), "left pane got the characters typed in it");
+    assert_eq!(fake_input_output.stdin_writes_to(2), b"cf".to_vec(), "top right pane got the characters typed in it");
+    assert_eq!(fake_input_output.stdin_writes_to(3), b"bg".to_vec(), "bottom right pane got the characters typed in it");
+}
+
+#[test]
+pub fn move_focus_to_pane_sharing_most_of_the_border() {
+    // ┌─────┬─────┐
+    // │     │  2  │
+    // │  1  │     │
+    // │     ├─────┤
+    // │     │  3  │
+    // └─────┴─────┘
+    let fake_win_size = Winsize {
+        ws_col: 121,
+        ws_row: 20,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+    let mut fake_input_output = get_fake_os_input(&fake_win_size);
+    // split-vertically (ctrl-n), split-horizontally (ctrl-b), move focus left and right
+    // (ctrl-g + h, ctrl-g + l) and type a character
+    fake_input_output.add_terminal_input(&[14, 2, 7, b'h', 7, b'l', b'a', 17]);
+    start(Box::new(fake_input_output.clone()), Opt::default());
+
+    assert_eq!(fake_input_output.stdin_writes_to(2), b"a".to_vec(), "the taller pane on the right was focused");
+    assert_eq!(fake_input_output.stdin_writes_to(3), b"".to_vec(), "the shorter pane on the right was not focused");
+}
+
+#[test]
+pub fn focus_previous_pane_and_back_to_it_when_closing() {
+    // ┌─────┬─────┐
+    // │     │  2  │
+    // │  1  ├─────┤
+    // │     │  3  │
+    // └─────┴─────┘
+    let fake_win_size = Winsize {
+        ws_col: 121,
+        ws_row: 20,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+    let mut fake_input_output = get_fake_os_input(&fake_win_size);
+    // split-vertically (ctrl-n), split-horizontally (ctrl-b), focus the previous pane three times
+    // (ctrl-g + ;), move focus left (ctrl-g + h) and close that pane (ctrl-x), typing a character
+    // after the changes of focus
+    fake_input_output.add_terminal_input(&[14, 2, 7, b';', b'a', 7, b';', b'b', 7, b';', 7, b'h', 24, b'c', 17]);
+    start(Box::new(fake_input_output.clone()), Opt::default());
+
+    assert_eq!(fake_input_output.stdin_writes_to(2), b"ac".to_vec(), "the previous pane was focused, and focused again when the pane focused after it was closed");
+    assert_eq!(fake_input_output.stdin_writes_to(3), b"b".to_vec(), "focusing the previous pane again went back to the last one");
+}
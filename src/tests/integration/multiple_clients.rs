@@ -0,0 +1,112 @@
+use ::nix::pty::Winsize;
+use ::insta::assert_snapshot;
+use ::std::thread;
+
+use crate::{start, Opt};
+use crate::client::start_client;
+use crate::ipc::AttachOptions;
+use crate::tests::fakes::{FakeInputOutput};
+use crate::tests::utils::{get_output_frame_snapshots, wait_for_output, wait_for_state};
+
+fn get_fake_os_input (fake_win_size: &Winsize) -> FakeInputOutput {
+    FakeInputOutput::new(fake_win_size.clone())
+}
+
+fn skip_blank_frames (snapshots: Vec<String>) -> impl Iterator<Item = String> {
+    // the first client might draw the screen once before its pane's output was read
+    snapshots.into_iter().skip_while(|snapshot| snapshot.trim().trim_matches('█').is_empty())
+}
+
+#[test]
+pub fn screen_fits_smallest_client() {
+    let fake_win_size = Winsize {
+        ws_col: 121,
+        ws_row: 20,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+    let smaller_win_size = Winsize {
+        ws_col: 90,
+        ws_row: 20,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+    let mut fake_input_output = get_fake_os_input(&fake_win_size);
+    // the first client stays until the second one has come and gone
+    let more_input = fake_input_output.keep_terminal_open();
+    let mut second_client = fake_input_output.new_client(smaller_win_size);
+    let second_client_input = second_client.keep_terminal_open();
+    let second_client_thread = thread::spawn({
+        let mut second_client = second_client.clone();
+        move || {
+            wait_for_output(&mut second_client);
+            let detach_thread = thread::spawn({
+                let mut second_client = second_client.clone();
+                move || {
+                    wait_for_state(&mut second_client, |state| state.panes[0].cols == 90);
+                    wait_for_output(&mut second_client);
+                    second_client_input.send(vec![7, 100]).unwrap(); // detach (ctrl-g + d)
+                    second_client_input
+                }
+            });
+            start_client(Box::new(second_client.clone()), "screen_fits_smallest_client", AttachOptions::default());
+            drop(detach_thread.join().unwrap());
+            wait_for_state(&mut second_client, |state| state.panes[0].cols == 121);
+            wait_for_output(&mut second_client);
+            drop(more_input);
+        }
+    });
+    let opts = Opt {
+        session: Some(String::from("screen_fits_smallest_client")),
+        ..Opt::default()
+    };
+    start(Box::new(fake_input_output.clone()), opts);
+    second_client_thread.join().unwrap();
+
+    let output_frames = fake_input_output.stdout_writer.output_frames.lock().unwrap();
+    let snapshots = get_output_frame_snapshots(&output_frames, &fake_win_size);
+    for snapshot in skip_blank_frames(snapshots) {
+        assert_snapshot!(snapshot);
+    }
+    let second_client_output_frames = second_client.stdout_writer.output_frames.lock().unwrap();
+    let second_client_snapshots = get_output_frame_snapshots(&second_client_output_frames, &smaller_win_size);
+    for snapshot in second_client_snapshots {
+        assert_snapshot!(snapshot);
+    }
+}
+
+#[test]
+pub fn read_only_client_input_is_ignored() {
+    let fake_win_size = Winsize {
+        ws_col: 121,
+        ws_row: 20,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+    let mut fake_input_output = get_fake_os_input(&fake_win_size);
+    let more_input = fake_input_output.keep_terminal_open();
+    let mut read_only_client = fake_input_output.new_client(fake_win_size);
+    // split-vertically, quit and detach (ctrl-n + ctrl-q + ctrl-g + d), only detaching should work
+    read_only_client.add_terminal_input(&[14, 17, 7, 100]);
+    let read_only_client_thread = thread::spawn({
+        let mut read_only_client = read_only_client.clone();
+        move || {
+            wait_for_output(&mut read_only_client);
+            let attach_options = AttachOptions { read_only: true, ..AttachOptions::default() };
+            start_client(Box::new(read_only_client), "read_only_client_input_is_ignored", attach_options);
+            drop(more_input);
+        }
+    });
+    let opts = Opt {
+        session: Some(String::from("read_only_client_input_is_ignored")),
+        ..Opt::default()
+    };
+    start(Box::new(fake_input_output.clone()), opts);
+    read_only_client_thread.join().unwrap();
+
+    let output_frames = fake_input_output.stdout_writer.output_frames.lock().unwrap();
+    let snapshots = get_output_frame_snapshots(&output_frames, &fake_win_size);
+    for snapshot in skip_blank_frames(snapshots) {
+        assert_snapshot!(snapshot);
+    }
+}
@@ -0,0 +1,393 @@
+use ::nix::pty::Winsize;
+use ::insta::assert_snapshot;
+use ::serde::de::DeserializeOwned;
+use ::std::path::PathBuf;
+use ::std::thread;
+
+use crate::{start, Opt};
+use crate::input::{CommandBinding, Key};
+use crate::ipc::{ApiCommand, CaptureOptions, PaneSelector, SplitDirection};
+use crate::pty_bus::{RunCommand, TerminalAction};
+use crate::os_input_output::ClientOsApi;
+use crate::tests::fakes::{FakeInputOutput, IoEvent};
+use crate::tests::utils::{wait_for_state, wait_until};
+
+fn get_fake_os_input (fake_win_size: &Winsize) -> FakeInputOutput {
+    FakeInputOutput::new(fake_win_size.clone())
+}
+
+fn send_request<T: DeserializeOwned> (fake_input_output: &mut FakeInputOutput, api_command: ApiCommand) -> Result<T, String> {
+    let mut server = fake_input_output.connect_to_server("").unwrap();
+    bincode::serialize_into(&mut server, &api_command).unwrap();
+    bincode::deserialize_from(&mut server).unwrap()
+}
+
+#[test]
+pub fn write_to_pane_by_id_and_name() {
+    let fake_win_size = Winsize {
+        ws_col: 121,
+        ws_row: 20,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+    let mut fake_input_output = get_fake_os_input(&fake_win_size);
+    // split-vertically (ctrl-n), which focuses the new pane, and then wait for the actions
+    fake_input_output.add_terminal_input(&[14]);
+    let more_input = fake_input_output.keep_terminal_open();
+    let actions_thread = thread::spawn({
+        let mut fake_input_output = fake_input_output.clone();
+        move || {
+            wait_for_state(&mut fake_input_output, |state| state.panes.len() == 2);
+            let results: Vec<Result<(), String>> = vec![
+                send_request(&mut fake_input_output, ApiCommand::WriteChars(PaneSelector::Id(1), String::from("ls"))),
+                send_request(&mut fake_input_output, ApiCommand::SendKeys(PaneSelector::Id(1), vec![Key::Enter, Key::Ctrl('c')])),
+                send_request(&mut fake_input_output, ApiCommand::RenamePane(PaneSelector::Id(1), String::from("left"))),
+                send_request(&mut fake_input_output, ApiCommand::SendKeys(PaneSelector::Name(String::from("left")), vec![Key::Up, Key::Text(String::from("abc"))])),
+            ];
+            drop(more_input);
+            results
+        }
+    });
+    let opts = Opt {
+        session: Some(String::from("write_to_pane_by_id_and_name")),
+        ..Opt::default()
+    };
+    start(Box::new(fake_input_output.clone()), opts);
+    let results = actions_thread.join().unwrap();
+
+    assert!(results.iter().all(|result| result.is_ok()), "all actions succeeded: {:?}", results);
+    // the 0 is the first input byte of the fake terminal, typed before the split
+    assert_eq!(fake_input_output.stdin_writes_to(1), b"\0ls\r\x03\x1b[Aabc".to_vec(), "actions were written to the unfocused pane");
+}
+
+#[test]
+pub fn write_to_missing_pane() {
+    let fake_win_size = Winsize {
+        ws_col: 121,
+        ws_row: 20,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+    let mut fake_input_output = get_fake_os_input(&fake_win_size);
+    let more_input = fake_input_output.keep_terminal_open();
+    let actions_thread = thread::spawn({
+        let mut fake_input_output = fake_input_output.clone();
+        move || {
+            wait_for_state(&mut fake_input_output, |_state| true);
+            let results = vec![
+                send_request::<()>(&mut fake_input_output, ApiCommand::WriteChars(PaneSelector::Id(42), String::from("ls"))),
+                send_request::<()>(&mut fake_input_output, ApiCommand::RenamePane(PaneSelector::Name(String::from("nope")), String::from("left"))),
+            ];
+            drop(more_input);
+            results
+        }
+    });
+    let opts = Opt {
+        session: Some(String::from("write_to_missing_pane")),
+        ..Opt::default()
+    };
+    start(Box::new(fake_input_output.clone()), opts);
+    let results = actions_thread.join().unwrap();
+
+    assert!(results.iter().all(|result| result.is_err()), "actions on missing panes failed: {:?}", results);
+}
+
+#[test]
+pub fn write_to_pane_that_is_not_reading_its_input() {
+    let fake_win_size = Winsize {
+        ws_col: 121,
+        ws_row: 20,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+    let mut fake_input_output = get_fake_os_input(&fake_win_size);
+    let more_input = fake_input_output.keep_terminal_open();
+    // more than the 4KB a pty takes before its process reads any of it
+    let chars: String = (0..64 * 1024).map(|i| (b'a' + (i % 26) as u8) as char).collect();
+    let actions_thread = thread::spawn({
+        let mut fake_input_output = fake_input_output.clone();
+        let chars = chars.clone();
+        move || {
+            wait_for_state(&mut fake_input_output, |state| state.panes.len() == 1);
+            let written_before = fake_input_output.stdin_writes_to(1).len();
+            fake_input_output.stop_reading_stdin(1, 4096);
+            let results: Vec<Result<(), String>> = vec![
+                send_request(&mut fake_input_output, ApiCommand::WriteChars(PaneSelector::Id(1), chars)),
+                send_request(&mut fake_input_output, ApiCommand::WriteChars(PaneSelector::Id(1), String::from("ls"))),
+                send_request(&mut fake_input_output, ApiCommand::RenamePane(PaneSelector::Id(1), String::from("busy"))),
+            ];
+            let written_while_not_reading = fake_input_output.stdin_writes_to(1).len() - written_before;
+            fake_input_output.resume_reading_stdin(1);
+            wait_until(|| Some(fake_input_output.stdin_writes_to(1)), |stdin_writes| stdin_writes.ends_with(b"ls"));
+            drop(more_input);
+            (results, written_before, written_while_not_reading)
+        }
+    });
+    let opts = Opt {
+        session: Some(String::from("write_to_pane_that_is_not_reading_its_input")),
+        ..Opt::default()
+    };
+    start(Box::new(fake_input_output.clone()), opts);
+    let (results, written_before, written_while_not_reading) = actions_thread.join().unwrap();
+
+    assert!(results.iter().all(|result| result.is_ok()), "writing to the pane didn't wait for it to read: {:?}", results);
+    assert_eq!(written_while_not_reading, 4096, "only what the pty had room for was written until the pane read it");
+    assert!(
+        fake_input_output.stdin_writes_to(1)[written_before..].starts_with(format!("{}ls", chars).as_bytes()),
+        "all of it was written to the pane, in order, once it was reading again"
+    );
+}
+
+#[test]
+pub fn open_file_without_an_editor() {
+    let fake_win_size = Winsize {
+        ws_col: 121,
+        ws_row: 20,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+    let mut fake_input_output = get_fake_os_input(&fake_win_size).without_editor();
+    let more_input = fake_input_output.keep_terminal_open();
+    let actions_thread = thread::spawn({
+        let mut fake_input_output = fake_input_output.clone();
+        move || {
+            wait_for_state(&mut fake_input_output, |state| state.panes.len() == 1);
+            let result = send_request::<()>(&mut fake_input_output, ApiCommand::OpenFile(PathBuf::from("notes.txt")));
+            let pane_count = wait_for_state(&mut fake_input_output, |_state| true).panes.len();
+            drop(more_input);
+            (result, pane_count)
+        }
+    });
+    let opts = Opt {
+        session: Some(String::from("open_file_without_an_editor")),
+        ..Opt::default()
+    };
+    start(Box::new(fake_input_output.clone()), opts);
+    let (result, pane_count) = actions_thread.join().unwrap();
+
+    assert!(result.unwrap_err().contains("editor"), "the file couldn't be opened, and the caller was told why");
+    assert_eq!(pane_count, 1, "no pane was opened for it");
+}
+
+#[test]
+pub fn capture_pane() {
+    let fake_win_size = Winsize {
+        ws_col: 121,
+        ws_row: 20,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+    let mut fake_input_output = get_fake_os_input(&fake_win_size);
+    let more_input = fake_input_output.keep_terminal_open();
+    let capture_thread = thread::spawn({
+        let mut fake_input_output = fake_input_output.clone();
+        move || {
+            let on_screen = CaptureOptions::default();
+            let history = CaptureOptions { history: true, ..CaptureOptions::default() };
+            let with_styles = CaptureOptions { ansi: true, ..CaptureOptions::default() };
+            wait_for_state(&mut fake_input_output, |_state| true);
+            // the pane shows all of its output at once, once it's read
+            let captures = vec![
+                wait_until(
+                    || send_request::<String>(&mut fake_input_output, ApiCommand::CapturePane(PaneSelector::Focused, on_screen)).ok(),
+                    |capture| !capture.trim().is_empty(),
+                ),
+                send_request::<String>(&mut fake_input_output, ApiCommand::CapturePane(PaneSelector::Id(1), history)).unwrap(),
+                send_request::<String>(&mut fake_input_output, ApiCommand::CapturePane(PaneSelector::Id(1), with_styles)).unwrap(),
+            ];
+            drop(more_input);
+            captures
+        }
+    });
+    let opts = Opt {
+        session: Some(String::from("capture_pane")),
+        ..Opt::default()
+    };
+    start(Box::new(fake_input_output.clone()), opts);
+    let captures = capture_thread.join().unwrap();
+
+    for capture in captures {
+        assert_snapshot!(capture);
+    }
+}
+
+#[test]
+pub fn get_session_state() {
+    let fake_win_size = Winsize {
+        ws_col: 121,
+        ws_row: 20,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+    let mut fake_input_output = get_fake_os_input(&fake_win_size);
+    // split-vertically (ctrl-n) and then wait for the query
+    fake_input_output.add_terminal_input(&[14]);
+    let more_input = fake_input_output.keep_terminal_open();
+    let query_thread = thread::spawn({
+        let mut fake_input_output = fake_input_output.clone();
+        move || {
+            wait_for_state(&mut fake_input_output, |state| state.panes.len() == 2);
+            send_request::<()>(&mut fake_input_output, ApiCommand::RenamePane(PaneSelector::Id(1), String::from("left"))).unwrap();
+            let session_state = wait_for_state(&mut fake_input_output, |_state| true);
+            drop(more_input);
+            session_state
+        }
+    });
+    let opts = Opt {
+        session: Some(String::from("get_session_state")),
+        ..Opt::default()
+    };
+    start(Box::new(fake_input_output.clone()), opts);
+    let session_state = query_thread.join().unwrap();
+
+    assert_snapshot!(serde_json::to_string_pretty(&session_state).unwrap());
+}
+
+#[test]
+pub fn run_command_in_new_pane() {
+    let fake_win_size = Winsize {
+        ws_col: 121,
+        ws_row: 20,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+    let mut fake_input_output = get_fake_os_input(&fake_win_size);
+    let more_input = fake_input_output.keep_terminal_open();
+    let run_command = RunCommand {
+        command: PathBuf::from("cargo"),
+        args: vec![String::from("watch"), String::from("-x"), String::from("test")],
+        cwd: Some(PathBuf::from("/tmp")),
+        env: vec![(String::from("RUST_BACKTRACE"), String::from("1"))],
+    };
+    let query_thread = thread::spawn({
+        let mut fake_input_output = fake_input_output.clone();
+        let run_command = run_command.clone();
+        move || {
+            wait_for_state(&mut fake_input_output, |_state| true);
+            let mut server = fake_input_output.connect_to_server("").unwrap();
+            bincode::serialize_into(&mut server, &ApiCommand::RunCommand(run_command, Some(SplitDirection::Vertical))).unwrap();
+            let session_state = wait_for_state(&mut fake_input_output, |state| state.panes.len() == 2);
+            drop(more_input);
+            session_state
+        }
+    });
+    let opts = Opt {
+        session: Some(String::from("run_command_in_new_pane")),
+        ..Opt::default()
+    };
+    start(Box::new(fake_input_output.clone()), opts);
+    let session_state = query_thread.join().unwrap();
+
+    let terminal_actions = fake_input_output.terminal_actions();
+    assert_eq!(terminal_actions.len(), 2, "a second pane was opened");
+    assert!(terminal_actions[0].1.is_none(), "the first pane runs the shell");
+    match &terminal_actions[1].1 {
+        Some(TerminalAction::RunCommand(spawned_command)) => {
+            assert_eq!(spawned_command.command, run_command.command);
+            assert_eq!(spawned_command.args, run_command.args);
+            assert_eq!(spawned_command.cwd, run_command.cwd);
+            assert_eq!(spawned_command.env, run_command.env);
+        }
+        other => panic!("second pane does not run the command: {:?}", other),
+    }
+    let pane_geometry: Vec<(u16, u16)> = session_state.panes.iter().map(|pane| (pane.x, pane.cols)).collect();
+    assert_eq!(pane_geometry, vec![(0, 60), (61, 60)], "the command pane was split vertically");
+}
+
+#[test]
+pub fn run_command_bound_to_a_key() {
+    let fake_win_size = Winsize {
+        ws_col: 121,
+        ws_row: 20,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+    let mut fake_input_output = get_fake_os_input(&fake_win_size);
+    fake_input_output.add_terminal_input(&[7, b't']); // ctrl-g t
+    let more_input = fake_input_output.keep_terminal_open();
+    let query_thread = thread::spawn({
+        let mut fake_input_output = fake_input_output.clone();
+        move || {
+            let session_state = wait_for_state(&mut fake_input_output, |state| state.panes.len() == 2);
+            drop(more_input);
+            session_state
+        }
+    });
+    let opts = Opt {
+        session: Some(String::from("run_command_bound_to_a_key")),
+        bind_command: vec!["t=cargo watch -x test".parse().unwrap()],
+        ..Opt::default()
+    };
+    start(Box::new(fake_input_output.clone()), opts);
+    let session_state = query_thread.join().unwrap();
+
+    assert_eq!(session_state.panes.len(), 2, "a pane was opened for the command");
+    match &fake_input_output.terminal_actions()[1].1 {
+        Some(TerminalAction::RunCommand(run_command)) => {
+            assert_eq!(run_command.command, PathBuf::from("sh"));
+            assert_eq!(run_command.args, vec![String::from("-c"), String::from("cargo watch -x test")]);
+        }
+        other => panic!("the new pane does not run the bound command: {:?}", other),
+    }
+    assert!("d=htop".parse::<CommandBinding>().is_err(), "keys ctrl-g already uses can't be bound");
+    assert!("t".parse::<CommandBinding>().is_err(), "a binding needs a command");
+}
+
+#[test]
+pub fn hold_exited_command_pane_and_rerun_it() {
+    let fake_win_size = Winsize {
+        ws_col: 121,
+        ws_row: 20,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+    let mut fake_input_output = get_fake_os_input(&fake_win_size);
+    // the command fails as soon as it's run, then ctrl-g r re-runs it once it was kept open
+    fake_input_output.set_exit_code(2, 1);
+    let more_input = fake_input_output.keep_terminal_open();
+    let run_command = RunCommand {
+        command: PathBuf::from("false"),
+        ..RunCommand::default()
+    };
+    let query_thread = thread::spawn({
+        let mut fake_input_output = fake_input_output.clone();
+        move || {
+            wait_for_state(&mut fake_input_output, |_state| true);
+            let mut server = fake_input_output.connect_to_server("").unwrap();
+            bincode::serialize_into(&mut server, &ApiCommand::RunCommand(run_command, Some(SplitDirection::Vertical))).unwrap();
+            let held_state = wait_for_state(&mut fake_input_output, |state| state.panes.iter().any(|pane| pane.exit_code.is_some()));
+            let held_tty_closed = fake_input_output.io_events().contains(&IoEvent::CloseTty(2));
+            let held_capture: Result<String, String> = send_request(&mut fake_input_output, ApiCommand::CapturePane(PaneSelector::Id(2), CaptureOptions::default()));
+            more_input.send(vec![7, b'r']).unwrap(); // ctrl-g r
+            let rerun_state = wait_for_state(&mut fake_input_output, |state| state.panes.iter().any(|pane| pane.id == 3));
+            let io_events = wait_until(|| Some(fake_input_output.io_events()), |io_events| io_events.contains(&IoEvent::CloseTty(2)));
+            drop(more_input);
+            (held_state, held_tty_closed, held_capture.unwrap(), rerun_state, io_events)
+        }
+    });
+    let opts = Opt {
+        session: Some(String::from("hold_exited_command_pane_and_rerun_it")),
+        ..Opt::default()
+    };
+    start(Box::new(fake_input_output.clone()), opts);
+    let (held_state, held_tty_closed, held_capture, rerun_state, io_events) = query_thread.join().unwrap();
+
+    let held_panes: Vec<(i32, Option<i32>)> = held_state.panes.iter().map(|pane| (pane.id, pane.exit_code)).collect();
+    assert_eq!(held_panes, vec![(1, None), (2, Some(1))], "the exited command pane is kept open");
+    assert!(!held_tty_closed, "along with its pty");
+    // only what the command wrote, the exit code is drawn over the pane rather than written to it
+    assert_snapshot!(held_capture);
+    assert_eq!(held_capture.lines().last(), Some("prompt $"), "the exit code is not part of the pane's output");
+    let output_frames = fake_input_output.stdout_writer.output_frames.lock().unwrap();
+    let red_frame = output_frames.iter().any(|frame| String::from_utf8_lossy(frame).contains("\u{1b}[31m│"));
+    assert!(red_frame, "the frame of the failed command is red");
+
+    let rerun_panes: Vec<(i32, u16, Option<i32>)> = rerun_state.panes.iter().map(|pane| (pane.id, pane.x, pane.exit_code)).collect();
+    assert_eq!(rerun_panes, vec![(1, 0, None), (3, 61, None)], "the command was re-run in place of its pane");
+    assert!(io_events.contains(&IoEvent::CloseTty(2)), "the pty of the pane it replaced was closed");
+    let terminal_actions = fake_input_output.terminal_actions();
+    match &terminal_actions[2].1 {
+        Some(TerminalAction::RunCommand(rerun_command)) => assert_eq!(rerun_command.command, PathBuf::from("false")),
+        other => panic!("the command was not re-run: {:?}", other),
+    }
+}
@@ -368,3 +368,34 @@ pub fn resize_down_with_panes_below_aligned_left_and_right_with_to_the_left_and_
         assert_snapshot!(snapshot);
     }
 }
+
+#[test]
+pub fn resize_down_until_the_pane_cannot_get_any_smaller() {
+    // ┌───────────┐                  ┌───────────┐
+    // │           │                  │           │
+    // │           │                  │           │
+    // ├───────────┤ ==resize=down==> │           │
+    // │███████████│     * 6          │           │
+    // │███████████│                  ├───────────┤
+    // │███████████│                  │███████████│
+    // └───────────┘                  └───────────┘
+    // █ == focused pane
+    let fake_win_size = Winsize {
+        ws_col: 121,
+        ws_row: 20,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+    let mut fake_input_output = get_fake_os_input(&fake_win_size);
+    // the pane is left with one line after the fourth resize, the ones after it are refused with
+    // a notice that stays until the next key
+    // split-horizontally, resize-down * 6 and quit (ctrl-b + ctrl-j * 6 + ctrl-q)
+    fake_input_output.add_terminal_input(&[2, 10, 10, 10, 10, 10, 10, 17]);
+    start(Box::new(fake_input_output.clone()), Opt::default());
+
+    let output_frames = fake_input_output.stdout_writer.output_frames.lock().unwrap();
+    let snapshots = get_output_frame_snapshots(&output_frames, &fake_win_size);
+    for snapshot in snapshots {
+        assert_snapshot!(snapshot);
+    }
+}
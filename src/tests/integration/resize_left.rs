@@ -129,8 +129,8 @@ pub fn resize_left_with_panes_to_the_left_aligned_top_with_current_pane() {
     };
     let mut fake_input_output = get_fake_os_input(&fake_win_size);
 
-    // (ctrl-n + ctrl-b + ctrl-p + ctrl-b + ctrl-p * 3, ctrl-h + ctrl-q)
-    fake_input_output.add_terminal_input(&[14, 2, 16, 2, 16, 16, 16, 8, 17]);
+    // (ctrl-b + ctrl-n + ctrl-p + ctrl-n + ctrl-p * 3, ctrl-h + ctrl-q)
+    fake_input_output.add_terminal_input(&[2, 14, 16, 14, 16, 16, 16, 8, 17]);
 
     start(Box::new(fake_input_output.clone()), Opt::default());
 
@@ -157,8 +157,8 @@ pub fn resize_left_with_panes_to_the_right_aligned_top_with_current_pane() {
     };
     let mut fake_input_output = get_fake_os_input(&fake_win_size);
 
-    // (ctrl-n + ctrl-b + ctrl-p + ctrl-b + ctrl-h + ctrl-q)
-    fake_input_output.add_terminal_input(&[14, 2, 16, 2, 8, 17]);
+    // (ctrl-b + ctrl-n + ctrl-p + ctrl-n + ctrl-p * 2, ctrl-h + ctrl-q)
+    fake_input_output.add_terminal_input(&[2, 14, 16, 14, 16, 16, 8, 17]);
 
     start(Box::new(fake_input_output.clone()), Opt::default());
 
@@ -185,8 +185,8 @@ pub fn resize_left_with_panes_to_the_left_aligned_bottom_with_current_pane() {
     };
     let mut fake_input_output = get_fake_os_input(&fake_win_size);
 
-    // (ctrl-n + ctrl-b + ctrl-p + ctrl-b + ctrl-p * 2, ctrl-h + ctrl-q)
-    fake_input_output.add_terminal_input(&[14, 2, 16, 2, 16, 16, 8, 17]);
+    // (ctrl-b + ctrl-n + ctrl-p + ctrl-n + ctrl-h + ctrl-q)
+    fake_input_output.add_terminal_input(&[2, 14, 16, 14, 8, 17]);
 
     start(Box::new(fake_input_output.clone()), Opt::default());
 
@@ -213,9 +213,9 @@ pub fn resize_left_with_panes_to_the_right_aligned_bottom_with_current_pane() {
     };
     let mut fake_input_output = get_fake_os_input(&fake_win_size);
 
-    // split-vertically, split_horizontally, change-focus, split-horizontally, resize-right and quit
-    // (ctrl-n + ctrl-b + ctrl-p + ctrl-b + ctrl-p, ctrl-h + ctrl-q)
-    fake_input_output.add_terminal_input(&[14, 2, 16, 2, 16, 8, 17]);
+    // split-horizontally, split-vertically, change-focus, split-vertically, change-focus, resize-left and quit
+    // (ctrl-b + ctrl-n + ctrl-p + ctrl-n + ctrl-p, ctrl-h + ctrl-q)
+    fake_input_output.add_terminal_input(&[2, 14, 16, 14, 16, 8, 17]);
 
     start(Box::new(fake_input_output.clone()), Opt::default());
 
@@ -0,0 +1,71 @@
+use ::nix::pty::Winsize;
+
+use crate::{start, Opt};
+use crate::tests::fakes::{FakeInputOutput, IoEvent};
+use crate::tests::utils::get_state_when;
+
+fn get_fake_os_input (fake_win_size: &Winsize) -> FakeInputOutput {
+    FakeInputOutput::new(*fake_win_size)
+}
+
+#[test]
+pub fn swap_pane_with_the_one_next_to_it() {
+    // ┌─────┬─────┐                  ┌─────┬─────┐
+    // │     │█████│                  │█████│     │
+    // │  1  │██2██│ ==swap=left==>   │██2██│  1  │
+    // │     │█████│                  │█████│     │
+    // └─────┴─────┘                  └─────┴─────┘
+    // █ == focused pane
+    let fake_win_size = Winsize {
+        ws_col: 121,
+        ws_row: 20,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+    let mut fake_input_output = get_fake_os_input(&fake_win_size);
+    // split-vertically (ctrl-n), swap the focused pane left (ctrl-g + H) and wait for the swap
+    fake_input_output.add_terminal_input(&[14, 7, b'H']);
+    let query_thread = get_state_when(&mut fake_input_output, |state| state.panes.iter().any(|pane| pane.id == 2 && pane.x == 0));
+    let opts = Opt {
+        session: Some(String::from("swap_pane_with_the_one_next_to_it")),
+        ..Opt::default()
+    };
+    start(Box::new(fake_input_output.clone()), opts);
+    let session_state = query_thread.join().unwrap();
+
+    let panes: Vec<_> = session_state.panes.iter().map(|pane| (pane.id, pane.x, pane.cols, pane.focused)).collect();
+    assert_eq!(panes, vec![(1, 61, 60, false), (2, 0, 60, true)], "the panes swapped places, focus moved with the pane");
+}
+
+#[test]
+pub fn move_pane_to_position_and_swap_with_next_pane() {
+    // ┌─────┬──┬──┐                      ┌─────┬──┬──┐                   ┌─────┬──┬──┐
+    // │     │  │██│                      │█████│  │  │                   │     │██│  │
+    // │  1  │2 │█3│ ==move=to=first==>   │██3██│1 │2 │ ==swap=next==>    │  1  │█3│2 │
+    // │     │  │██│                      │█████│  │  │                   │     │██│  │
+    // └─────┴──┴──┘                      └─────┴──┴──┘                   └─────┴──┴──┘
+    // █ == focused pane
+    let fake_win_size = Winsize {
+        ws_col: 121,
+        ws_row: 20,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+    let mut fake_input_output = get_fake_os_input(&fake_win_size);
+    // split-vertically twice (ctrl-n), move the focused pane to the first position (ctrl-g + m + 1),
+    // swap it with the next one (ctrl-g + s) and wait for it to be second
+    fake_input_output.add_terminal_input(&[14, 14, 7, b'm', b'1', 7, b's']);
+    let query_thread = get_state_when(&mut fake_input_output, |state| state.panes.iter().any(|pane| pane.id == 3 && pane.x == 61));
+    let opts = Opt {
+        session: Some(String::from("move_pane_to_position_and_swap_with_next_pane")),
+        ..Opt::default()
+    };
+    start(Box::new(fake_input_output.clone()), opts);
+    let session_state = query_thread.join().unwrap();
+
+    let panes: Vec<_> = session_state.panes.iter().map(|pane| (pane.id, pane.x, pane.cols, pane.focused)).collect();
+    assert_eq!(panes, vec![(1, 0, 60, false), (2, 92, 29, false), (3, 61, 30, true)], "the panes moved, focus moved with the pane");
+    let io_events = fake_input_output.io_events();
+    assert!(io_events.contains(&IoEvent::SetTerminalSizeUsingFd(3, 60, 20)), "the pty of the moved pane was resized to its new place");
+    assert!(io_events.contains(&IoEvent::SetTerminalSizeUsingFd(2, 29, 20)), "the pty of the pane that made room for it was resized");
+}
@@ -0,0 +1,149 @@
+use ::nix::pty::Winsize;
+use ::insta::assert_snapshot;
+use ::std::thread;
+
+use crate::{start, Opt};
+use crate::ipc::{ApiCommand, SessionState};
+use crate::os_input_output::ClientOsApi;
+use crate::tests::fakes::FakeInputOutput;
+use crate::tests::utils::{get_output_frame_snapshots, get_state_when, pane_sizes, wait_for_state};
+
+fn get_fake_os_input (fake_win_size: &Winsize) -> FakeInputOutput {
+    FakeInputOutput::new(*fake_win_size)
+}
+
+#[test]
+pub fn open_tabs_and_switch_between_them() {
+    //                                  1  2               1  2               1  2
+    // ┌─────┬─────┐                  ┌───────────┐      ┌─────┬─────┐      ┌───────────┐
+    // │     │     │                  │           │      │     │     │      │           │
+    // │  1  │  2  │ ===new=tab===>   │     3     │ =p=> │  1  │  2  │ =n=> │     3     │
+    // │     │     │                  │           │      │     │     │      │           │
+    // └─────┴─────┘                  └───────────┘      └─────┴─────┘      └───────────┘
+    let fake_win_size = Winsize {
+        ws_col: 121,
+        ws_row: 20,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+    let mut fake_input_output = get_fake_os_input(&fake_win_size);
+    // split-vertically (ctrl-n), new tab (ctrl-g + c), previous tab (ctrl-g + p), next tab
+    // (ctrl-g + n) and quit (ctrl-q)
+    fake_input_output.add_terminal_input(&[14, 7, b'c', 7, b'p', 7, b'n', 17]);
+    start(Box::new(fake_input_output.clone()), Opt::default());
+
+    assert_eq!(pane_sizes(&fake_input_output, 1), vec![(121, 20), (60, 20), (60, 19)], "the panes of the first tab made room for the tab bar");
+    assert_eq!(pane_sizes(&fake_input_output, 2), vec![(60, 20), (60, 19)], "the panes of the first tab made room for the tab bar");
+    assert_eq!(pane_sizes(&fake_input_output, 3), vec![(121, 19)], "the pane of the new tab took the whole tab");
+    let output_frames = fake_input_output.stdout_writer.output_frames.lock().unwrap();
+    let snapshots = get_output_frame_snapshots(&output_frames, &fake_win_size);
+    for snapshot in snapshots {
+        assert_snapshot!(snapshot);
+    }
+}
+
+#[test]
+pub fn closing_the_last_pane_of_a_tab_closes_it() {
+    let fake_win_size = Winsize {
+        ws_col: 121,
+        ws_row: 20,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+    let mut fake_input_output = get_fake_os_input(&fake_win_size);
+    // new tab (ctrl-g + c), close its pane (ctrl-x) and quit (ctrl-q)
+    fake_input_output.add_terminal_input(&[7, b'c', 24, 17]);
+    start(Box::new(fake_input_output.clone()), Opt::default());
+
+    assert_eq!(pane_sizes(&fake_input_output, 1), vec![(121, 20), (121, 19), (121, 20)], "the tab bar went away with the second tab");
+    let output_frames = fake_input_output.stdout_writer.output_frames.lock().unwrap();
+    let snapshots = get_output_frame_snapshots(&output_frames, &fake_win_size);
+    let last_snapshot = snapshots.iter().rev().nth(1).unwrap(); // the last one says goodbye
+    assert_snapshot!(last_snapshot);
+}
+
+#[test]
+pub fn rename_tab_and_go_to_tab() {
+    let fake_win_size = Winsize {
+        ws_col: 121,
+        ws_row: 20,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+    let mut fake_input_output = get_fake_os_input(&fake_win_size);
+    // new tab (ctrl-g + c) and wait for the actions
+    fake_input_output.add_terminal_input(&[7, b'c']);
+    let more_input = fake_input_output.keep_terminal_open();
+    let actions_thread = thread::spawn({
+        let mut fake_input_output = fake_input_output.clone();
+        move || {
+            wait_for_state(&mut fake_input_output, |state| state.tabs.len() == 2);
+            let mut server = fake_input_output.connect_to_server("").unwrap();
+            bincode::serialize_into(&mut server, &ApiCommand::RenameTab(String::from("logs"))).unwrap();
+            wait_for_state(&mut fake_input_output, |state| state.tabs[1].name.is_some());
+            let mut server = fake_input_output.connect_to_server("").unwrap();
+            bincode::serialize_into(&mut server, &ApiCommand::GoToTab(0)).unwrap();
+            let went_to_first_tab: Result<(), String> = bincode::deserialize_from(&mut server).unwrap();
+            let mut server = fake_input_output.connect_to_server("").unwrap();
+            bincode::serialize_into(&mut server, &ApiCommand::GoToTab(2)).unwrap();
+            let went_to_third_tab: Result<(), String> = bincode::deserialize_from(&mut server).unwrap();
+            let mut server = fake_input_output.connect_to_server("").unwrap();
+            bincode::serialize_into(&mut server, &ApiCommand::GetState).unwrap();
+            let session_state: SessionState = bincode::deserialize_from(&mut server).unwrap();
+            drop(more_input);
+            (went_to_first_tab, went_to_third_tab, session_state)
+        }
+    });
+    start(Box::new(fake_input_output.clone()), Opt::default());
+    let (went_to_first_tab, went_to_third_tab, session_state) = actions_thread.join().unwrap();
+
+    assert_eq!(went_to_first_tab, Ok(()));
+    assert_eq!(went_to_third_tab, Err(String::from("no tab 3, there are only 2")));
+    let tabs: Vec<_> = session_state.tabs.iter().map(|tab| (tab.name.clone(), tab.active)).collect();
+    assert_eq!(tabs, vec![(None, true), (Some(String::from("logs")), false)], "the new tab was renamed and the first one shown");
+    let panes: Vec<_> = session_state.panes.iter().map(|pane| (pane.id, pane.tab, pane.y, pane.rows, pane.focused)).collect();
+    assert_eq!(panes, vec![(1, 0, 1, 19, true), (2, 1, 1, 19, false)], "each tab has its pane, below the tab bar");
+}
+
+#[test]
+pub fn go_to_tab_by_its_position() {
+    let fake_win_size = Winsize {
+        ws_col: 121,
+        ws_row: 20,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+    let mut fake_input_output = get_fake_os_input(&fake_win_size);
+    // new tab twice (ctrl-g + c), go to the first tab (ctrl-g + 1) and wait for it to be shown
+    fake_input_output.add_terminal_input(&[7, b'c', 7, b'c', 7, b'1']);
+    let query_thread = get_state_when(&mut fake_input_output, |state| state.tabs.len() == 3 && state.tabs[0].active);
+    let opts = Opt {
+        session: Some(String::from("go_to_tab_by_its_position")),
+        ..Opt::default()
+    };
+    start(Box::new(fake_input_output.clone()), opts);
+    let session_state = query_thread.join().unwrap();
+
+    let tabs: Vec<_> = session_state.tabs.iter().map(|tab| tab.active).collect();
+    assert_eq!(tabs, vec![true, false, false], "the first tab is shown");
+}
+
+#[test]
+pub fn new_tab_without_room_for_the_tab_bar_is_refused() {
+    let fake_win_size = Winsize {
+        ws_col: 121,
+        ws_row: 3,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+    let mut fake_input_output = get_fake_os_input(&fake_win_size);
+    // split-horizontally (ctrl-b) into two panes of one row, new tab (ctrl-g + c) and quit (ctrl-q)
+    fake_input_output.add_terminal_input(&[2, 7, b'c', 17]);
+    start(Box::new(fake_input_output.clone()), Opt::default());
+
+    assert_eq!(pane_sizes(&fake_input_output, 1), vec![(121, 3), (121, 1)], "the tab bar took no room from the panes");
+    assert_eq!(pane_sizes(&fake_input_output, 3), vec![], "the new tab was not opened");
+    let output_frames = fake_input_output.stdout_writer.output_frames.lock().unwrap();
+    let snapshots = get_output_frame_snapshots(&output_frames, &fake_win_size);
+    assert!(snapshots.iter().any(|snapshot| snapshot.contains("NO ROOM TO SPLIT")), "the new tab was refused with a notice");
+}
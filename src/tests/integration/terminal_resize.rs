@@ -0,0 +1,58 @@
+use ::nix::pty::Winsize;
+use ::insta::assert_snapshot;
+use ::std::thread;
+
+use crate::{start, Opt};
+use crate::tests::fakes::{FakeInputOutput, IoEvent};
+use crate::tests::utils::{get_output_frame_snapshots, wait_for_output, wait_for_state};
+
+/*
+ * this is when the terminal mosaic is drawn on is resized (eg. its window is made smaller),
+ * rather than a pane inside mosaic
+ *
+ */
+
+fn get_fake_os_input (fake_win_size: &Winsize) -> FakeInputOutput {
+    FakeInputOutput::new(*fake_win_size)
+}
+
+#[test]
+pub fn panes_are_resized_in_proportion_to_the_terminal() {
+    let fake_win_size = Winsize {
+        ws_col: 121,
+        ws_row: 20,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+    let smaller_win_size = Winsize {
+        ws_col: 60,
+        ws_row: 20,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+    let mut fake_input_output = get_fake_os_input(&fake_win_size);
+    // split vertically (ctrl-n) and then resize the terminal
+    fake_input_output.add_terminal_input(&[14]);
+    let more_input = fake_input_output.keep_terminal_open();
+    let resize_thread = thread::spawn({
+        let mut fake_input_output = fake_input_output.clone();
+        move || {
+            wait_for_state(&mut fake_input_output, |state| state.panes.len() == 2);
+            wait_for_output(&mut fake_input_output);
+            fake_input_output.resize_terminal(smaller_win_size);
+            wait_for_state(&mut fake_input_output, |state| state.panes[1].cols == 30);
+            wait_for_output(&mut fake_input_output);
+            drop(more_input);
+        }
+    });
+    start(Box::new(fake_input_output.clone()), Opt::default());
+    resize_thread.join().unwrap();
+
+    let io_events = fake_input_output.io_events();
+    assert!(io_events.contains(&IoEvent::SetTerminalSizeUsingFd(1, 29, 20)), "the left pane's pty was resized");
+    assert!(io_events.contains(&IoEvent::SetTerminalSizeUsingFd(2, 30, 20)), "the right pane's pty was resized");
+    let output_frames = fake_input_output.stdout_writer.output_frames.lock().unwrap();
+    let snapshots = get_output_frame_snapshots(&output_frames, &smaller_win_size);
+    let last_snapshot = snapshots.iter().rev().nth(1).unwrap(); // the last one says goodbye
+    assert_snapshot!(last_snapshot);
+}
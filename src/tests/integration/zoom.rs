@@ -0,0 +1,64 @@
+use ::nix::pty::Winsize;
+use ::insta::assert_snapshot;
+
+use crate::{start, Opt};
+use crate::tests::fakes::{FakeInputOutput, IoEvent};
+use crate::tests::utils::get_output_frame_snapshots;
+
+fn get_fake_os_input (fake_win_size: &Winsize) -> FakeInputOutput {
+    FakeInputOutput::new(*fake_win_size)
+}
+
+#[test]
+pub fn zoom_focused_pane_and_unzoom_it() {
+    // ┌─────┬─────┐                  ┌───────────┐                  ┌─────┬─────┐
+    // │     │█████│                  │███████████│                  │     │█████│
+    // │     │█████│ =====zoom=====>  │███████████│ ====unzoom====>  │     │█████│
+    // │     │█████│                  │███████████│                  │     │█████│
+    // └─────┴─────┘                  └───────────┘                  └─────┴─────┘
+    // █ == focused pane
+    let fake_win_size = Winsize {
+        ws_col: 121,
+        ws_row: 20,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+    let mut fake_input_output = get_fake_os_input(&fake_win_size);
+    // split-vertically (ctrl-n), zoom (ctrl-g + z), unzoom (ctrl-g + z) and quit (ctrl-q)
+    fake_input_output.add_terminal_input(&[14, 7, b'z', 7, b'z', 17]);
+    start(Box::new(fake_input_output.clone()), Opt::default());
+
+    let io_events = fake_input_output.io_events();
+    let pane_sizes = |id| -> Vec<(u16, u16)> {
+        io_events.iter().filter_map(|io_event| match io_event {
+            IoEvent::SetTerminalSizeUsingFd(pane_id, cols, rows) if *pane_id == id => Some((*cols, *rows)),
+            _ => None,
+        }).collect()
+    };
+    assert_eq!(pane_sizes(2), vec![(60, 20), (121, 20), (60, 20)], "the pty of the zoomed pane took the whole screen and was then put back");
+    assert_eq!(pane_sizes(1), vec![(121, 20), (60, 20)], "the hidden pane kept its size");
+    let output_frames = fake_input_output.stdout_writer.output_frames.lock().unwrap();
+    let snapshots = get_output_frame_snapshots(&output_frames, &fake_win_size);
+    for snapshot in snapshots {
+        assert_snapshot!(snapshot);
+    }
+}
+
+#[test]
+pub fn splitting_a_zoomed_pane_unzooms_it() {
+    let fake_win_size = Winsize {
+        ws_col: 121,
+        ws_row: 20,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+    let mut fake_input_output = get_fake_os_input(&fake_win_size);
+    // split-vertically (ctrl-n), zoom (ctrl-g + z), split-horizontally (ctrl-b) and quit (ctrl-q)
+    fake_input_output.add_terminal_input(&[14, 7, b'z', 2, 17]);
+    start(Box::new(fake_input_output.clone()), Opt::default());
+
+    let output_frames = fake_input_output.stdout_writer.output_frames.lock().unwrap();
+    let snapshots = get_output_frame_snapshots(&output_frames, &fake_win_size);
+    let last_snapshot = snapshots.iter().rev().nth(1).unwrap(); // the last one says goodbye
+    assert_snapshot!(last_snapshot);
+}
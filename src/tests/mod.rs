@@ -3,3 +3,4 @@ pub mod possible_tty_inputs;
 pub mod tty_inputs;
 pub mod fakes;
 pub mod utils;
+pub mod benchmarks;
@@ -1,5 +1,12 @@
 use ::nix::pty::Winsize;
+use ::std::os::unix::io::RawFd;
+use ::std::thread::{self, JoinHandle};
+use ::std::time::{Duration, Instant};
+
+use crate::ipc::{ApiCommand, SessionState};
+use crate::os_input_output::ClientOsApi;
 use crate::terminal_pane::TerminalPane;
+use crate::tests::fakes::{FakeInputOutput, IoEvent};
 
 pub fn get_output_frame_snapshots(output_frames: &[Vec<u8>], win_size: &Winsize) -> Vec<String> {
     let mut vte_parser = vte::Parser::new();
@@ -33,3 +40,57 @@ pub fn get_output_frame_snapshots(output_frames: &[Vec<u8>], win_size: &Winsize)
     snapshots
 }
 
+pub fn pane_sizes(fake_input_output: &FakeInputOutput, id: RawFd) -> Vec<(u16, u16)> {
+    // every size the pane's pty was given, in order
+    fake_input_output.io_events().iter().filter_map(|io_event| match io_event {
+        IoEvent::SetTerminalSizeUsingFd(pane_id, cols, rows) if *pane_id == id => Some((*cols, *rows)),
+        _ => None,
+    }).collect()
+}
+
+pub fn wait_until<T>(mut get: impl FnMut() -> Option<T>, expected: impl Fn(&T) -> bool) -> T {
+    // gets something from the session until it's what the test expects (eg. once the input that
+    // was sent to it was handled), rather than guessing how long that takes. gives up after a
+    // while and returns the last one it got, so that the test can tell what's wrong with it
+    let gave_up_at = Instant::now() + Duration::from_secs(10);
+    let mut last = None;
+    while Instant::now() < gave_up_at {
+        if let Some(got) = get() {
+            if expected(&got) {
+                return got;
+            }
+            last = Some(got);
+        }
+        thread::sleep(Duration::from_millis(20));
+    }
+    last.expect("could not get anything from the session")
+}
+
+pub fn get_state(fake_input_output: &mut FakeInputOutput) -> Option<SessionState> {
+    // none until the session's first client attached
+    let mut server = fake_input_output.connect_to_server("").ok()?;
+    bincode::serialize_into(&mut server, &ApiCommand::GetState).ok()?;
+    bincode::deserialize_from(&mut server).ok()
+}
+
+pub fn wait_for_state(fake_input_output: &mut FakeInputOutput, expected: impl Fn(&SessionState) -> bool) -> SessionState {
+    wait_until(|| get_state(fake_input_output), expected)
+}
+
+pub fn wait_for_output(fake_input_output: &mut FakeInputOutput) -> SessionState {
+    // once the panes' ptys were read to their end. the state is got after that, so that the screen
+    // applied what was read by the time it's returned
+    let all_output_read = fake_input_output.clone();
+    wait_until(|| if all_output_read.all_output_read() { get_state(fake_input_output) } else { None }, |_state| true)
+}
+
+pub fn get_state_when(fake_input_output: &mut FakeInputOutput, expected: impl Fn(&SessionState) -> bool + Send + 'static) -> JoinHandle<SessionState> {
+    // the session quits once its state was the expected one
+    let more_input = fake_input_output.keep_terminal_open();
+    let mut fake_input_output = fake_input_output.clone();
+    thread::spawn(move || {
+        let session_state = wait_for_state(&mut fake_input_output, expected);
+        drop(more_input);
+        session_state
+    })
+}